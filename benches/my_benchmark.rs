@@ -1,7 +1,7 @@
-use criterion::{criterion_group, criterion_main, Criterion, BenchmarkId};
+use criterion::{criterion_group, criterion_main, Criterion};
 use rand::{thread_rng, Rng};
 use rand::distributions::Alphanumeric;
-use rlox::bytecode::hash_table::HashTable;
+use rlox::bytecode::{self, hash_table::HashTable};
 use rlox::bytecode::object_string::ObjectString;
 
 pub fn criterion_benchmark(c: &mut Criterion) {
@@ -22,6 +22,22 @@ pub fn criterion_benchmark(c: &mut Criterion) {
             hash_table.insert(ObjectString::from_string(string.clone()), ());
         }
     }));
+
+    let script = "var i = 0; while (i < 100000) { i = i + 1; }";
+    c.bench_function("loop", |b| b.iter(|| {
+        assert!(bytecode::run_interpreter(script.to_string(), bytecode::Options::default()).is_ok());
+    }));
+
+    let script = "var s = \"\"; for (var i = 0; i < 1000; i = i + 1) { s = s + \"a\"; }";
+    c.bench_function("concatenation", |b| b.iter(|| {
+        assert!(bytecode::run_interpreter(script.to_string(), bytecode::Options::default()).is_ok());
+    }));
+
+    let script = "class Counter { init() { this.count = 0; } increment() { this.count = this.count + 1; } } \
+        var counter = Counter(); for (var i = 0; i < 10000; i = i + 1) { counter.increment(); }";
+    c.bench_function("invoke", |b| b.iter(|| {
+        assert!(bytecode::run_interpreter(script.to_string(), bytecode::Options::default()).is_ok());
+    }));
 }
 
 criterion_group!(benches, criterion_benchmark);
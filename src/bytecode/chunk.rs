@@ -2,24 +2,37 @@ use super::value::Value;
 use super::vec::Vec;
 use super::op_code::OpCode;
 use super::constant_pool::ConstantPool;
+use std::convert::TryFrom;
 use std::mem;
 use std::slice::Iter;
 
+const INITIAL_CODES_CAPACITY: usize = 64;
+
 pub struct Chunk {
     pub codes: Vec<u8>,
     constants: ConstantPool,
     lines: Vec<LineStart>,
+    trace: bool,
 }
 
 impl Chunk {
     pub fn new() -> Self {
+        Chunk::with_trace(false)
+    }
+
+    pub fn with_trace(trace: bool) -> Self {
         Self {
-            codes: Vec::new(),
+            codes: Vec::with_capacity(INITIAL_CODES_CAPACITY),
             lines: Vec::new(),
-            constants: ConstantPool::new()
+            constants: ConstantPool::new(),
+            trace,
         }
     }
 
+    pub fn is_tracing(&self) -> bool {
+        self.trace
+    }
+
     #[inline]
     pub fn byte_to_op_code(byte: u8) -> OpCode {
         unsafe {
@@ -27,51 +40,124 @@ impl Chunk {
         }
     }
 
+    #[allow(dead_code)]
     pub fn disassemble(&self, name: String) {
         println!("== {} ==", name);
         let mut iter = self.codes.iter();
         let mut offset: usize = 0;
         while let Some(code) = iter.next() {
-            let op_code = Chunk::byte_to_op_code(code.clone());
+            let op_code = Chunk::byte_to_op_code(*code);
             offset = self.disassemble_instruction(op_code, &mut iter, offset);
         }
     }
 
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let mut boundaries = std::vec::Vec::new();
+        let mut jumps = std::vec::Vec::new();
+        let mut last_op_code = None;
+        let mut offset = 0;
+        while offset < self.codes.length {
+            let op_code = OpCode::try_from(self.codes[offset])
+                .map_err(|_| VerifyError::new(offset, "Unknown op code."))?;
+            let mut size = op_code.code_size();
+            if offset + size > self.codes.length {
+                return Err(VerifyError::new(offset, "Instruction operands run past the end of the chunk."));
+            }
+            match op_code {
+                OpCode::JumpIfFalse | OpCode::Jump => {
+                    jumps.push((offset, Some(offset + size + self.read_jump(offset))));
+                }
+                OpCode::Loop => {
+                    jumps.push((offset, (offset + size).checked_sub(self.read_jump(offset))));
+                }
+                OpCode::Closure => match self.constants.value(self.codes[offset + 1] as usize) {
+                    Value::Function(function) => {
+                        function.chunk.verify()?;
+                        size += 2 * function.upvalue_count as usize;
+                        if offset + size > self.codes.length {
+                            return Err(VerifyError::new(offset, "Closure upvalues run past the end of the chunk."));
+                        }
+                    }
+                    _ => return Err(VerifyError::new(offset, "Closure constant is not a function.")),
+                },
+                _ => {}
+            }
+            boundaries.push(offset);
+            last_op_code = Some(op_code);
+            offset += size;
+        }
+        if last_op_code != Some(OpCode::Return) {
+            return Err(VerifyError::new(offset, "Chunk does not end with a return."));
+        }
+        for (offset, target) in jumps {
+            match target {
+                Some(target) if boundaries.binary_search(&target).is_ok() => {}
+                _ => return Err(VerifyError::new(offset, "Jump target is not an instruction boundary.")),
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn read_jump(&self, offset: usize) -> usize {
+        usize::from(self.codes[offset + 1]) << 8u8 | usize::from(self.codes[offset + 2])
+    }
+
     pub fn push_code(&mut self, code: OpCode, line: usize) {
-        println!("push code {}", &code);
+        if self.trace {
+            println!("push code {}", &code);
+        }
         let code = unsafe {
-            mem::transmute::<OpCode, u8>(code.clone())
+            mem::transmute::<OpCode, u8>(code)
         };
         self.push(code, line);
     }
 
     pub fn add_constant(&mut self, constant: Value, line: usize) {
-        println!("add constant {:?}", &constant);
+        if self.trace {
+            println!("add constant {:?}", &constant);
+        }
         let index = self.push_constant_to_pool(constant);
         self.push_constant(index, line);
     }
 
     #[inline]
     pub fn push_constant_to_pool(&mut self, constant: Value) -> usize {
+        if let Some(index) = self.constants.find_scalar(&constant) {
+            return index;
+        }
         self.constants.push(constant);
         self.constants.length() - 1
     }
 
+    /// Releases the spare capacity of a chunk that is complete. Function
+    /// chunks live as long as their function, which in a REPL session is
+    /// until it ends.
+    pub fn shrink_to_fit(&mut self) {
+        self.codes.shrink_to_fit();
+        self.lines.shrink_to_fit();
+        self.constants.shrink_to_fit();
+    }
+
+    #[allow(dead_code)]
     pub fn disassemble_instruction(&self, op_code: OpCode, iter: &mut Iter<u8>, offset: usize) -> usize {
         let line = self.line(offset);
         let mut offset = offset;
         match op_code {
             OpCode::Return | OpCode::Negate | OpCode::Add
-            | OpCode::Subtract | OpCode::Multiply | OpCode::Divide
+            | OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::Modulo
             | OpCode::False | OpCode::True | OpCode::Nil
             | OpCode::Not | OpCode::Equal | OpCode::Greater
-            | OpCode::Less | OpCode::Print | OpCode::Pop | OpCode::CloseUpvalue => {
+            | OpCode::Less | OpCode::GreaterEqual | OpCode::LessEqual
+            | OpCode::Print | OpCode::Pop | OpCode::CloseUpvalue
+            | OpCode::Inherit | OpCode::Dup | OpCode::IndexGet | OpCode::IndexSet => {
                 println!("{:04} {:4} {} at {}", offset, "", op_code, line);
             }
             OpCode::Constant | OpCode::DefineGlobal | OpCode::GetGlobal
             | OpCode::SetGlobal | OpCode::SetLocal | OpCode::GetLocal
             | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Class
-            | OpCode::GetProperty | OpCode::SetProperty | OpCode::Method => {
+            | OpCode::GetProperty | OpCode::SetProperty | OpCode::Method
+            | OpCode::GetSuper => {
                 print!("{:04} ", offset);
                 self.print_constant(iter, op_code, line);
             }
@@ -83,10 +169,20 @@ impl Chunk {
                 let condition_offset = Chunk::read_condition_offset(iter);
                 println!("{:04} {} {} at {}", offset, op_code, condition_offset, line)
             }
+            OpCode::BuildList => {
+                let element_count = *(iter.next().unwrap());
+                println!("{:04} {:4} {} {} at {}", offset, "", op_code, element_count, line)
+            }
             OpCode::Call => {
                 let argument_count = *(iter.next().unwrap());
                 println!("{:04} {:4} {} {} at {}", offset, "", op_code, argument_count, line)
             }
+            OpCode::Invoke => {
+                let index = *(iter.next().unwrap()) as usize;
+                let argument_count = *(iter.next().unwrap());
+                let name = self.constants.value(index);
+                println!("{:04} {:4} {} ({} args) {:>16?} at {}", offset, index, op_code, argument_count, name, line)
+            }
             OpCode::Closure => {
                 print!("{:04} ", offset);
                 let value = self.print_constant(iter, op_code, line);
@@ -129,17 +225,11 @@ impl Chunk {
     }
 
     pub fn line(&self, offset: usize) -> usize {
-        let mut start = 0;
-        let mut end = self.lines.length - 1;
-        loop {
-            let mid = (end + start) / 2;
-            if offset < self.lines[mid].offset {
-                end = mid - 1;
-            } else if (mid == self.lines.length - 1) || (self.lines[mid + 1].offset > offset) {
-                return self.lines[mid].line
-            } else {
-                start = mid + 1;
-            }
+        // Index of the first line that starts after the offset.
+        let next_index = self.lines.partition_point(|line_start| line_start.offset <= offset);
+        match next_index.checked_sub(1) {
+            Some(index) => self.lines[index].line,
+            None => self.lines.first().map(|line_start| line_start.line).unwrap_or(0),
         }
     }
 
@@ -154,8 +244,19 @@ impl Chunk {
         }
     }
 
+    pub fn truncate(&mut self, length: usize) {
+        while self.codes.length > length {
+            self.codes.pop();
+        }
+        while self.lines.last().map(|line_start| line_start.offset >= length).unwrap_or(false) {
+            self.lines.pop();
+        }
+    }
+
     fn push_constant(&mut self, index: usize, line: usize) {
-        println!("push constant at index {:?}", index);
+        if self.trace {
+            println!("push constant at index {:?}", index);
+        }
         if index < 256 {
             self.push_code(OpCode::Constant, line);
             self.push(index as u8, line);
@@ -163,11 +264,12 @@ impl Chunk {
             self.push_code(OpCode::ConstantLong, line);
             self.push((index & 0xff) as u8, line);
             self.push(((index >> 8u8) & 0xff) as u8, line);
-            self.push(((index >> 8u8) & 0xff) as u8, line);
+            self.push(((index >> 16u8) & 0xff) as u8, line);
         }
     }
 
     #[inline]
+    #[allow(dead_code)]
     fn print_constant(&self, iterator: &mut Iter<u8>, op_code: OpCode, line: usize) -> &Value {
         let index = *iterator.next().unwrap() as usize;
         let constant = self.constants.value(index);
@@ -180,3 +282,155 @@ struct LineStart {
     offset: usize,
     line: usize,
 }
+
+#[derive(Debug)]
+pub struct VerifyError {
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl VerifyError {
+    fn new(offset: usize, message: &'static str) -> Self {
+        Self { offset, message }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_chunk(codes: &[u8]) -> Chunk {
+        let mut chunk = Chunk::new();
+        for code in codes {
+            chunk.push(*code, 1);
+        }
+        chunk
+    }
+
+    #[test]
+    fn line() {
+        assert_eq!(Chunk::new().line(0), 0);
+
+        let chunk = make_chunk(&[OpCode::Nil as u8, OpCode::Return as u8]);
+        assert_eq!(chunk.line(0), 1);
+        assert_eq!(chunk.line(1), 1);
+
+        let mut chunk = Chunk::new();
+        for (code, line) in [(OpCode::Nil, 1), (OpCode::Nil, 3), (OpCode::Pop, 3), (OpCode::Pop, 4), (OpCode::Return, 7)] {
+            chunk.push(code as u8, line);
+        }
+        let lines: std::vec::Vec<usize> = (0..6).map(|offset| chunk.line(offset)).collect();
+        assert_eq!(lines, vec![1, 3, 3, 4, 7, 7]);
+    }
+
+    #[test]
+    fn push_constant_to_pool_reuses_scalars() {
+        let mut chunk = Chunk::new();
+        let one = chunk.push_constant_to_pool(Value::Number(1.0));
+        let nil = chunk.push_constant_to_pool(Value::Nil);
+        assert_eq!(chunk.push_constant_to_pool(Value::Number(1.0)), one);
+        assert_eq!(chunk.push_constant_to_pool(Value::Nil), nil);
+        assert_ne!(chunk.push_constant_to_pool(Value::Number(-0.0)), chunk.push_constant_to_pool(Value::Number(0.0)));
+        assert_ne!(chunk.push_constant_to_pool(Value::Bool(true)), chunk.push_constant_to_pool(Value::Bool(false)));
+        assert_eq!(chunk.constants.length(), 6);
+    }
+
+    #[test]
+    fn drop_releases_constants() {
+        use super::super::value::object_string::ObjectString;
+        use std::rc::Rc;
+
+        let string = Rc::new(ObjectString::from_string("constant".to_string()));
+        let mut chunk = Chunk::new();
+        chunk.add_constant(Value::String(Rc::clone(&string)), 1);
+        chunk.push_constant_to_pool(Value::String(Rc::clone(&string)));
+        assert_eq!(Rc::strong_count(&string), 3);
+        drop(chunk);
+        assert_eq!(Rc::strong_count(&string), 1);
+    }
+
+    #[test]
+    fn drop_runs_constant_destructors() {
+        use super::super::value::object_native_function::ObjectNativeFunction;
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct DropCounter(Rc<Cell<usize>>);
+
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        // A native function constant owns its closure, so the counter
+        // captured by the closure drops along with the constant.
+        let drops = Rc::new(Cell::new(0));
+        let mut chunk = Chunk::new();
+        for line in 0..3 {
+            let counter = DropCounter(Rc::clone(&drops));
+            let native = ObjectNativeFunction::new("counted", 0, move |_| {
+                let _counter = &counter;
+                Ok(Value::Nil)
+            });
+            chunk.add_constant(Value::NativeFunction(native), line);
+        }
+        assert_eq!(drops.get(), 0);
+        drop(chunk);
+        assert_eq!(drops.get(), 3);
+    }
+
+    #[test]
+    fn shrink_to_fit_keeps_contents() {
+        let mut chunk = make_chunk(&[OpCode::Nil as u8, OpCode::Return as u8]);
+        chunk.add_constant(Value::Number(2.0), 3);
+        chunk.shrink_to_fit();
+        assert_eq!(chunk.codes.capacity(), chunk.codes.length);
+        assert_eq!(chunk.lines.capacity(), chunk.lines.length);
+        assert_eq!(chunk.line(3), 3);
+        assert_eq!(chunk.constants.value(0), &Value::Number(2.0));
+    }
+
+    #[test]
+    fn valid_chunk() {
+        let chunk = make_chunk(&[
+            OpCode::True as u8,
+            OpCode::JumpIfFalse as u8, 0, 1,
+            OpCode::Pop as u8,
+            OpCode::Loop as u8, 0, 7,
+            OpCode::Return as u8,
+        ]);
+        assert!(chunk.verify().is_ok());
+    }
+
+    #[test]
+    fn missing_return() {
+        assert!(make_chunk(&[]).verify().is_err());
+        assert!(make_chunk(&[OpCode::Nil as u8]).verify().is_err());
+        assert!(make_chunk(&[OpCode::Return as u8, OpCode::Nil as u8]).verify().is_err());
+    }
+
+    #[test]
+    fn out_of_bounds_jump() {
+        let chunk = make_chunk(&[OpCode::Jump as u8, 0, 10, OpCode::Nil as u8, OpCode::Return as u8]);
+        assert_eq!(chunk.verify().unwrap_err().offset, 0);
+        let chunk = make_chunk(&[OpCode::Nil as u8, OpCode::Loop as u8, 0, 10, OpCode::Return as u8]);
+        assert_eq!(chunk.verify().unwrap_err().offset, 1);
+    }
+
+    #[test]
+    fn jump_into_operand() {
+        let chunk = make_chunk(&[
+            OpCode::Jump as u8, 0, 1,
+            OpCode::GetLocal as u8, 0,
+            OpCode::Return as u8,
+        ]);
+        assert!(chunk.verify().is_err());
+    }
+
+    #[test]
+    fn truncated_operands() {
+        assert!(make_chunk(&[OpCode::Return as u8, OpCode::Jump as u8, 0]).verify().is_err());
+        assert!(make_chunk(&[u8::MAX]).verify().is_err());
+    }
+}
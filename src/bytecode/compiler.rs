@@ -9,9 +9,14 @@ use super::op_code::OpCode;
 use super::scanner::ScanError;
 use super::token::{Token, TokenType};
 use super::value::{Value, object_function::ObjectFunction, object_string};
-use object_string::{ObjectString, INIT_KEYWORD};
+use object_string::{ObjectString, ANONYMOUS_CLASS_NAME, INIT_KEYWORD};
 use super::scanner::Scanner;
+use super::function_cache::FunctionCache;
 use super::parse_rule::{ParseType, Precedence, ParseRule};
+use crate::limits::DEFAULT_MAX_NESTING_DEPTH;
+use crate::number::parse_number;
+
+const PARSE_RULES_COUNT: usize = TokenType::Eof as usize + 1;
 
 pub struct Compiler<'a> {
     scanner: Rc<RefCell<Scanner<'a>>>,
@@ -20,30 +25,42 @@ pub struct Compiler<'a> {
     scope: Rc<RefCell<Scope>>,
     source: &'a str,
     chunk: Chunk,
-    parse_rules: &'a [ParseRule<'a>; 39],
+    parse_rules: &'a [ParseRule<'a>; PARSE_RULES_COUNT],
     previous_token: Option<Token>,
     current_token: Option<Token>,
     loop_context: Option<LoopContext>,
     is_inside_class: bool,
+    has_superclass: bool,
     function_type: FunctionType,
+    implicit_semicolon: bool,
+    expression_depth: usize,
+    max_expression_depth: usize,
+    had_error: bool,
+    function_cache: Option<Rc<RefCell<FunctionCache>>>,
 }
 
 pub struct CompilerContext<'a> {
     scanner: Rc<RefCell<Scanner<'a>>>,
     source: &'a str,
-    parse_rules: &'a [ParseRule<'a>; 39],
+    parse_rules: &'a [ParseRule<'a>; PARSE_RULES_COUNT],
     interned_strings: Rc<RefCell<HashTable<Rc<ObjectString>, ()>>>,
     previous_token: Option<Token>,
     current_token: Option<Token>,
     enclosing_scope: Rc<RefCell<Scope>>,
     is_inside_class: bool,
+    has_superclass: bool,
     function_type: FunctionType,
+    implicit_semicolon: bool,
+    trace: bool,
+    expression_depth: usize,
+    max_expression_depth: usize,
+    function_cache: Option<Rc<RefCell<FunctionCache>>>,
 }
 
 impl<'a> CompilerContext<'a>  {
     pub fn new(
         source: &'a str,
-        parse_rules: &'a [ParseRule<'a>; 39],
+        parse_rules: &'a [ParseRule<'a>; PARSE_RULES_COUNT],
         interned_strings: Rc<RefCell<HashTable<Rc<ObjectString>, ()>>>,
         is_inside_class: bool,
         function_type: FunctionType,
@@ -57,9 +74,33 @@ impl<'a> CompilerContext<'a>  {
             current_token: None,
             enclosing_scope: Rc::new(RefCell::new(Scope::new(None))),
             is_inside_class,
+            has_superclass: false,
             function_type,
+            implicit_semicolon: false,
+            trace: false,
+            expression_depth: 0,
+            max_expression_depth: DEFAULT_MAX_NESTING_DEPTH,
+            function_cache: None,
         }
     }
+
+    pub fn set_implicit_semicolon(&mut self, enabled: bool) {
+        self.implicit_semicolon = enabled;
+    }
+
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.trace = enabled;
+    }
+
+    pub fn set_max_nesting_depth(&mut self, max_nesting_depth: usize) {
+        self.max_expression_depth = max_nesting_depth;
+    }
+
+    /// Reuses global functions whose source is unchanged since an earlier
+    /// compilation that shared the same cache.
+    pub fn set_function_cache(&mut self, function_cache: Rc<RefCell<FunctionCache>>) {
+        self.function_cache = Some(function_cache);
+    }
 }
 
 impl<'a> Compiler<'a> {
@@ -70,13 +111,19 @@ impl<'a> Compiler<'a> {
             string_constants: HashTable::new(),
             scope: Rc::new(RefCell::new(Scope::new(Some(context.enclosing_scope)))),
             source: context.source,
-            chunk: Chunk::new(),
+            chunk: Chunk::with_trace(context.trace),
             parse_rules: context.parse_rules,
             previous_token: context.previous_token,
             current_token: context.current_token,
             loop_context: None,
             is_inside_class: context.is_inside_class,
+            has_superclass: context.has_superclass,
             function_type: context.function_type,
+            implicit_semicolon: context.implicit_semicolon,
+            expression_depth: context.expression_depth,
+            max_expression_depth: context.max_expression_depth,
+            had_error: false,
+            function_cache: context.function_cache,
         }
     }
 
@@ -84,6 +131,8 @@ impl<'a> Compiler<'a> {
         if let Err(error) = self.start_compilation() {
             self.handle_error(&error);
             None
+        } else if self.had_error {
+            None
         } else {
             Some(&self.chunk)
         }
@@ -94,10 +143,11 @@ impl<'a> Compiler<'a> {
         while self.current_token().token_type != TokenType::Eof {
             if let Err(error) = self.declaration() {
                 self.handle_error(&error);
+                self.had_error = true;
                 self.synchronize();
             }
         }
-        let line = self.previous_token().line;
+        let line = self.current_token().line;
         self.consume(TokenType::Eof, "Expect end of expression.")?;
         self.emit_return(line);
         Ok(())
@@ -114,24 +164,31 @@ impl<'a> Compiler<'a> {
     }
 
     #[inline]
-    fn scope(&self) -> Ref<Scope> {
+    fn scope(&self) -> Ref<'_, Scope> {
         self.scope.as_ref().borrow()
     }
 
     #[inline]
-    fn scope_mut(&self) -> RefMut<Scope> {
+    fn scope_mut(&self) -> RefMut<'_, Scope> {
         self.scope.as_ref().borrow_mut()
     }
 
+    /// Moves to the next valid token. Scan errors are skipped so there is
+    /// always a current token to synchronize on; the first one is returned
+    /// and any that follow it are reported directly.
     fn advance(&mut self) -> CompilationResult {
         self.previous_token = self.current_token.take();
-        let token = self.scanner
-            .as_ref()
-            .borrow_mut()
-            .scan_token()
-            .map_err(CompileError::ScanError)?;
+        let mut result = Ok(());
+        let token = loop {
+            let scan_result = self.scanner.as_ref().borrow_mut().scan_token();
+            match scan_result {
+                Ok(token) => break token,
+                Err(error) if result.is_ok() => result = Err(CompileError::ScanError(error)),
+                Err(error) => self.handle_error(&CompileError::ScanError(error)),
+            }
+        };
         self.current_token = Some(token);
-        Ok(())
+        result
     }
 
     fn consume(
@@ -147,6 +204,14 @@ impl<'a> Compiler<'a> {
         }
     }
 
+    fn consume_semicolon(&mut self, error_message: &'static str) -> CompilationResult {
+        if self.implicit_semicolon && self.current_token().token_type == TokenType::Eof {
+            Ok(())
+        } else {
+            self.consume(TokenType::Semicolon, error_message)
+        }
+    }
+
     #[inline]
     fn declaration(&mut self) -> CompilationResult {
         let token_type = self.current_token().token_type;
@@ -159,7 +224,7 @@ impl<'a> Compiler<'a> {
                 self.advance()?;
                 self.function_declaration()
             }
-            TokenType::Class => {
+            TokenType::Class if !self.is_class_expression_next() => {
                 self.advance()?;
                 self.class_declaration()
             }
@@ -194,6 +259,10 @@ impl<'a> Compiler<'a> {
                 self.advance()?;
                 self.continue_statement()
             }
+            TokenType::Break => {
+                self.advance()?;
+                self.break_statement()
+            }
             TokenType::LeftBrace => self.parse_block(),
             _ => self.expression_statement()
         }
@@ -207,7 +276,7 @@ impl<'a> Compiler<'a> {
             Err(CompileError::make_from_token(self.previous_token(), "Can't return a value from an initializer."))
         } else {
             self.expression()?;
-            self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+            self.consume_semicolon("Expect ';' after return value.")?;
             self.chunk.push_code(OpCode::Return, self.previous_token().line);
             Ok(())
         }
@@ -222,7 +291,7 @@ impl<'a> Compiler<'a> {
         } else {
             self.modify_chunk(|chunk| chunk.push_code(OpCode::Nil, line));
         }
-        self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.")?;
+        self.consume_semicolon("Expect ';' after variable declaration.")?;
         self.define_variable(index, line);
         Ok(())
     }
@@ -273,20 +342,86 @@ impl<'a> Compiler<'a> {
 
     #[inline]
     fn function_declaration(&mut self) -> CompilationResult {
+        let hoisted_index = self.scope_mut().take_hoisted_local(self.current_token(), self.source);
+        if let Some(index) = hoisted_index {
+            self.advance()?;
+            let line = self.current_token().line;
+            self.compile_function(FunctionType::Function)?;
+            self.chunk.push_code(OpCode::SetLocal, line);
+            self.chunk.push(index, line);
+            self.chunk.push_code(OpCode::Pop, line);
+            return Ok(());
+        }
         let global_index = self.parse_variable("Expect function name.")?;
         let line = self.current_token().line;
-        if global_index.is_none() {
-            self.scope_mut().mark_local_initialized();
+        match self.function_cache.clone() {
+            Some(function_cache) if global_index.is_some() => self.compile_cached_function(function_cache)?,
+            _ => {
+                if global_index.is_none() {
+                    self.scope_mut().mark_local_initialized();
+                }
+                self.compile_function(FunctionType::Function)?;
+            }
         }
-        self.compile_function(FunctionType::Function)?;
         self.define_variable(global_index, line);
         Ok(())
     }
 
-    fn compile_function(&mut self, function_type: FunctionType) -> CompilationResult {
+    /// Global functions cannot capture upvalues, so their chunk depends only
+    /// on their source and can be shared between scripts of a session.
+    fn compile_cached_function(&mut self, function_cache: Rc<RefCell<FunctionCache>>) -> CompilationResult {
+        let name_token = self.previous_token().clone();
+        let (Some(name), Some(body_end)) = (name_token.lexeme, self.function_body_end()) else {
+            return self.compile_function(FunctionType::Function).map(|_| ());
+        };
+        let source = &self.source[name.start..body_end.start + body_end.length];
+        let cached_function = function_cache.as_ref().borrow().find(source, name_token.line);
+        match cached_function {
+            Some(function) => {
+                while self.previous_token().lexeme.map(|lexeme| lexeme.start) != Some(body_end.start) {
+                    self.advance()?;
+                }
+                let constant_index = self.chunk.push_constant_to_pool(Value::Function(function));
+                self.chunk.push_code(OpCode::Closure, name_token.line);
+                self.chunk.push(constant_index as u8, name_token.line);
+            }
+            None => {
+                let function = self.compile_function(FunctionType::Function)?;
+                function_cache.as_ref().borrow_mut().insert(source, name_token.line, function);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the `}` closing the body of the function whose parameter list
+    /// starts at the current token, without consuming any tokens.
+    fn function_body_end(&self) -> Option<Lexeme> {
+        let mut scanner = self.scanner.as_ref().borrow().lookahead();
+        let mut token = self.current_token().clone();
+        let mut depth = 0usize;
+        loop {
+            match token.token_type {
+                TokenType::Eof => return None,
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 1 => return token.lexeme,
+                TokenType::RightBrace => depth = depth.checked_sub(1)?,
+                _ => {}
+            }
+            token = scanner.scan_token().ok()?;
+        }
+    }
+
+    fn compile_function(&mut self, function_type: FunctionType) -> Result<Rc<ObjectFunction>, CompileError> {
         let function_name = self.intern_string();
         let function_name_line = self.previous_token().line;
+        let mut compiler = self.nested_compiler(function_type)?;
+        let arity = compiler.parse_function()?;
+        let line = self.previous_token().line;
+        compiler.emit_return(line);
+        Ok(self.emit_closure(compiler, function_name, arity, function_name_line))
+    }
 
+    fn nested_compiler(&self, function_type: FunctionType) -> Result<Compiler<'a>, CompileError> {
         let compiler_context = CompilerContext {
             scanner: Rc::clone(&self.scanner),
             source: self.source,
@@ -296,39 +431,47 @@ impl<'a> Compiler<'a> {
             current_token: self.current_token.clone(),
             enclosing_scope: Rc::clone(&self.scope),
             is_inside_class: self.is_inside_class,
+            has_superclass: self.has_superclass,
             function_type,
+            implicit_semicolon: false,
+            trace: self.chunk.is_tracing(),
+            // Function bodies inside expressions keep counting the enclosing nesting.
+            expression_depth: self.expression_depth,
+            max_expression_depth: self.max_expression_depth,
+            function_cache: None,
         };
-        let mut compiler = Compiler::new(compiler_context);
-
+        let compiler = Compiler::new(compiler_context);
         let token = Token {
             token_type: function_type.initial_local_variable_token_type(),
             lexeme: None,
             line: 0,
         };
         compiler.scope.as_ref().borrow_mut().add_local(token)?;
+        Ok(compiler)
+    }
 
-        let arity = compiler.parse_function()?;
-        let line = self.previous_token().line;
-        compiler.emit_return(line);
-
+    fn emit_closure(
+        &mut self,
+        mut compiler: Compiler<'a>,
+        function_name: Rc<ObjectString>,
+        arity: u8,
+        function_name_line: usize,
+    ) -> Rc<ObjectFunction> {
         self.previous_token = compiler.previous_token.clone();
         self.current_token = compiler.current_token.clone();
 
         let upvalue_count = compiler.scope().upvalues_size();
-        let function = ObjectFunction {
-            name: function_name,
-            arity,
-            upvalue_count,
-            chunk: mem::replace(&mut compiler.chunk, Chunk::new()),
-        };
-        let constant_index = self.chunk.push_constant_to_pool(Value::Function(Rc::new(function)));
+        let mut chunk = mem::replace(&mut compiler.chunk, Chunk::new());
+        chunk.shrink_to_fit();
+        let function = Rc::new(ObjectFunction::new(function_name, arity, upvalue_count, chunk));
+        let constant_index = self.chunk.push_constant_to_pool(Value::Function(Rc::clone(&function)));
         self.chunk.push_code(OpCode::Closure, function_name_line);
         self.chunk.push(constant_index as u8, function_name_line);
         for upvalue in compiler.scope().upvalues_iter() {
             self.chunk.push(if upvalue.is_local { 1 } else { 0 }, function_name_line);
             self.chunk.push(upvalue.index, function_name_line);
         }
-        Ok(())
+        function
     }
 
     fn parse_function(&mut self) -> Result<u8, CompileError> {
@@ -340,6 +483,7 @@ impl<'a> Compiler<'a> {
         };
         self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
         self.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
+        self.hoist_functions()?;
         self.block_statement()?;
         Ok(arity)
     }
@@ -354,6 +498,12 @@ impl<'a> Compiler<'a> {
                ))
             }
             arity += 1;
+            let token = self.current_token();
+            if token.token_type == TokenType::Identifier && self.scope().is_redeclaration(token, self.source) {
+                let name = token.lexeme.as_ref().unwrap().make_slice(self.source);
+                let message = format!("Duplicate parameter name '{}'.", name);
+                return Err(CompileError::make_from_token(token, message));
+            }
             let index = self.parse_variable("Expect parameter name.")?;
             self.define_variable(index, self.previous_token().line);
             if self.current_token().token_type == TokenType::Comma {
@@ -368,6 +518,7 @@ impl<'a> Compiler<'a> {
     #[inline]
     fn class_declaration(&mut self) -> CompilationResult {
         self.consume(TokenType::Identifier, "Expect class name.")?;
+        let class_name = self.previous_token().clone();
         let name = self.intern_string();
         let constant_index = self.chunk.push_constant_to_pool(Value::String(name));
         let line = self.previous_token().line;
@@ -382,32 +533,116 @@ impl<'a> Compiler<'a> {
             self.define_variable(None, line);
         }
         let prev_is_inside_class = self.is_inside_class;
+        let prev_has_superclass = self.has_superclass;
         self.is_inside_class = true;
+        self.has_superclass = false;
 
-        // TODO: Will break with inheritance
-        self.variable(false)?;
+        let result = self.class_body(&class_name, line);
+        if self.has_superclass {
+            self.end_scope();
+        }
+        self.is_inside_class = prev_is_inside_class;
+        self.has_superclass = prev_has_superclass;
+        result
+    }
+
+    /// Compiles `class { ... }` in expression position. The class is built by
+    /// a hidden function that is called straight away, so the `super` local
+    /// gets its own frame instead of landing among the expression temporaries.
+    fn class_expression(&mut self, _can_assign: bool) -> CompilationResult {
+        let line = self.previous_token().line;
+        let name = self.interned_strings
+            .as_ref()
+            .borrow_mut()
+            .find_string_or_insert_new(ANONYMOUS_CLASS_NAME.to_string());
+        let mut compiler = self.nested_compiler(FunctionType::Function)?;
+        compiler.anonymous_class_body(Rc::clone(&name), line)?;
+        self.emit_closure(compiler, name, 0, line);
+        self.chunk.push_code(OpCode::Call, line);
+        self.chunk.push(0u8, line);
+        Ok(())
+    }
+
+    fn anonymous_class_body(&mut self, name: Rc<ObjectString>, line: usize) -> CompilationResult {
+        let class_token = Token {
+            token_type: TokenType::Class,
+            lexeme: None,
+            line,
+        };
+        let constant_index = self.chunk.push_constant_to_pool(Value::String(name));
+        self.scope_mut().begin_scope();
+        self.chunk.push_code(OpCode::Class, line);
+        self.chunk.push(constant_index as u8, line);
+        self.scope_mut().add_local(class_token.clone())?;
+        self.scope_mut().mark_local_initialized();
+
+        self.is_inside_class = true;
+        self.class_body(&class_token, line)?;
+        if self.has_superclass {
+            self.end_scope();
+        }
+        self.named_variable(&class_token, false)?;
+        self.chunk.push_code(OpCode::Return, line);
+        Ok(())
+    }
+
+    fn class_body(&mut self, class_name: &Token, line: usize) -> CompilationResult {
+        if self.current_token().token_type == TokenType::Less {
+            self.advance()?;
+            self.superclass(class_name)?;
+        }
+        self.named_variable(class_name, false)?;
         self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+        let mut method_names = HashTable::new();
         loop {
             match self.current_token().token_type {
                 TokenType::RightBrace | TokenType::Eof => {
                     break;
                 }
                 _ => {
-                    self.method()?;
+                    self.method(&mut method_names)?;
                 }
             }
         }
         self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
         self.chunk.push_code(OpCode::Pop, line);
-        self.is_inside_class = prev_is_inside_class;
+        Ok(())
+    }
+
+    fn superclass(&mut self, class_name: &Token) -> CompilationResult {
+        self.consume(TokenType::Identifier, "Expect superclass name.")?;
+        let superclass_name = self.previous_token();
+        let lexeme = superclass_name.lexeme.as_ref().map(|lexeme| lexeme.make_slice(self.source));
+        if lexeme == class_name.lexeme.as_ref().map(|lexeme| lexeme.make_slice(self.source)) {
+            return Err(CompileError::make_from_token(superclass_name, "A class can't inherit from itself."));
+        }
+        self.variable(false)?;
+
+        self.scope_mut().begin_scope();
+        let token = Token {
+            token_type: TokenType::Super,
+            lexeme: None,
+            line: 0,
+        };
+        self.scope_mut().add_local(token)?;
+        self.scope_mut().mark_local_initialized();
+        self.has_superclass = true;
+
+        self.named_variable(class_name, false)?;
+        self.push_code(OpCode::Inherit);
         Ok(())
     }
 
     #[inline]
-    fn method(&mut self) -> CompilationResult {
+    fn method(&mut self, method_names: &mut HashTable<Rc<ObjectString>, ()>) -> CompilationResult {
         self.consume(TokenType::Identifier, "Expect method name.")?;
         let name = self.intern_string();
-        let is_initializer = &name.value == INIT_KEYWORD;
+        if method_names.contains(&name) {
+            let message = format!("Duplicate method '{}' in class.", name.value);
+            return Err(CompileError::make_from_token(self.previous_token(), message));
+        }
+        method_names.insert(Rc::clone(&name), ());
+        let is_initializer = name.value == INIT_KEYWORD;
         let constant_index = self.chunk.push_constant_to_pool(Value::String(name));
         let line = self.previous_token().line;
         self.compile_function(FunctionType::Method(is_initializer))?;
@@ -419,7 +654,7 @@ impl<'a> Compiler<'a> {
     #[inline]
     fn print_statement(&mut self) -> CompilationResult {
         self.expression()?;
-        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        self.consume_semicolon("Expect ';' after value.")?;
         self.push_code(OpCode::Print);
         Ok(())
     }
@@ -427,11 +662,56 @@ impl<'a> Compiler<'a> {
     fn parse_block(&mut self) -> CompilationResult {
         self.advance()?;
         self.scope_mut().begin_scope();
-        let result = self.block_statement();
+        let result = self.hoist_functions().and_then(|_| self.block_statement());
         self.end_scope();
         result
     }
 
+    /// Reserves a local slot for every function declared directly in the
+    /// block that starts at the current token, so functions of the block can
+    /// call each other regardless of declaration order.
+    fn hoist_functions(&mut self) -> CompilationResult {
+        let line = self.current_token().line;
+        for token in self.block_function_names() {
+            if self.scope().is_redeclaration(&token, self.source) {
+                return Err(CompileError::make_from_token(&token, "Already a variable with this name in this scope."));
+            }
+            self.scope_mut().add_hoisted_local(token)?;
+            self.chunk.push_code(OpCode::Nil, line);
+        }
+        Ok(())
+    }
+
+    /// `class` directly followed by `{` or `<` starts an anonymous class
+    /// expression rather than a declaration.
+    fn is_class_expression_next(&self) -> bool {
+        let next_token = self.scanner.as_ref().borrow().lookahead().scan_token();
+        matches!(next_token.map(|token| token.token_type), Ok(TokenType::LeftBrace | TokenType::Less))
+    }
+
+    fn block_function_names(&self) -> Vec<Token> {
+        let mut scanner = self.scanner.as_ref().borrow().lookahead();
+        let mut names = Vec::new();
+        let mut previous_type = TokenType::LeftBrace;
+        let mut token = self.current_token().clone();
+        let mut depth = 0usize;
+        loop {
+            match token.token_type {
+                TokenType::Eof => return names,
+                TokenType::LeftBrace => depth += 1,
+                TokenType::RightBrace if depth == 0 => return names,
+                TokenType::RightBrace => depth -= 1,
+                TokenType::Identifier if depth == 0 && previous_type == TokenType::Fun => names.push(token.clone()),
+                _ => {}
+            }
+            previous_type = token.token_type;
+            token = match scanner.scan_token() {
+                Ok(token) => token,
+                Err(_) => return names,
+            };
+        }
+    }
+
     fn block_statement(&mut self) -> CompilationResult {
         loop {
             match self.current_token().token_type {
@@ -461,19 +741,16 @@ impl<'a> Compiler<'a> {
         let then_jump = self.emit_jump(OpCode::JumpIfFalse, condition_line);
         self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, condition_line));
 
-        let previous_loop_context = self.loop_context;
         let locals_depth = self.scope().current_scope_depth();
-        self.loop_context = Some(LoopContext {
-            start_index: loop_start,
-            locals_depth,
-        });
-        self.statement()?;
-        self.loop_context = previous_loop_context;
+        let previous_loop_context = self.loop_context.replace(LoopContext::new(loop_start, locals_depth));
+        let result = self.statement();
+        let loop_context = mem::replace(&mut self.loop_context, previous_loop_context);
+        result?;
 
         self.emit_loop(loop_start, condition_line)?;
         self.patch_jump(then_jump)?;
         self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, condition_line));
-        Ok(())
+        self.patch_break_jumps(loop_context)
     }
 
     fn for_statement(&mut self) -> CompilationResult {
@@ -482,33 +759,34 @@ impl<'a> Compiler<'a> {
         self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
         self.initializer_clause()?;
 
-        let previous_loop_context = self.loop_context;
         let mut loop_start = self.current_chunk_size();
-        let locals_depth = self.scope().current_scope_depth();
-        self.loop_context = Some(LoopContext {
-            start_index: loop_start,
-            locals_depth,
-        });
         let exit_jump = self.condition_clause()?;
         self.increment_clause(&mut loop_start)?;
 
         let locals_depth = self.scope().current_scope_depth();
-        self.loop_context = Some(LoopContext {
-            start_index: loop_start,
-            locals_depth,
-        });
-        self.statement()?;
-        self.loop_context = previous_loop_context;
+        let previous_loop_context = self.loop_context.replace(LoopContext::new(loop_start, locals_depth));
+        let result = self.statement();
+        let loop_context = mem::replace(&mut self.loop_context, previous_loop_context);
+        result?;
         self.emit_loop(loop_start, statement_line)?;
 
         if let Some(exit_jump) = exit_jump {
             self.patch_jump(exit_jump)?;
             self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, statement_line));
         }
+        self.patch_break_jumps(loop_context)?;
         self.end_scope();
         Ok(())
     }
 
+    fn patch_break_jumps(&mut self, loop_context: Option<LoopContext>) -> CompilationResult {
+        let break_jumps = loop_context.map(|context| context.break_jumps).unwrap_or_default();
+        for jump in break_jumps {
+            self.patch_jump(jump)?;
+        }
+        Ok(())
+    }
+
     #[inline]
     fn initializer_clause(&mut self) -> CompilationResult {
         match self.current_token().token_type {
@@ -553,24 +831,38 @@ impl<'a> Compiler<'a> {
     }
 
     fn if_statement(&mut self) -> CompilationResult {
-        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
-        self.expression()?;
-        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        // `else if` branches are compiled in a loop rather than recursively,
+        // so long chains neither grow the native stack nor nest jumps.
+        let mut end_jumps = Vec::new();
+        loop {
+            self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+            self.expression()?;
+            self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
 
-        let if_condition_line = self.current_token().line;
-        let then_jump = self.emit_jump(OpCode::JumpIfFalse, if_condition_line);
-        self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, if_condition_line));
-        self.statement()?;
+            let if_condition_line = self.current_token().line;
+            let then_jump = self.emit_jump(OpCode::JumpIfFalse, if_condition_line);
+            self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, if_condition_line));
+            self.statement()?;
 
-        let else_jump = self.emit_jump(OpCode::Jump, self.current_token().line);
-        self.patch_jump(then_jump)?;
-        self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, if_condition_line));
+            end_jumps.push(self.emit_jump(OpCode::Jump, self.current_token().line));
+            self.patch_jump(then_jump)?;
+            self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, if_condition_line));
 
-        if self.current_token().token_type == TokenType::Else {
+            if self.current_token().token_type != TokenType::Else {
+                break;
+            }
             self.advance()?;
-            self.statement()?;
+            if self.current_token().token_type == TokenType::If {
+                self.advance()?;
+            } else {
+                self.statement()?;
+                break;
+            }
         }
-        self.patch_jump(else_jump)
+        for jump in end_jumps {
+            self.patch_jump(jump)?;
+        }
+        Ok(())
     }
 
     #[inline]
@@ -624,16 +916,12 @@ impl<'a> Compiler<'a> {
 
     #[inline]
     fn continue_statement(&mut self) -> CompilationResult {
-        self.consume(TokenType::Semicolon, "Expect ';' after continue statement.")?;
-        let token = self.previous_token();
-        match self.loop_context {
-            Some(context) => {
-                let line = token.line;
-                let locals_count = self.scope_mut().remove_to_scope(context.locals_depth + 1);
-                for _ in 0..locals_count {
-                    self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, line));
-                }
-                self.emit_loop(context.start_index, line)
+        self.consume_semicolon("Expect ';' after continue statement.")?;
+        let line = self.previous_token().line;
+        match self.loop_context.as_ref().map(|context| (context.start_index, context.locals_depth)) {
+            Some((start_index, locals_depth)) => {
+                self.pop_loop_locals(locals_depth, line);
+                self.emit_loop(start_index, line)
             }
             None => {
                 let token = self.previous_token();
@@ -642,20 +930,74 @@ impl<'a> Compiler<'a> {
         }
     }
 
+    #[inline]
+    fn break_statement(&mut self) -> CompilationResult {
+        self.consume_semicolon("Expect ';' after break statement.")?;
+        let line = self.previous_token().line;
+        match self.loop_context.as_ref().map(|context| context.locals_depth) {
+            Some(locals_depth) => {
+                self.pop_loop_locals(locals_depth, line);
+                let jump = self.emit_jump(OpCode::Jump, line);
+                if let Some(context) = self.loop_context.as_mut() {
+                    context.break_jumps.push(jump);
+                }
+                Ok(())
+            }
+            None => {
+                let token = self.previous_token();
+                Err(CompileError::make_from_token(token, "Can't use 'break' outside of a loop."))
+            }
+        }
+    }
+
+    #[inline]
+    fn pop_loop_locals(&mut self, locals_depth: u8, line: usize) {
+        let locals_count = self.scope().locals_count_to_scope(locals_depth + 1);
+        for _ in 0..locals_count {
+            self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, line));
+        }
+    }
+
     #[inline]
     fn expression_statement(&mut self) -> CompilationResult {
+        let start = self.current_chunk_size();
         self.expression()?;
-        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
-        self.push_code(OpCode::Pop);
+        self.consume_semicolon("Expect ';' after expression.")?;
+        if self.is_side_effect_free(start) {
+            self.chunk.truncate(start);
+        } else {
+            self.push_code(OpCode::Pop);
+        }
         Ok(())
     }
 
+    fn is_side_effect_free(&self, start: usize) -> bool {
+        let size = self.current_chunk_size() - start;
+        match self.chunk.codes.get(start).map(|code| Chunk::byte_to_op_code(*code)) {
+            Some(op_code @ (
+                OpCode::Constant | OpCode::ConstantLong | OpCode::True | OpCode::False
+                | OpCode::Nil | OpCode::GetLocal | OpCode::GetUpvalue
+            )) => op_code.code_size() == size,
+            _ => false,
+        }
+    }
+
     #[inline]
     fn expression(&mut self) -> CompilationResult {
         self.parse_precedence(Precedence::Assignment)
     }
 
     fn parse_precedence(&mut self, precedence: Precedence) -> CompilationResult {
+        if self.expression_depth >= self.max_expression_depth {
+            return Err(CompileError::make_from_token(self.current_token(), "Expression nesting too deep."));
+        }
+        self.expression_depth += 1;
+        let result = self.parse_nested_precedence(precedence);
+        self.expression_depth -= 1;
+        result
+    }
+
+    fn parse_nested_precedence(&mut self, precedence: Precedence) -> CompilationResult {
         self.advance()?;
         let token = self.previous_token();
         let rule = self.parse_rule(&token.token_type);
@@ -679,7 +1021,8 @@ impl<'a> Compiler<'a> {
                 None => Err(CompileError::make_from_token(token, "Expect expression."))
             }?;
         };
-        if can_assign && self.current_token().token_type == TokenType::Equal {
+        let token_type = self.current_token().token_type;
+        if can_assign && (token_type == TokenType::Equal || Compiler::compound_operator(token_type).is_some()) {
             self.advance()?;
             Err(CompileError::make_from_token(self.previous_token(), "Invalid assignment target."))
         } else {
@@ -697,7 +1040,12 @@ impl<'a> Compiler<'a> {
         let previous_token = self.previous_token();
         let line = previous_token.line;
         let token_type = previous_token.token_type;
-        self.parse_precedence(Precedence::Unary)?;
+        // A grouping operand already counts its own nesting level.
+        if self.current_token().token_type == TokenType::LeftParen {
+            self.parse_nested_precedence(Precedence::Unary)?;
+        } else {
+            self.parse_precedence(Precedence::Unary)?;
+        }
         self.modify_chunk(|chunk| {
             match token_type {
                 TokenType::Minus => chunk.push_code(OpCode::Negate, line),
@@ -721,21 +1069,17 @@ impl<'a> Compiler<'a> {
                 TokenType::Minus => chunk.push_code(OpCode::Subtract, token_line),
                 TokenType::Star => chunk.push_code(OpCode::Multiply, token_line),
                 TokenType::Slash => chunk.push_code(OpCode::Divide, token_line),
+                TokenType::Percent => chunk.push_code(OpCode::Modulo, token_line),
                 TokenType::BangEqual => {
                     chunk.push_code(OpCode::Equal, token_line);
                     chunk.push_code(OpCode::Not, token_line);
                 }
                 TokenType::EqualEqual => chunk.push_code(OpCode::Equal, token_line),
                 TokenType::Greater => chunk.push_code(OpCode::Greater, token_line),
-                TokenType::GreaterEqual => {
-                    chunk.push_code(OpCode::Less, token_line);
-                    chunk.push_code(OpCode::Not, token_line);
-                }
+                // Not `!(a < b)`: every comparison with NaN must be false.
+                TokenType::GreaterEqual => chunk.push_code(OpCode::GreaterEqual, token_line),
                 TokenType::Less => chunk.push_code(OpCode::Less, token_line),
-                TokenType::LessEqual => {
-                    chunk.push_code(OpCode::Greater, token_line);
-                    chunk.push_code(OpCode::Not, token_line);
-                }
+                TokenType::LessEqual => chunk.push_code(OpCode::LessEqual, token_line),
                 _ => {}
             };
         });
@@ -753,6 +1097,23 @@ impl<'a> Compiler<'a> {
             self.chunk.push_code(OpCode::SetProperty, line);
             self.chunk.push(constant_index, line);
             Ok(())
+        } else if let Some(operator) = self.compound_assignment(can_assign) {
+            self.advance()?;
+            self.chunk.push_code(OpCode::Dup, line);
+            self.chunk.push_code(OpCode::GetProperty, line);
+            self.chunk.push(constant_index, line);
+            self.expression()?;
+            self.chunk.push_code(operator, line);
+            self.chunk.push_code(OpCode::SetProperty, line);
+            self.chunk.push(constant_index, line);
+            Ok(())
+        } else if self.current_token().token_type == TokenType::LeftParen {
+            self.advance()?;
+            let arguments_count = self.parse_arguments()?;
+            self.chunk.push_code(OpCode::Invoke, line);
+            self.chunk.push(constant_index, line);
+            self.chunk.push(arguments_count, line);
+            Ok(())
         } else {
             self.chunk.push_code(OpCode::GetProperty, line);
             self.chunk.push(constant_index, line);
@@ -760,6 +1121,41 @@ impl<'a> Compiler<'a> {
         }
     }
 
+    fn list(&mut self, _can_assign: bool) -> CompilationResult {
+        let line = self.previous_token().line;
+        let mut elements_count = 0u8;
+        while self.current_token().token_type != TokenType::RightBracket {
+            if elements_count == u8::MAX {
+                let token = self.current_token();
+                return Err(CompileError::make_from_token(token, "Can't have more than 255 elements in a list literal."));
+            }
+            elements_count += 1;
+            self.expression()?;
+            if self.current_token().token_type != TokenType::Comma {
+                break;
+            }
+            self.advance()?;
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+        self.chunk.push_code(OpCode::BuildList, line);
+        self.chunk.push(elements_count, line);
+        Ok(())
+    }
+
+    fn index(&mut self, can_assign: bool) -> CompilationResult {
+        self.expression()?;
+        self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+        let line = self.previous_token().line;
+        if can_assign && self.current_token().token_type == TokenType::Equal {
+            self.advance()?;
+            self.expression()?;
+            self.chunk.push_code(OpCode::IndexSet, line);
+        } else {
+            self.chunk.push_code(OpCode::IndexGet, line);
+        }
+        Ok(())
+    }
+
     fn and_operator(&mut self, _can_assign: bool) -> CompilationResult {
         let line = self.current_token().line;
         let jump = self.emit_jump(OpCode::JumpIfFalse, line);
@@ -781,13 +1177,27 @@ impl<'a> Compiler<'a> {
         self.patch_jump(end_jump)
     }
 
+    fn conditional(&mut self, _can_assign: bool) -> CompilationResult {
+        let line = self.previous_token().line;
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse, line);
+        self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, line));
+        self.expression()?;
+        self.consume(TokenType::Colon, "Expect ':' after then branch of conditional expression.")?;
+
+        let else_jump = self.emit_jump(OpCode::Jump, line);
+        self.patch_jump(then_jump)?;
+        self.modify_chunk(|chunk| chunk.push_code(OpCode::Pop, line));
+        self.parse_precedence(Precedence::Conditional)?;
+        self.patch_jump(else_jump)
+    }
+
     fn emit_number(&mut self, _can_assign: bool) -> CompilationResult {
-        let number: f32 = self.previous_token().lexeme
+        let lexeme = self.previous_token().lexeme
             .as_ref()
             .expect("Only EOF token can not have a lexeme")
-            .make_slice(self.source)
-            .parse()
-            .expect("Invalid number parsed");
+            .make_slice(self.source);
+        let number: f32 = parse_number(lexeme)
+            .map_err(|message| CompileError::make_from_token(self.previous_token(), message))?;
         let line = self.previous_token().line;
         self.modify_chunk(|chunk| chunk.add_constant(Value::Number(number), line));
         Ok(())
@@ -818,7 +1228,12 @@ impl<'a> Compiler<'a> {
     }
 
     fn variable(&mut self, can_assign: bool) -> CompilationResult {
-        let (set_code, get_code, index) = self.variable_operations()?;
+        let token = self.previous_token().clone();
+        self.named_variable(&token, can_assign)
+    }
+
+    fn named_variable(&mut self, token: &Token, can_assign: bool) -> CompilationResult {
+        let (set_code, get_code, index) = self.variable_operations(token)?;
         if can_assign && self.current_token().token_type == TokenType::Equal {
             self.advance()?;
             self.expression()?;
@@ -827,6 +1242,19 @@ impl<'a> Compiler<'a> {
                 chunk.push_code(set_code, line);
                 chunk.push(index, line);
             });
+        } else if let Some(operator) = self.compound_assignment(can_assign) {
+            self.advance()?;
+            let line = self.previous_token().line;
+            self.modify_chunk(|chunk| {
+                chunk.push_code(get_code, line);
+                chunk.push(index, line);
+            });
+            self.expression()?;
+            self.modify_chunk(|chunk| {
+                chunk.push_code(operator, line);
+                chunk.push_code(set_code, line);
+                chunk.push(index, line);
+            });
         } else {
             let line = self.previous_token().line;
             self.modify_chunk(|chunk| {
@@ -837,6 +1265,25 @@ impl<'a> Compiler<'a> {
         Ok(())
     }
 
+    fn compound_assignment(&self, can_assign: bool) -> Option<OpCode> {
+        if can_assign {
+            Compiler::compound_operator(self.current_token().token_type)
+        } else {
+            None
+        }
+    }
+
+    /// Arithmetic performed by a compound assignment token such as `+=`.
+    fn compound_operator(token_type: TokenType) -> Option<OpCode> {
+        match token_type {
+            TokenType::PlusEqual => Some(OpCode::Add),
+            TokenType::MinusEqual => Some(OpCode::Subtract),
+            TokenType::StarEqual => Some(OpCode::Multiply),
+            TokenType::SlashEqual => Some(OpCode::Divide),
+            _ => None,
+        }
+    }
+
     fn function_call(&mut self, _can_assign: bool) -> CompilationResult {
         let line = self.current_token().line;
         let arguments_count = self.parse_arguments()?;
@@ -870,15 +1317,15 @@ impl<'a> Compiler<'a> {
     }
 
     #[inline]
-    fn variable_operations(&mut self) -> Result<(OpCode, OpCode, u8), CompileError> {
-        let local_index = self.scope().find_local(self.previous_token(), self.source)?;
+    fn variable_operations(&mut self, token: &Token) -> Result<(OpCode, OpCode, u8), CompileError> {
+        let local_index = self.scope().find_local(token, self.source)?;
         match local_index {
             Some(index) => Ok((OpCode::SetLocal, OpCode::GetLocal, index)),
             None => {
-                if let Some(upvalue_index) = self.scope_mut().resolve_upvalue(self.previous_token(), self.source)? {
+                if let Some(upvalue_index) = self.scope_mut().resolve_upvalue(token, self.source)? {
                     return Ok((OpCode::SetUpvalue, OpCode::GetUpvalue, upvalue_index));
                 }
-                let object = self.intern_string();
+                let object = self.intern_token(token);
                 let index = self.string_constants.find(&object).copied();
                 let index = match index {
                     Some(index) => index,
@@ -898,16 +1345,48 @@ impl<'a> Compiler<'a> {
 
     #[inline]
     fn this(&mut self, _can_assign: bool) -> CompilationResult {
-        if self.is_inside_class == false {
+        if !self.is_inside_class {
             Err(CompileError::make_from_token(self.previous_token(), "Can't use 'this' outside of a class."))
         } else {
             self.variable(false)
         }
     }
 
+    #[inline]
+    fn super_(&mut self, _can_assign: bool) -> CompilationResult {
+        let super_token = self.previous_token().clone();
+        if !self.is_inside_class {
+            return Err(CompileError::make_from_token(&super_token, "Can't use 'super' outside of a class."));
+        }
+        if !self.has_superclass {
+            return Err(CompileError::make_from_token(&super_token, "Can't use 'super' in a class with no superclass."));
+        }
+        self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+        self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+        let name = self.intern_string();
+        let constant_index = self.chunk.push_constant_to_pool(Value::String(name)) as u8;
+        let line = self.previous_token().line;
+
+        let this_token = Token {
+            token_type: TokenType::This,
+            lexeme: None,
+            line,
+        };
+        self.named_variable(&this_token, false)?;
+        self.named_variable(&super_token, false)?;
+        self.chunk.push_code(OpCode::GetSuper, line);
+        self.chunk.push(constant_index, line);
+        Ok(())
+    }
+
     #[inline]
     fn intern_string(&mut self) -> Rc<ObjectString> {
-        let token = self.previous_token();
+        let token = self.previous_token().clone();
+        self.intern_token(&token)
+    }
+
+    #[inline]
+    fn intern_token(&self, token: &Token) -> Rc<ObjectString> {
         let lexeme = token.lexeme
             .as_ref()
             .unwrap()
@@ -990,8 +1469,8 @@ impl<'a> Compiler<'a> {
         }
     }
 
-    pub fn make_parse_rules<'c>() -> [ParseRule<'c>; 39] {
-        return [
+    pub fn make_parse_rules<'c>() -> [ParseRule<'c>; PARSE_RULES_COUNT] {
+        [
             ParseRule {
                 parse_type: ParseType::Both {
                     prefix: Compiler::grouping,
@@ -1002,6 +1481,14 @@ impl<'a> Compiler<'a> {
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::RightParen
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::LeftBrace
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::RightBrace
+            ParseRule {
+                parse_type: ParseType::Both {
+                    prefix: Compiler::list,
+                    infix: Compiler::index
+                },
+                precedence: Precedence::Call
+            },                                                                       // TokenType::LeftBracket
+            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::RightBracket
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Comma
             ParseRule {
                 parse_type: ParseType::Infix(Compiler::dot),
@@ -1027,6 +1514,15 @@ impl<'a> Compiler<'a> {
                 parse_type: ParseType::Infix(Compiler::binary),
                 precedence: Precedence::Factor
             },                                                                       // TokenType::Star
+            ParseRule {
+                parse_type: ParseType::Infix(Compiler::binary),
+                precedence: Precedence::Factor
+            },                                                                       // TokenType::Percent
+            ParseRule {
+                parse_type: ParseType::Infix(Compiler::conditional),
+                precedence: Precedence::Conditional
+            },                                                                       // TokenType::Question
+            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Colon
             ParseRule {
                 parse_type: ParseType::Prefix(Compiler::unary),
                 precedence: Precedence::None
@@ -1056,6 +1552,10 @@ impl<'a> Compiler<'a> {
                 parse_type: ParseType::Infix(Compiler::binary),
                 precedence: Precedence:: Comparison
             }, // TokenType::LessEqual
+            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::PlusEqual
+            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::MinusEqual
+            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::StarEqual
+            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::SlashEqual
             ParseRule {
                 parse_type: ParseType::Prefix(Compiler::variable),
                 precedence: Precedence::None
@@ -1072,7 +1572,10 @@ impl<'a> Compiler<'a> {
                 parse_type: ParseType::Infix(Compiler::and_operator),
                 precedence: Precedence::And
             }, // TokenType::And
-            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Class
+            ParseRule {
+                parse_type: ParseType::Prefix(Compiler::class_expression),
+                precedence: Precedence::None
+            }, // TokenType::Class
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Else
             ParseRule {
                 parse_type: ParseType::Prefix(Compiler::literal),
@@ -1091,7 +1594,10 @@ impl<'a> Compiler<'a> {
             }, // TokenType::Or
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Print
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Return
-            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Super
+            ParseRule {
+                parse_type: ParseType::Prefix(Compiler::super_),
+                precedence: Precedence::None
+            }, // TokenType::Super
             ParseRule {
                 parse_type: ParseType::Prefix(Compiler::this),
                 precedence: Precedence::None
@@ -1102,8 +1608,10 @@ impl<'a> Compiler<'a> {
             }, // TokenType::True
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Var
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::While
+            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Continue
+            ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Break
             ParseRule { parse_type: ParseType::None, precedence: Precedence::None }, // TokenType::Eof
-        ];
+        ]
     }
 }
 
@@ -1112,24 +1620,30 @@ pub enum CompileError {
     TokenError {
         line: usize,
         lexeme: Option<Lexeme>,
-        message: &'static str,
+        message: String,
     }
 }
 
 impl CompileError {
-    pub fn make_from_token(token: &Token, message: &'static str) -> Self {
+    pub fn make_from_token(token: &Token, message: impl Into<String>) -> Self {
         CompileError::TokenError {
             line: token.line,
             lexeme: token.lexeme,
-            message
+            message: message.into(),
         }
     }
 }
 
-#[derive(Clone, Copy)]
 struct LoopContext {
     start_index: usize,
     locals_depth: u8,
+    break_jumps: Vec<usize>,
+}
+
+impl LoopContext {
+    fn new(start_index: usize, locals_depth: u8) -> Self {
+        Self { start_index, locals_depth, break_jumps: Vec::new() }
+    }
 }
 
 pub type CompilationResult = Result<(), CompileError>;
@@ -1156,3 +1670,225 @@ impl FunctionType {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Low enough for the nesting tests to fit in a test thread's stack.
+    const MAX_NESTING_DEPTH: usize = 32;
+
+    fn compile(source: &str) -> Option<std::vec::Vec<u8>> {
+        let parse_rules = Compiler::make_parse_rules();
+        let interned_strings = Rc::new(RefCell::new(HashTable::new()));
+        let mut context = CompilerContext::new(source, &parse_rules, interned_strings, false, FunctionType::Script);
+        context.set_max_nesting_depth(MAX_NESTING_DEPTH);
+        let mut compiler = Compiler::new(context);
+        compiler.compile().map(|chunk| chunk.codes.iter().copied().collect())
+    }
+
+    fn first_error(source: &str) -> Option<(usize, String)> {
+        let parse_rules = Compiler::make_parse_rules();
+        let interned_strings = Rc::new(RefCell::new(HashTable::new()));
+        let mut context = CompilerContext::new(source, &parse_rules, interned_strings, false, FunctionType::Script);
+        context.set_max_nesting_depth(MAX_NESTING_DEPTH);
+        let mut compiler = Compiler::new(context);
+        let mut result = compiler.advance();
+        while result.is_ok() && compiler.current_token().token_type != TokenType::Eof {
+            result = compiler.declaration();
+        }
+        match result {
+            Err(CompileError::TokenError { line, message, .. }) => Some((line, message)),
+            Err(CompileError::ScanError(error)) => Some((error.line, error.message.to_string())),
+            Ok(()) => None,
+        }
+    }
+
+    #[test]
+    fn compiled_chunk_verifies() {
+        let source = "fun f(n) { var a = 0; while (a < n) { a = a + 1; if (a == 2) continue; } \
+            fun g() { return a; } return g; } for (var i = 0; i < 3; i = i + 1) print f(i) and i or nil;";
+        let parse_rules = Compiler::make_parse_rules();
+        let interned_strings = Rc::new(RefCell::new(HashTable::new()));
+        let context = CompilerContext::new(source, &parse_rules, interned_strings, false, FunctionType::Script);
+        let mut compiler = Compiler::new(context);
+        assert!(compiler.compile().unwrap().verify().is_ok());
+    }
+
+    #[test]
+    fn literal_expression_statement_is_discarded() {
+        assert_eq!(compile("1;"), compile(""));
+        assert_eq!(compile("{ var a = 1; a; }"), compile("{ var a = 1; }"));
+    }
+
+    #[test]
+    fn call_expression_statement_is_kept() {
+        let codes = compile("f();").unwrap();
+        assert_eq!(
+            codes,
+            vec![OpCode::GetGlobal as u8, 0, OpCode::Call as u8, 0, OpCode::Pop as u8, OpCode::Nil as u8, OpCode::Return as u8]
+        );
+    }
+
+    #[test]
+    fn global_read_expression_statement_is_kept() {
+        let codes = compile("a;").unwrap();
+        assert_eq!(codes[codes.len() - 3], OpCode::Pop as u8);
+    }
+
+    #[test]
+    fn duplicate_method_name() {
+        assert!(compile("class A { foo() {} foo() {} }").is_none());
+        assert!(compile("class A { foo() {} bar() {} }").is_some());
+    }
+
+    #[test]
+    fn invalid_superclass_usage() {
+        assert!(compile("class A < A {}").is_none());
+        assert!(compile("print super.f;").is_none());
+        assert!(compile("class A { f() { return super.f(); } }").is_none());
+        assert!(compile("class A {} class B < A { f() { return super.f(); } }").is_some());
+        assert!(compile("{ class A {} class B < A {} }").is_some());
+    }
+
+    #[test]
+    fn break_outside_of_loop() {
+        assert!(compile("break;").is_none());
+        assert!(compile("while (true) { fun f() { break; } }").is_none());
+        assert!(compile("while (true) break;").is_some());
+    }
+
+    #[test]
+    fn read_local_in_its_own_initializer() {
+        let message = "Can't read local variable in its own initializer.".to_string();
+        assert_eq!(first_error("{\n  var a = a;\n}"), Some((2, message.clone())));
+        assert_eq!(first_error("fun f() {\n  var a = a;\n}"), Some((2, message.clone())));
+        assert_eq!(first_error("{\n  var a = 1;\n  {\n    var a = a;\n  }\n}"), Some((4, message)));
+        assert_eq!(first_error("{ var a = 1; { var b = a; print b; } }"), None);
+        assert_eq!(first_error("var a = a;"), None);
+    }
+
+    fn compile_line(source: &str) -> Option<std::vec::Vec<u8>> {
+        let parse_rules = Compiler::make_parse_rules();
+        let interned_strings = Rc::new(RefCell::new(HashTable::new()));
+        let mut context = CompilerContext::new(source, &parse_rules, interned_strings, false, FunctionType::Script);
+        context.set_implicit_semicolon(true);
+        let mut compiler = Compiler::new(context);
+        compiler.compile().map(|chunk| chunk.codes.iter().copied().collect())
+    }
+
+    #[test]
+    fn implicit_semicolon_at_end_of_line() {
+        assert!(compile("print 1").is_none());
+        assert_eq!(compile_line("print 1"), compile("print 1;"));
+        assert_eq!(compile_line("var a = 1; print a"), compile("var a = 1; print a;"));
+        assert!(compile_line("print 1 print 2").is_none());
+        assert!(compile_line("{ print 1 }").is_none());
+    }
+
+    #[test]
+    fn conditional_requires_colon() {
+        assert!(compile("print true ? 1 : 2;").is_some());
+        assert!(compile("print true ? 1;").is_none());
+        assert!(compile("print true ? 1 2;").is_none());
+    }
+
+    #[test]
+    fn duplicate_parameter_name() {
+        assert!(compile("fun f(a, a) {}").is_none());
+        assert!(compile("fun f(a, b) {}").is_some());
+        assert!(compile("var a; fun f(a) {}").is_some());
+    }
+
+    #[test]
+    fn deeply_nested_expression_is_an_error() {
+        let depth = 10_000;
+        assert!(compile(&format!("print {}1{};", "(".repeat(depth), ")".repeat(depth))).is_none());
+        assert!(compile(&format!("print {}1;", "-".repeat(depth))).is_none());
+        assert!(compile(&format!("print {}1;", "!".repeat(depth))).is_none());
+    }
+
+    #[test]
+    fn nesting_counts_class_expression_bodies() {
+        let nested = |depth: usize| format!("print {}1{};", "class { m() { return ".repeat(depth), "; } }".repeat(depth));
+        assert!(compile(&nested(MAX_NESTING_DEPTH / 2)).is_some());
+        let error = Some((1, "Expression nesting too deep.".to_string()));
+        assert_eq!(first_error(&nested(MAX_NESTING_DEPTH)), error);
+        assert_eq!(first_error(&nested(3000)), error);
+    }
+
+    #[test]
+    fn nesting_within_limit() {
+        let depth = MAX_NESTING_DEPTH - 1;
+        assert!(compile(&format!("print {}1{};", "(".repeat(depth), ")".repeat(depth))).is_some());
+        assert!(compile(&format!("print {}1;", "-".repeat(depth))).is_some());
+        assert!(compile(&format!("print {}1{};", "-(".repeat(depth), ")".repeat(depth))).is_some());
+        assert!(compile(&format!("print {}1{};", "1 + (".repeat(depth / 2), ")".repeat(depth / 2))).is_some());
+        assert!(compile(&format!("print {}1{};", "-(".repeat(depth + 2), ")".repeat(depth + 2))).is_none());
+    }
+
+    #[test]
+    fn number_literal() {
+        assert!(compile("print 1.5;").is_some());
+    }
+
+    #[test]
+    fn number_literal_with_separators() {
+        assert_eq!(compile("print 1_000;"), compile("print 1000;"));
+        assert_eq!(compile("print 1_0.2_5;"), compile("print 10.25;"));
+        let error = "Digit separator '_' must be placed between digits.".to_string();
+        assert_eq!(first_error("print 1__0;"), Some((1, error.clone())));
+        assert_eq!(first_error("print 1_;"), Some((1, error)));
+        assert_eq!(first_error("print _1;"), Some((1, "Unexpected character.".to_string())));
+    }
+
+    #[test]
+    fn compound_assignment_target() {
+        assert_eq!(first_error("var a; a += 1;"), None);
+        assert_eq!(first_error("var a; var b; a + b += 1;"), Some((1, "Invalid assignment target.".to_string())));
+        assert_eq!(first_error("1 -= 2;"), Some((1, "Invalid assignment target.".to_string())));
+    }
+
+    #[test]
+    fn local_functions_are_hoisted() {
+        assert!(compile("{ fun a() { return b(); } fun b() { return a(); } }").is_some());
+        assert!(compile("{ fun a() { { fun c() {} } } fun b() {} a(); b(); }").is_some());
+        let message = "Already a variable with this name in this scope.".to_string();
+        assert_eq!(first_error("{ fun f() {} fun f() {} }"), Some((1, message.clone())));
+        assert_eq!(first_error("{\n  var f = 1;\n  fun f() {}\n}"), Some((2, message)));
+    }
+
+    #[test]
+    fn scan_errors_are_compile_errors() {
+        assert!(compile("print \"unterminated;").is_none());
+        assert!(compile("print @ 1; print 2;").is_none());
+        assert_eq!(first_error("print @@ 1;"), Some((1, "Unexpected character.".to_string())));
+    }
+
+    #[test]
+    fn leading_dot_number_literal() {
+        assert_eq!(compile("print .5;"), compile("print 0.5;"));
+        assert_eq!(compile("print .2_5;"), compile("print 0.25;"));
+        assert_eq!(first_error("print a.b;"), None);
+        assert!(first_error("print a.5;").is_some());
+    }
+
+    #[test]
+    fn trailing_dot_number() {
+        assert_eq!(first_error("print 5.;"), Some((1, "Expect property name after '.'.".to_string())));
+        assert_eq!(first_error("print 5.foo;"), None);
+    }
+
+    #[test]
+    fn radix_number_literals() {
+        assert_eq!(compile("print 0xff;"), compile("print 255;"));
+        assert_eq!(compile("print 0b1010;"), compile("print 10;"));
+        assert_eq!(first_error("print 0x;"), Some((1, "Expected digits after number prefix.".to_string())));
+    }
+
+    #[test]
+    fn out_of_range_number_literal() {
+        let source = format!("print 1{};", "0".repeat(400));
+        assert!(compile(&source).is_none());
+    }
+}
@@ -18,6 +18,24 @@ impl ConstantPool {
         self.values.push(value);
     }
 
+    /// Index of an equal scalar constant already in the pool.
+    /// Strings are interned and objects are compared by identity, so only
+    /// numbers, booleans and nil are worth looking up.
+    pub fn find_scalar(&self, value: &Value) -> Option<usize> {
+        match value {
+            Value::Number(number) => self.values.iter().position(|existing| match existing {
+                Value::Number(existing) => existing.to_bits() == number.to_bits(),
+                _ => false,
+            }),
+            Value::Bool(_) | Value::Nil => self.values.iter().position(|existing| existing == value),
+            _ => None,
+        }
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+    }
+
     pub fn length(&self) -> usize {
         self.values.length
     }
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use super::value::object_function::ObjectFunction;
+
+/// Top-level functions compiled by earlier scripts of a session, keyed by a
+/// hash of their source text and first line. The line is part of the key so
+/// a reused chunk still reports runtime errors at the right place.
+#[derive(Default)]
+pub struct FunctionCache {
+    functions: HashMap<u64, CachedFunction>,
+    #[cfg(test)]
+    compiled_count: usize,
+}
+
+struct CachedFunction {
+    source: String,
+    line: usize,
+    function: Rc<ObjectFunction>,
+}
+
+impl FunctionCache {
+    fn key(source: &str, line: usize) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        line.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Compares the source of a hit, so a hash collision is a miss rather
+    /// than a different function.
+    pub fn find(&self, source: &str, line: usize) -> Option<Rc<ObjectFunction>> {
+        self.functions.get(&FunctionCache::key(source, line))
+            .filter(|cached| cached.line == line && cached.source == source)
+            .map(|cached| Rc::clone(&cached.function))
+    }
+
+    pub fn insert(&mut self, source: &str, line: usize, function: Rc<ObjectFunction>) {
+        #[cfg(test)]
+        {
+            self.compiled_count += 1;
+        }
+        let cached = CachedFunction { source: source.to_string(), line, function };
+        self.functions.insert(FunctionCache::key(source, line), cached);
+    }
+
+    /// Number of functions compiled rather than taken from the cache.
+    #[cfg(test)]
+    pub fn compiled_count(&self) -> usize {
+        self.compiled_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::chunk::Chunk;
+    use super::super::value::object_string::ObjectString;
+
+    fn function() -> Rc<ObjectFunction> {
+        let name = Rc::new(ObjectString::from_string("f".to_string()));
+        Rc::new(ObjectFunction::new(name, 0, 0, Chunk::new()))
+    }
+
+    #[test]
+    fn colliding_key_with_other_source_is_a_miss() {
+        let mut cache = FunctionCache::default();
+        let source = "f() { return 1; }";
+        cache.insert(source, 1, function());
+        assert!(cache.find(source, 1).is_some());
+        assert!(cache.find(source, 2).is_none());
+
+        let other = "f() { return 2; }";
+        let cached = cache.functions.remove(&FunctionCache::key(source, 1)).unwrap();
+        cache.functions.insert(FunctionCache::key(other, 1), cached);
+        assert!(cache.find(other, 1).is_none());
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher as _};
+use std::sync::OnceLock;
+
+/// Turns the bytes of a key into a hash.
+///
+/// `Hashable` implementations pick the hasher they use, so keys that need a
+/// different function (for example one that resists deliberate collisions)
+/// can switch without touching the table's probing code.
+pub trait Hasher {
+    fn hash_bytes(&self, bytes: &[u8]) -> usize;
+}
+
+const FNV_OFFSET_BASIS: usize = 0xcbf29ce484222325;
+const FNV_PRIME: usize = 0x100000001b3;
+
+/// 64-bit FNV-1a with an optional seed mixed into the offset basis.
+/// A seed of zero gives the standard FNV-1a hash.
+#[derive(Clone, Copy, Default)]
+pub struct Fnv1a {
+    seed: usize,
+}
+
+impl Fnv1a {
+    pub fn with_seed(seed: usize) -> Self {
+        Self { seed }
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn hash_bytes(&self, bytes: &[u8]) -> usize {
+        bytes.iter().fold(FNV_OFFSET_BASIS ^ self.seed, |acc, byte| {
+            (acc ^ (*byte as usize)).wrapping_mul(FNV_PRIME)
+        })
+    }
+}
+
+/// Seed used for string hashes for the lifetime of the process.
+///
+/// With a fixed hash a script can pick global and field names that all land
+/// in the same bucket and turn every table lookup into a linear probe.
+/// Choosing the seed at random on startup makes those names differ from run
+/// to run. Set `RLOX_HASH_SEED` to a number to get reproducible hashes.
+pub fn hash_seed() -> usize {
+    static SEED: OnceLock<usize> = OnceLock::new();
+    *SEED.get_or_init(|| {
+        std::env::var("RLOX_HASH_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(|| RandomState::new().build_hasher().finish() as usize)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fnv1a_test_vectors() {
+        assert_eq!(Fnv1a::default().hash_bytes(b""), 0xcbf29ce484222325);
+        assert_eq!(Fnv1a::default().hash_bytes(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(Fnv1a::default().hash_bytes(b"foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn seeded_hashes_are_reproducible() {
+        let hash = Fnv1a::with_seed(42).hash_bytes(b"name");
+        assert_eq!(Fnv1a::with_seed(42).hash_bytes(b"name"), hash);
+        assert_ne!(Fnv1a::with_seed(43).hash_bytes(b"name"), hash);
+        assert_eq!(hash_seed(), hash_seed());
+    }
+}
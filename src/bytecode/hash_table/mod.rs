@@ -1,12 +1,21 @@
+mod hasher;
 mod raw_table;
 
 use raw_table::{RawTable, Entry, EntryType};
 use std::cmp::PartialEq;
+use std::fmt::{Debug, Formatter};
 pub use raw_table::Hashable;
+pub use hasher::{hash_seed, Fnv1a, Hasher};
 
 pub struct HashTable<Key: Hashable + PartialEq, Value> {
+    // Occupied slots, including tombstones, used for the load factor.
     length: usize,
+    // Live entries only.
+    count: usize,
     buffer: RawTable<Key, Value>,
+    // Number of probe sequences started, so tests can check lookups are not repeated.
+    #[cfg(test)]
+    probes: std::cell::Cell<usize>,
 }
 
 impl<Key: Hashable + PartialEq, Value: Default> HashTable<Key, Value> {
@@ -14,36 +23,71 @@ impl<Key: Hashable + PartialEq, Value: Default> HashTable<Key, Value> {
         Self::default()
     }
 
+    /// Allocates enough slots to hold `count` entries without growing.
+    pub fn with_capacity(count: usize) -> Self {
+        let capacity = if count == 0 {
+            0
+        } else {
+            (count * 4).div_ceil(3).next_power_of_two()
+        };
+        Self {
+            length: 0,
+            count: 0,
+            buffer: RawTable::with_capacity(capacity),
+            #[cfg(test)]
+            probes: std::cell::Cell::new(0),
+        }
+    }
+
     pub fn insert(&mut self, key: Key, value: Value) {
-        self.grow_if_needed();
-        let mut index = key.hash() % self.buffer.capacity;
+        let (index, is_filled) = self.claim_slot(&key);
         unsafe {
-            let mut pointer = self.pointer().add(index);
-            let mut tombstone_index: Option<usize> = None;
-            loop {
-                let entry = pointer.as_ref().unwrap();
-                match &entry.entry_type {
-                    EntryType::Filled(entry_key) if &key == entry_key => { break; },
-                    EntryType::Empty => {
-                        if let Some(tombstone_index) = tombstone_index {
-                            pointer = self.pointer().add(tombstone_index);
-                        } else {
-                            self.length += 1;
-                        }
-                        break;
-                    }
-                    EntryType::Deleted if tombstone_index.is_none() => {
-                        tombstone_index = Some(index);
-                        index += 1;
-                        pointer = self.pointer().add(self.make_index(index));
-                    }
-                    _ => {
-                        index += 1;
-                        pointer = self.pointer().add(self.make_index(index));
+            let pointer = self.pointer().add(index);
+            if is_filled {
+                pointer.as_mut().unwrap().value = value;
+            } else {
+                pointer.write(Entry::new(key, value));
+            }
+        }
+    }
+
+    /// Returns the value stored under `key`, inserting `make_value()` first
+    /// if there is none. The slot is looked up once for both cases.
+    pub fn get_or_insert_with<F>(&mut self, key: Key, make_value: F) -> &mut Value where F: FnOnce() -> Value {
+        let (index, is_filled) = self.claim_slot(&key);
+        unsafe {
+            let pointer = self.pointer().add(index);
+            if !is_filled {
+                pointer.write(Entry::new(key, make_value()));
+            }
+            &mut pointer.as_mut().unwrap().value
+        }
+    }
+
+    /// Finds the slot holding `key` or the one it should be inserted into,
+    /// reusing the first tombstone on the way. Returns whether the slot is
+    /// already filled; otherwise the slot is counted as taken.
+    fn claim_slot(&mut self, key: &Key) -> (usize, bool) {
+        self.grow_if_needed();
+        #[cfg(test)]
+        self.probes.set(self.probes.get() + 1);
+        let mut index = self.make_index(key.hash());
+        let mut tombstone_index: Option<usize> = None;
+        loop {
+            let entry = unsafe { self.pointer().add(index).as_ref().unwrap() };
+            match &entry.entry_type {
+                EntryType::Filled(entry_key) if key == entry_key => return (index, true),
+                EntryType::Empty => {
+                    self.count += 1;
+                    if tombstone_index.is_none() {
+                        self.length += 1;
                     }
+                    return (tombstone_index.unwrap_or(index), false);
                 }
+                EntryType::Deleted if tombstone_index.is_none() => tombstone_index = Some(index),
+                _ => {}
             }
-            pointer.write(Entry::new(key, value));
+            index = self.make_index(index + 1);
         }
     }
 
@@ -57,40 +101,33 @@ impl<Key: Hashable + PartialEq, Value: Default> HashTable<Key, Value> {
             .map(|entry| &entry.value)
     }
 
-    pub fn remove(&mut self, key: &Key) -> Option<Value> {
-        if self.length == 0 {
-            return None;
+    pub fn find_mut(&mut self, key: &Key) -> Option<&mut Value> {
+        let index = self.find_index(key.hash(), |entry_key| entry_key == key)?;
+        unsafe {
+            Some(&mut self.pointer().add(index).as_mut().unwrap().value)
         }
-        let hash = key.hash();
-        let mut index = self.make_index(hash);
-        loop {
-            unsafe  {
-                let pointer = self.pointer().add(index);
-                let entry = pointer.as_ref().unwrap();
-                match &entry.entry_type {
-                    EntryType::Empty => { return None; }
-                    EntryType::Filled(entry_key) if entry_key == key => {
-                        let value = pointer.read().value;
-                        pointer.write(Entry::deleted());
-                        return Some(value);
-                    }
-                    _ => {
-                        index = self.make_index(index + 1);
-                    }
-                }
-            }
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Option<Value> {
+        let index = self.find_index(key.hash(), |entry_key| entry_key == key)?;
+        unsafe {
+            let pointer = self.pointer().add(index);
+            let value = pointer.read().value;
+            pointer.write(Entry::deleted());
+            self.count -= 1;
+            Some(value)
         }
     }
 
     pub fn clone_all(&self, destination: &mut Self) where Key: Clone, Value: Clone {
         assert_eq!(destination.length, 0);
-        unsafe {
-            for index in 0..self.buffer.capacity {
-                let entry = self.pointer().add(index).as_ref().unwrap();
-                if let EntryType::Filled(ref key) = entry.entry_type {
-                    destination.insert(key.clone(), entry.value.clone());
-                }
-            }
+        destination.merge_from(self);
+    }
+
+    /// Copies entries of `source` whose keys are missing here, so existing entries win.
+    pub fn merge_from(&mut self, source: &Self) where Key: Clone, Value: Clone {
+        for (key, value) in source.iter() {
+            self.get_or_insert_with(key.clone(), || value.clone());
         }
     }
 
@@ -100,28 +137,29 @@ impl<Key: Hashable + PartialEq, Value: Default> HashTable<Key, Value> {
         hash: usize,
         is_searched_key: F
     ) -> Option<&Entry<Key, Value>> where F: Fn(&Key) -> bool {
+        self.find_index(hash, is_searched_key)
+            .map(|index| unsafe { self.pointer().add(index).as_ref().unwrap() })
+    }
+
+    /// Probes every slot at most once, so a search ends even when no slot
+    /// is empty.
+    #[inline]
+    fn find_index<F>(&self, hash: usize, is_searched_key: F) -> Option<usize> where F: Fn(&Key) -> bool {
         if self.length == 0 {
             return None;
         }
+        #[cfg(test)]
+        self.probes.set(self.probes.get() + 1);
         let mut index = self.make_index(hash);
-        let initial_index = index;
-        loop {
-            unsafe {
-                let entry = self.pointer().add(index).as_ref().unwrap();
-                match &entry.entry_type {
-                    EntryType::Empty => { break None; }
-                    EntryType::Filled(entry_key) if is_searched_key(entry_key) => {
-                        break Some(entry);
-                    }
-                    _ => {
-                        index = self.make_index(index + 1);
-                    }
-                }
-                if index == initial_index {
-                    break None;
-                }
-            };
+        for _ in 0..self.buffer.capacity {
+            let entry = unsafe { self.pointer().add(index).as_ref().unwrap() };
+            match &entry.entry_type {
+                EntryType::Empty => return None,
+                EntryType::Filled(entry_key) if is_searched_key(entry_key) => return Some(index),
+                _ => index = self.make_index(index + 1),
+            }
         }
+        None
     }
 
     #[inline]
@@ -140,11 +178,50 @@ impl<Key: Hashable + PartialEq, Value: Default> HashTable<Key, Value> {
     }
 }
 
+impl<Key: Hashable + PartialEq, Value> HashTable<Key, Value> {
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Value)> + '_ {
+        let pointer = self.buffer.pointer.as_ptr();
+        (0..self.buffer.capacity).filter_map(move |index| {
+            let entry = unsafe { pointer.add(index).as_ref().unwrap() };
+            match &entry.entry_type {
+                EntryType::Filled(key) => Some((key, &entry.value)),
+                _ => None,
+            }
+        })
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Key> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> + '_ {
+        self.iter().map(|(_, value)| value)
+    }
+}
+
+/// Prints the live entries only, in slot order.
+impl<Key: Hashable + PartialEq + Debug, Value: Default + Debug> Debug for HashTable<Key, Value> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
 impl<Key: Hashable + PartialEq, Value: Default> Default for HashTable<Key, Value> {
     fn default() -> Self {
         Self {
             length: 0,
+            count: 0,
             buffer: RawTable::new(),
+            #[cfg(test)]
+            probes: std::cell::Cell::new(0),
         }
     }
 }
@@ -153,6 +230,7 @@ impl<Key: Hashable + PartialEq, Value: Default> Default for HashTable<Key, Value
 mod tests {
     use crate::bytecode::value::object_string::ObjectString;
     use crate::bytecode::value::Value;
+    use std::rc::Rc;
     use super::*;
 
     #[test]
@@ -162,11 +240,59 @@ mod tests {
         assert!(hash_map.contains(&"some_key".to_string()))
     }
 
+    #[test]
+    fn debug_prints_live_entries() {
+        let mut hash_map = HashTable::<String, String>::new();
+        assert_eq!(format!("{:?}", hash_map), "{}");
+        hash_map.insert("a".to_string(), "x".to_string());
+        hash_map.insert("b".to_string(), "y".to_string());
+        hash_map.remove(&"b".to_string());
+        assert_eq!(format!("{:?}", hash_map), "{\"a\": \"x\"}");
+    }
+
+    #[test]
+    fn search_in_full_table_terminates() {
+        let mut hash_map = HashTable::<String, Value>::with_capacity(3);
+        let capacity = hash_map.buffer.capacity;
+        for index in 0..capacity {
+            unsafe {
+                hash_map.pointer().add(index).write(Entry::new(format!("key_{}", index), Value::Nil));
+            }
+        }
+        hash_map.length = capacity;
+        hash_map.count = capacity;
+        assert!(hash_map.find(&"absent".to_string()).is_none());
+        assert!(hash_map.remove(&"absent".to_string()).is_none());
+        assert!(hash_map.remove(&"key_1".to_string()).is_some());
+        assert!(!hash_map.contains(&"key_1".to_string()));
+        assert!(hash_map.find(&"absent".to_string()).is_none());
+    }
+
+    #[test]
+    fn get_or_insert_with_probes_once() {
+        let mut globals = HashTable::<String, Value>::new();
+        let counter = "counter".to_string();
+        for _ in 0..3 {
+            let probes = globals.probes.get();
+            let value = globals.get_or_insert_with(counter.clone(), || Value::Number(0f32));
+            if let Value::Number(number) = value {
+                *number += 1f32;
+            }
+            assert_eq!(globals.probes.get(), probes + 1);
+        }
+        assert_eq!(globals.find(&counter), Some(&Value::Number(3f32)));
+        assert_eq!(globals.len(), 1);
+
+        globals.remove(&counter);
+        assert_eq!(globals.get_or_insert_with(counter.clone(), || Value::Nil), &Value::Nil);
+        assert_eq!(globals.len(), 1);
+    }
+
     #[test]
     fn find() {
         let mut hash_map = HashTable::<String, Value>::new();
         hash_map.insert("some_key".to_string(), Value::Bool(true));
-        let value = hash_map.find(&"some_key".to_string()).map(|v| v.clone());
+        let value = hash_map.find(&"some_key".to_string()).cloned();
         assert_eq!(value, Some(Value::Bool(true)));
     }
 
@@ -221,6 +347,142 @@ mod tests {
         assert!(hash_map.contains(&second_key));
     }
 
+    #[test]
+    fn find_mut() {
+        let mut hash_map = HashTable::<String, Value>::new();
+        let key = "counter".to_string();
+        hash_map.insert(key.clone(), Value::Number(1f32));
+        hash_map.insert("other".to_string(), Value::Nil);
+        if let Some(Value::Number(number)) = hash_map.find_mut(&key) {
+            *number += 1f32;
+        }
+        assert_eq!(hash_map.find(&key), Some(&Value::Number(2f32)));
+        assert_eq!(hash_map.len(), 2);
+        assert!(hash_map.find_mut(&"missing".to_string()).is_none());
+        hash_map.remove(&key);
+        assert!(hash_map.find_mut(&key).is_none());
+    }
+
+    #[test]
+    fn len() {
+        let mut hash_map = HashTable::<String, Value>::new();
+        assert!(hash_map.is_empty());
+        hash_map.insert("a".to_string(), Value::Nil);
+        hash_map.insert("b".to_string(), Value::Nil);
+        hash_map.insert("c".to_string(), Value::Nil);
+        hash_map.insert("c".to_string(), Value::Bool(true));
+        assert_eq!(hash_map.len(), 3);
+        hash_map.remove(&"b".to_string());
+        hash_map.remove(&"b".to_string());
+        assert_eq!(hash_map.len(), 2);
+        assert!(!hash_map.is_empty());
+        hash_map.insert("b".to_string(), Value::Nil);
+        assert_eq!(hash_map.len(), 3);
+        for index in 0..10 {
+            hash_map.insert(format!("key_{}", index), Value::Nil);
+        }
+        assert_eq!(hash_map.len(), 13);
+        assert_eq!(hash_map.iter().count(), 13);
+    }
+
+    #[test]
+    fn iter() {
+        let mut hash_map = HashTable::<String, Value>::new();
+        assert_eq!(hash_map.iter().count(), 0);
+        for index in 0..20 {
+            hash_map.insert(format!("key_{}", index), Value::Number(index as f32));
+        }
+        hash_map.insert("key_3".to_string(), Value::Nil);
+        hash_map.remove(&"key_7".to_string());
+
+        let mut pairs: std::vec::Vec<(String, Value)> = hash_map
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        pairs.sort_by_key(|(key, _)| key[4..].parse::<usize>().unwrap());
+        let mut expected: std::vec::Vec<(String, Value)> = (0..20)
+            .filter(|index| *index != 7)
+            .map(|index| (format!("key_{}", index), Value::Number(index as f32)))
+            .collect();
+        expected[3].1 = Value::Nil;
+        assert_eq!(pairs, expected);
+
+        assert_eq!(hash_map.keys().count(), 19);
+        assert!(hash_map.keys().all(|key| key != "key_7"));
+        assert_eq!(hash_map.values().filter(|value| **value == Value::Nil).count(), 1);
+    }
+
+    #[test]
+    fn merge_from_keeps_existing_entries() {
+        let mut parent = HashTable::<String, Value>::new();
+        parent.insert("shared".to_string(), Value::Number(1f32));
+        parent.insert("inherited".to_string(), Value::Number(2f32));
+        let mut child = HashTable::<String, Value>::new();
+        child.insert("shared".to_string(), Value::Bool(true));
+        child.insert("own".to_string(), Value::Nil);
+
+        child.merge_from(&parent);
+
+        assert_eq!(child.len(), 3);
+        assert_eq!(child.find(&"shared".to_string()), Some(&Value::Bool(true)));
+        assert_eq!(child.find(&"inherited".to_string()), Some(&Value::Number(2f32)));
+        assert_eq!(child.find(&"own".to_string()), Some(&Value::Nil));
+        assert_eq!(parent.len(), 2);
+        assert_eq!(parent.find(&"shared".to_string()), Some(&Value::Number(1f32)));
+    }
+
+    #[test]
+    fn interned_strings_table() {
+        let mut strings = HashTable::<Rc<ObjectString>, ()>::new();
+        let first = strings.find_string_or_insert_new("name".to_string());
+        let second = strings.find_string_or_insert_new("name".to_string());
+        assert!(Rc::ptr_eq(&first, &second));
+        strings.find_string_or_insert_new("other".to_string());
+        assert_eq!(strings.len(), 2);
+
+        strings.remove(&first);
+        let reinserted = strings.find_string_or_insert_new("name".to_string());
+        assert!(!Rc::ptr_eq(&first, &reinserted));
+        assert_eq!(strings.len(), 2);
+    }
+
+    #[test]
+    fn globals_table() {
+        let mut strings = HashTable::<Rc<ObjectString>, ()>::new();
+        let mut globals = HashTable::<Rc<ObjectString>, Value>::new();
+        let name = strings.find_string_or_insert_new("answer".to_string());
+        globals.insert(Rc::clone(&name), Value::Nil);
+        *globals.find_mut(&name).unwrap() = Value::Number(42f32);
+
+        let same_name = strings.find_string_or_insert_new("answer".to_string());
+        assert_eq!(globals.find(&same_name), Some(&Value::Number(42f32)));
+        assert_eq!(globals.remove(&same_name), Some(Value::Number(42f32)));
+        assert!(globals.is_empty());
+    }
+
+    #[test]
+    fn with_capacity() {
+        let mut hash_map = HashTable::<String, Value>::with_capacity(100);
+        let capacity = hash_map.buffer.capacity;
+        assert_eq!(capacity, 256);
+        for index in 0..70 {
+            hash_map.insert(format!("key_{}", index), Value::Number(index as f32));
+        }
+        assert_eq!(hash_map.buffer.capacity, capacity);
+        assert_eq!(hash_map.len(), 70);
+        for index in 0..70 {
+            assert_eq!(hash_map.find(&format!("key_{}", index)), Some(&Value::Number(index as f32)));
+        }
+        assert!(!hash_map.contains(&"key_70".to_string()));
+
+        let mut exact = HashTable::<String, Value>::with_capacity(3);
+        for index in 0..3 {
+            exact.insert(format!("key_{}", index), Value::Nil);
+        }
+        assert_eq!(exact.buffer.capacity, 4);
+        assert_eq!(HashTable::<String, Value>::with_capacity(0).buffer.capacity, 0);
+    }
+
     impl Hashable for String {
         fn hash(&self) -> usize {
             ObjectString::hash_string(self)
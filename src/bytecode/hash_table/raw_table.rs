@@ -12,7 +12,9 @@ pub trait Hashable {
     fn hash(&self) -> usize;
 }
 
+#[derive(Default)]
 pub enum EntryType<Key> {
+    #[default]
     Empty,
     Filled(Key),
     Deleted,
@@ -56,14 +58,21 @@ impl<Key: Hashable + PartialEq, Value: Default> RawTable<Key, Value> {
         }
     }
 
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut table = RawTable::new();
+        if capacity > 0 {
+            table.grow_to(capacity);
+        }
+        table
+    }
+
     pub fn grow(&mut self) -> usize {
-        let (new_capacity, new_layout) = if self.capacity == 0 {
-            (1, Layout::array::<Entry<Key, Value>>(1).unwrap())
-        } else {
-            let new_capacity = self.capacity * 2;
-            let new_layout = Layout::array::<Entry<Key, Value>>(new_capacity).unwrap();
-            (new_capacity, new_layout)
-        };
+        let new_capacity = if self.capacity == 0 { 1 } else { self.capacity * 2 };
+        self.grow_to(new_capacity)
+    }
+
+    fn grow_to(&mut self, new_capacity: usize) -> usize {
+        let new_layout = Layout::array::<Entry<Key, Value>>(new_capacity).unwrap();
         assert!(new_layout.size() <= isize::MAX as usize, "Allocation too large");
 
         let (new_pointer, filled_entries_count) = unsafe {
@@ -101,7 +110,6 @@ impl<Key: Hashable + PartialEq, Value: Default> RawTable<Key, Value> {
         assert!(new_capacity > self.capacity);
         let old_pointer = self.pointer.as_ptr();
         (0..self.capacity)
-            .into_iter()
             .map(|index| old_pointer.add(index).read())
             .fold(0, |acc, entry| {
                 match entry.entry_type.filled() {
@@ -142,12 +150,6 @@ impl<Key: PartialEq, Value> Drop for RawTable<Key, Value> {
     }
 }
 
-impl<Key> Default for EntryType<Key> {
-    fn default() -> Self {
-        Self::Empty
-    }
-}
-
 impl<Key: PartialEq, Value: Default> Default for Entry<Key, Value> {
     fn default() -> Self {
         Self {
@@ -0,0 +1,195 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::{Rc, Weak};
+use super::value::{ListElements, Value};
+use super::value::object_class::ObjectClass;
+use super::value::object_closure::ObjectClosure;
+use super::value::object_dictionary::{DictionaryEntries, DictionaryKey};
+use super::hash_table::HashTable;
+use super::value::object_instance::ObjectInstance;
+use super::value::object_upvalue::ObjectUpvalue;
+
+const INITIAL_COLLECTION_THRESHOLD: usize = 1024;
+const HEAP_GROW_FACTOR: usize = 2;
+
+/// Tracks the heap objects that can take part in reference cycles: instances,
+/// lists, dictionaries and upvalues. Every cycle between values passes through one of them,
+/// so once marking finds the ones unreachable from the roots, clearing their
+/// contents breaks the cycles and lets reference counting free the memory.
+pub struct Heap {
+    instances: Vec<Weak<RefCell<ObjectInstance>>>,
+    lists: Vec<Weak<RefCell<Vec<Value>>>>,
+    dictionaries: Vec<Weak<RefCell<HashTable<DictionaryKey, Value>>>>,
+    upvalues: Vec<Weak<RefCell<ObjectUpvalue>>>,
+    next_collection: usize,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            instances: Vec::new(),
+            lists: Vec::new(),
+            dictionaries: Vec::new(),
+            upvalues: Vec::new(),
+            next_collection: INITIAL_COLLECTION_THRESHOLD,
+        }
+    }
+
+    pub fn track_instance(&mut self, instance: &Rc<RefCell<ObjectInstance>>) {
+        self.instances.push(Rc::downgrade(instance));
+    }
+
+    pub fn track_list(&mut self, list: &ListElements) {
+        self.lists.push(Rc::downgrade(list));
+    }
+
+    pub fn track_dictionary(&mut self, dictionary: &DictionaryEntries) {
+        self.dictionaries.push(Rc::downgrade(dictionary));
+    }
+
+    pub fn track_upvalue(&mut self, upvalue: &Rc<RefCell<ObjectUpvalue>>) {
+        self.upvalues.push(Rc::downgrade(upvalue));
+    }
+
+    pub fn tracked_count(&self) -> usize {
+        self.instances.len() + self.lists.len() + self.dictionaries.len() + self.upvalues.len()
+    }
+
+    #[inline]
+    pub fn should_collect(&self) -> bool {
+        self.tracked_count() >= self.next_collection
+    }
+
+    /// Clears every tracked object that is not reachable from `roots` or
+    /// `upvalues` and forgets the ones that were freed. Returns how many
+    /// objects were cleared.
+    pub fn collect<'v, 'u, R, U>(&mut self, roots: R, upvalues: U) -> usize
+    where
+        R: IntoIterator<Item = &'v Value>,
+        U: IntoIterator<Item = &'u Rc<RefCell<ObjectUpvalue>>>,
+    {
+        let mut marker = Marker::default();
+        for root in roots {
+            marker.mark_value(root);
+        }
+        for upvalue in upvalues {
+            marker.mark_upvalue(upvalue);
+        }
+
+        let mut cleared = 0;
+        for instance in self.instances.iter().filter_map(Weak::upgrade) {
+            if !marker.instances.contains(&Rc::as_ptr(&instance)) {
+                instance.as_ref().borrow_mut().clear_properties();
+                cleared += 1;
+            }
+        }
+        for list in self.lists.iter().filter_map(Weak::upgrade) {
+            if !marker.lists.contains(&Rc::as_ptr(&list)) {
+                list.as_ref().borrow_mut().clear();
+                cleared += 1;
+            }
+        }
+        for dictionary in self.dictionaries.iter().filter_map(Weak::upgrade) {
+            if !marker.dictionaries.contains(&Rc::as_ptr(&dictionary)) {
+                // Entries are removed one by one since dropping a table does not drop them.
+                let keys: Vec<DictionaryKey> = dictionary.as_ref().borrow().keys().cloned().collect();
+                for key in keys {
+                    dictionary.as_ref().borrow_mut().remove(&key);
+                }
+                cleared += 1;
+            }
+        }
+        for upvalue in self.upvalues.iter().filter_map(Weak::upgrade) {
+            if !marker.upvalues.contains(&Rc::as_ptr(&upvalue)) {
+                upvalue.as_ref().borrow().set_value(Value::Nil);
+                cleared += 1;
+            }
+        }
+
+        self.instances.retain(|instance| instance.strong_count() > 0);
+        self.lists.retain(|list| list.strong_count() > 0);
+        self.dictionaries.retain(|dictionary| dictionary.strong_count() > 0);
+        self.upvalues.retain(|upvalue| upvalue.strong_count() > 0);
+        self.next_collection = (self.tracked_count() * HEAP_GROW_FACTOR).max(INITIAL_COLLECTION_THRESHOLD);
+        cleared
+    }
+}
+
+#[derive(Default)]
+struct Marker {
+    instances: HashSet<*const RefCell<ObjectInstance>>,
+    lists: HashSet<*const RefCell<Vec<Value>>>,
+    dictionaries: HashSet<*const RefCell<HashTable<DictionaryKey, Value>>>,
+    upvalues: HashSet<*const RefCell<ObjectUpvalue>>,
+    classes: HashSet<*const RefCell<ObjectClass>>,
+    closures: HashSet<*const ObjectClosure>,
+}
+
+impl Marker {
+    fn mark_value(&mut self, value: &Value) {
+        match value {
+            Value::Closure(closure) => self.mark_closure(closure),
+            Value::Class(class) => self.mark_class(class),
+            Value::Instance(instance) => self.mark_instance(instance),
+            Value::BoundMethod(method) => {
+                self.mark_instance(&method.receiver);
+                self.mark_closure(&method.method);
+            }
+            Value::List(list) => {
+                if self.lists.insert(Rc::as_ptr(list)) {
+                    for element in list.as_ref().borrow().iter() {
+                        self.mark_value(element);
+                    }
+                }
+            }
+            Value::Dictionary(dictionary) => {
+                if self.dictionaries.insert(Rc::as_ptr(dictionary)) {
+                    for value in dictionary.as_ref().borrow().values() {
+                        self.mark_value(value);
+                    }
+                }
+            }
+            Value::NativeFunction(native) => {
+                if let Some(receiver) = &native.receiver {
+                    self.mark_value(receiver);
+                }
+            }
+            Value::Number(_) | Value::Bool(_) | Value::Nil | Value::String(_) | Value::Function(_) => {}
+        }
+    }
+
+    fn mark_instance(&mut self, instance: &Rc<RefCell<ObjectInstance>>) {
+        if !self.instances.insert(Rc::as_ptr(instance)) {
+            return;
+        }
+        let instance = instance.as_ref().borrow();
+        self.mark_class(&instance.class);
+        for (_, value) in instance.fields() {
+            self.mark_value(value);
+        }
+    }
+
+    fn mark_class(&mut self, class: &Rc<RefCell<ObjectClass>>) {
+        if !self.classes.insert(Rc::as_ptr(class)) {
+            return;
+        }
+        for method in class.as_ref().borrow().methods() {
+            self.mark_closure(method);
+        }
+    }
+
+    fn mark_closure(&mut self, closure: &Rc<ObjectClosure>) {
+        if !self.closures.insert(Rc::as_ptr(closure)) {
+            return;
+        }
+        for upvalue in &closure.upvalues {
+            self.mark_upvalue(upvalue);
+        }
+    }
+
+    fn mark_upvalue(&mut self, upvalue: &Rc<RefCell<ObjectUpvalue>>) {
+        if self.upvalues.insert(Rc::as_ptr(upvalue)) {
+            self.mark_value(upvalue.as_ref().borrow().value());
+        }
+    }
+}
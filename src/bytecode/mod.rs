@@ -1,9 +1,14 @@
 use std::cell::RefCell;
+use std::io::{self, Write};
 use std::rc::Rc;
 use crate::bytecode::compiler::{Compiler, CompilerContext, FunctionType};
+use crate::bytecode::function_cache::FunctionCache;
 use crate::bytecode::hash_table::HashTable;
+use crate::bytecode::scanner::Scanner;
+use crate::bytecode::token::TokenType;
 use crate::bytecode::value::object_string::ObjectString;
 use crate::bytecode::virtual_machine::VirtualMachine;
+use crate::limits::DEFAULT_MAX_NESTING_DEPTH;
 
 mod chunk;
 mod op_code;
@@ -18,24 +23,162 @@ mod vec;
 mod scope;
 mod value;
 mod upvalue;
+mod native_functions;
+mod heap;
+mod function_cache;
 pub mod hash_table;
 
-pub fn run_interpreter(script: String) {
-    let interned_strings = Rc::new(
-        RefCell::new(HashTable::<Rc<ObjectString>, ()>::new())
-    );
-
-    let parse_rules = Compiler::make_parse_rules();
-    let compiler_context = CompilerContext::new(
-        &script,
-        &parse_rules,
-        Rc::clone(&interned_strings),
-        false,
-        FunctionType::Script,
-    );
-    let mut compiler = Compiler::new(compiler_context);
-    if let Some(chunk) = compiler.compile() {
-        let mut virtual_machine = VirtualMachine::new(Rc::clone(&interned_strings));
-        virtual_machine.interpret(chunk);
+pub use value::object_string;
+pub use crate::run_error::RunError;
+
+const INITIAL_INTERNED_STRINGS_CAPACITY: usize = 256;
+
+#[derive(Clone, Copy)]
+pub struct Options {
+    pub verify: bool,
+    pub frames_limit: Option<usize>,
+    pub repl: bool,
+    pub trace: bool,
+    pub max_nesting_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            verify: false,
+            frames_limit: None,
+            repl: false,
+            trace: false,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+}
+
+
+pub fn run_interpreter(script: String, options: Options) -> Result<(), RunError> {
+    Session::new(options).run(&script)
+}
+
+/// Compiles and runs scripts against one virtual machine, so globals
+/// defined by one script are visible to the next.
+pub struct Session {
+    interned_strings: Rc<RefCell<HashTable<Rc<ObjectString>, ()>>>,
+    /// Only a REPL runs the same functions again, so other sessions skip
+    /// the extra lookahead and hashing.
+    function_cache: Option<Rc<RefCell<FunctionCache>>>,
+    virtual_machine: VirtualMachine,
+    options: Options,
+}
+
+impl Session {
+    pub fn new(options: Options) -> Self {
+        Session::with_output(options, Box::new(io::stdout()))
+    }
+
+    pub fn with_output(options: Options, output: Box<dyn Write>) -> Self {
+        let interned_strings = Rc::new(
+            RefCell::new(HashTable::<Rc<ObjectString>, ()>::with_capacity(INITIAL_INTERNED_STRINGS_CAPACITY))
+        );
+        let mut virtual_machine = VirtualMachine::with_output(Rc::clone(&interned_strings), output);
+        if let Some(frames_limit) = options.frames_limit {
+            virtual_machine.set_frames_limit(frames_limit);
+        }
+        let function_cache = options.repl.then(|| Rc::new(RefCell::new(FunctionCache::default())));
+        Self { interned_strings, function_cache, virtual_machine, options }
+    }
+
+    pub fn run(&mut self, script: &str) -> Result<(), RunError> {
+        let parse_rules = Compiler::make_parse_rules();
+        let mut compiler_context = CompilerContext::new(
+            script,
+            &parse_rules,
+            Rc::clone(&self.interned_strings),
+            false,
+            FunctionType::Script,
+        );
+        compiler_context.set_implicit_semicolon(self.options.repl);
+        compiler_context.set_trace(self.options.trace);
+        compiler_context.set_max_nesting_depth(self.options.max_nesting_depth);
+        if let Some(function_cache) = &self.function_cache {
+            compiler_context.set_function_cache(Rc::clone(function_cache));
+        }
+        let mut compiler = Compiler::new(compiler_context);
+        let chunk = compiler.compile().ok_or(RunError::Compile)?;
+        if cfg!(debug_assertions) || self.options.verify {
+            if let Err(error) = chunk.verify() {
+                eprintln!("[offset {}] Verify error: {}", error.offset, error.message);
+                return Err(RunError::Compile);
+            }
+        }
+        self.virtual_machine.interpret(chunk).map_err(|_| RunError::Runtime)
+    }
+}
+
+pub fn dump_tokens(script: &str, output: &mut dyn Write) -> Result<(), RunError> {
+    let mut scanner = Scanner::new(script);
+    loop {
+        let token = scanner.scan_token().map_err(|error| {
+            eprintln!("[line {}] Error: {}", error.line, error.message);
+            RunError::Compile
+        })?;
+        let lexeme = token.lexeme.map(|lexeme| lexeme.make_slice(script)).unwrap_or_default();
+        writeln!(output, "{:?} {:?} {}", token.token_type, lexeme, token.line).expect("Failed to write to output");
+        if token.token_type == TokenType::Eof {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shared_output::SharedOutput;
+
+    #[test]
+    fn runaway_recursion_is_a_runtime_error() {
+        let result = run_interpreter("fun f() { f(); } f();".to_string(), Options::default());
+        assert_eq!(result.err().map(|error| error.exit_code()), Some(70));
+    }
+
+    #[test]
+    fn session_keeps_globals_between_scripts() {
+        let output = SharedOutput::default();
+        let options = Options { repl: true, ..Options::default() };
+        let mut session = Session::with_output(options, Box::new(output.clone()));
+        assert!(session.run("var x = 1;").is_ok());
+        assert!(session.run("print x;").is_ok());
+        assert!(session.run("print missing;").is_err());
+        assert!(session.run("{ var a = 2; print a + x; }").is_ok());
+        assert!(session.run("fun f() { var n = 0; fun g() { n = n + 1; return n; } return g; } var g = f();").is_ok());
+        assert!(session.run("print g(); print g()").is_ok());
+        assert_eq!(output.take_string(), "1\n3\n1\n2\n");
+    }
+
+    #[test]
+    fn session_reuses_unchanged_global_functions() {
+        let output = SharedOutput::default();
+        let options = Options { repl: true, ..Options::default() };
+        let mut session = Session::with_output(options, Box::new(output.clone()));
+        let compiled_count = |session: &Session| session.function_cache.as_ref().unwrap().borrow().compiled_count();
+        let script = "fun add(a, b) { fun inner() { return a; } return inner() + b; }\nfun one() { return 1; }\nprint add(one(), 2);";
+        assert!(session.run(script).is_ok());
+        assert_eq!(compiled_count(&session), 2);
+        assert!(session.run(script).is_ok());
+        assert_eq!(compiled_count(&session), 2);
+
+        let changed = "fun add(a, b) { fun inner() { return a; } return inner() + b; }\nfun one() { return 10; }\nprint add(one(), 2);";
+        assert!(session.run(changed).is_ok());
+        assert_eq!(compiled_count(&session), 3);
+        assert!(session.run("fun broken() { return nil + 1; }\nbroken();").is_err());
+        assert!(session.run("fun broken() { return nil + 1; }\nbroken();").is_err());
+        assert_eq!(compiled_count(&session), 4);
+        assert_eq!(output.take_string(), "3\n3\n12\n");
+        assert!(Session::new(Options::default()).function_cache.is_none());
+    }
+
+    #[test]
+    fn compile_error_exit_code() {
+        let result = run_interpreter("print ;".to_string(), Options::default());
+        assert_eq!(result.err().map(|error| error.exit_code()), Some(65));
     }
 }
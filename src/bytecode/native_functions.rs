@@ -0,0 +1,231 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::number::parse_number;
+use super::value::Value;
+use super::value::object_native_function::{NativeError, ObjectNativeFunction};
+use super::value::object_string::ObjectString;
+use super::virtual_machine::VirtualMachine;
+
+pub fn native_functions() -> [ObjectNativeFunction; 12] {
+    [
+        ObjectNativeFunction::new("clock", 0, clock),
+        ObjectNativeFunction::new("sqrt", 1, sqrt),
+        ObjectNativeFunction::new("floor", 1, floor),
+        ObjectNativeFunction::new("len", 1, len),
+        ObjectNativeFunction::new("str", 1, str),
+        ObjectNativeFunction::new("num", 1, num),
+        ObjectNativeFunction::new("inspect", 1, inspect),
+        ObjectNativeFunction::with_callbacks("sort", 1, sort).with_optional_parameters(1),
+        ObjectNativeFunction::with_callbacks("map", 2, map),
+        ObjectNativeFunction::with_callbacks("filter", 2, filter),
+        ObjectNativeFunction::with_callbacks("reduce", 3, reduce),
+        ObjectNativeFunction::with_callbacks("dict", 0, dict),
+    ]
+}
+
+/// Methods of lists. Each one gets the list as its first argument.
+pub fn list_methods() -> [ObjectNativeFunction; 3] {
+    [
+        ObjectNativeFunction::new("push", 1, list_push),
+        ObjectNativeFunction::new("pop", 0, list_pop),
+        ObjectNativeFunction::new("length", 0, list_length),
+    ]
+}
+
+fn clock(_arguments: &[Value]) -> Result<Value, String> {
+    let system_time = SystemTime::now();
+    let milliseconds = system_time.duration_since(UNIX_EPOCH).unwrap().as_millis();
+    Ok(Value::Number(milliseconds as f32))
+}
+
+fn sqrt(arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::Number(number) => Ok(Value::Number(number.sqrt())),
+        _ => Err("Argument of sqrt must be a number.".to_string())
+    }
+}
+
+fn floor(arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::Number(number) => Ok(Value::Number(number.floor())),
+        _ => Err("Argument of floor must be a number.".to_string())
+    }
+}
+
+fn len(arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::String(string) => Ok(Value::Number(string.char_count() as f32)),
+        _ => Err("Argument of len must be a string.".to_string())
+    }
+}
+
+fn str(arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::String(string) => Ok(Value::String(Rc::clone(string))),
+        value => Ok(Value::String(Rc::new(ObjectString::from_string(value.to_string()))))
+    }
+}
+
+fn num(arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::Number(number) => Ok(Value::Number(*number)),
+        Value::String(string) => parse_number(string.value.trim())
+            .map(Value::Number)
+            .map_err(|_| format!("Can't convert {:?} to a number.", string.value)),
+        _ => Err("Argument of num must be a string or a number.".to_string())
+    }
+}
+
+fn inspect(arguments: &[Value]) -> Result<Value, String> {
+    Ok(Value::String(Rc::new(ObjectString::from_string(arguments[0].inspect()))))
+}
+
+fn list_push(arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::List(elements) => {
+            elements.as_ref().borrow_mut().push(arguments[1].clone());
+            Ok(Value::Nil)
+        }
+        _ => Err("Only lists have a push method.".to_string())
+    }
+}
+
+fn list_pop(arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::List(elements) => elements.as_ref().borrow_mut().pop()
+            .ok_or_else(|| "Can't pop from an empty list.".to_string()),
+        _ => Err("Only lists have a pop method.".to_string())
+    }
+}
+
+fn list_length(arguments: &[Value]) -> Result<Value, String> {
+    match &arguments[0] {
+        Value::List(elements) => Ok(Value::Number(elements.as_ref().borrow().len() as f32)),
+        _ => Err("Only lists have a length method.".to_string())
+    }
+}
+
+/// Copies the elements of the list argument, rooting them because a
+/// callback may remove them from the list.
+fn list_argument(
+    virtual_machine: &mut VirtualMachine,
+    arguments: &[Value],
+    native_name: &str,
+) -> Result<Vec<Value>, NativeError> {
+    match &arguments[0] {
+        Value::List(list) => Ok(list.as_ref().borrow().iter()
+            .map(|element| virtual_machine.root(element.clone()))
+            .collect()),
+        _ => Err(NativeError::Message(format!("First argument of {} must be a list.", native_name))),
+    }
+}
+
+fn callback_argument<'a>(
+    virtual_machine: &VirtualMachine,
+    arguments: &'a [Value],
+    native_name: &str,
+    arity: u8,
+) -> Result<&'a Value, NativeError> {
+    let callback = &arguments[1];
+    if virtual_machine.can_call(callback, arity) {
+        Ok(callback)
+    } else {
+        let message = format!("Second argument of {} must be a function of {} arguments.", native_name, arity);
+        Err(NativeError::Message(message))
+    }
+}
+
+fn map(virtual_machine: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, NativeError> {
+    let elements = list_argument(virtual_machine, arguments, "map")?;
+    let callback = callback_argument(virtual_machine, arguments, "map", 1)?;
+    let mut mapped = Vec::with_capacity(elements.len());
+    for element in elements {
+        let result = virtual_machine.call_from_native(callback, &[element])?;
+        mapped.push(virtual_machine.root(result));
+    }
+    Ok(virtual_machine.make_list(mapped))
+}
+
+fn filter(virtual_machine: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, NativeError> {
+    let elements = list_argument(virtual_machine, arguments, "filter")?;
+    let callback = callback_argument(virtual_machine, arguments, "filter", 1)?;
+    let mut kept = Vec::new();
+    for element in elements {
+        let result = virtual_machine.call_from_native(callback, std::slice::from_ref(&element))?;
+        if !matches!(result, Value::Bool(false) | Value::Nil) {
+            kept.push(element);
+        }
+    }
+    Ok(virtual_machine.make_list(kept))
+}
+
+fn reduce(virtual_machine: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, NativeError> {
+    let elements = list_argument(virtual_machine, arguments, "reduce")?;
+    let callback = callback_argument(virtual_machine, arguments, "reduce", 2)?;
+    let mut accumulator = arguments[2].clone();
+    for element in elements {
+        let result = virtual_machine.call_from_native(callback, &[accumulator, element])?;
+        accumulator = virtual_machine.root(result);
+    }
+    Ok(accumulator)
+}
+
+fn dict(virtual_machine: &mut VirtualMachine, _arguments: &[Value]) -> Result<Value, NativeError> {
+    Ok(virtual_machine.make_dictionary())
+}
+
+/// Sorts a list in place and returns it. Without a comparator numbers and
+/// strings sort ascending, with NaN after every other number.
+fn sort(virtual_machine: &mut VirtualMachine, arguments: &[Value]) -> Result<Value, NativeError> {
+    let Value::List(list) = &arguments[0] else {
+        return Err(NativeError::Message("First argument of sort must be a list.".to_string()));
+    };
+    let elements = list_argument(virtual_machine, arguments, "sort")?;
+    let sorted = match arguments.get(1) {
+        Some(comparator) => merge_sort(elements, &mut |left, right| {
+            match virtual_machine.call_from_native(comparator, &[left.clone(), right.clone()])? {
+                Value::Number(number) => Ok(number.partial_cmp(&0.0).unwrap_or(Ordering::Equal)),
+                _ => Err(NativeError::Message("Comparator of sort must return a number.".to_string())),
+            }
+        })?,
+        None => merge_sort(elements, &mut compare_values)?,
+    };
+    *list.as_ref().borrow_mut() = sorted;
+    Ok(Value::List(Rc::clone(list)))
+}
+
+fn compare_values(left: &Value, right: &Value) -> Result<Ordering, NativeError> {
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => {
+            Ok(left.partial_cmp(right).unwrap_or_else(|| left.is_nan().cmp(&right.is_nan())))
+        }
+        (Value::String(left), Value::String(right)) => Ok(left.value.cmp(&right.value)),
+        _ => Err(NativeError::Message("sort can only compare numbers with numbers and strings with strings.".to_string())),
+    }
+}
+
+/// A stable merge sort that stops at the first failed comparison. Unlike
+/// `sort_by` it cannot panic when a Lox comparator is not a total order.
+fn merge_sort<F>(mut values: Vec<Value>, compare: &mut F) -> Result<Vec<Value>, NativeError>
+where F: FnMut(&Value, &Value) -> Result<Ordering, NativeError> {
+    if values.len() < 2 {
+        return Ok(values);
+    }
+    let right = values.split_off(values.len() / 2);
+    let left = merge_sort(values, compare)?;
+    let right = merge_sort(right, compare)?;
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(left_value), Some(right_value)) = (left.peek(), right.peek()) {
+        if compare(right_value, left_value)? == Ordering::Less {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::fmt::{self, Display, Formatter};
 
 #[derive(Copy, Clone, PartialEq)]
@@ -10,6 +11,7 @@ pub enum OpCode {
     Subtract,
     Multiply,
     Divide,
+    Modulo,
     True,
     False,
     Nil,
@@ -17,6 +19,8 @@ pub enum OpCode {
     Greater,
     Equal,
     Less,
+    GreaterEqual,
+    LessEqual,
     Print,
     Pop,
     DefineGlobal,
@@ -36,6 +40,13 @@ pub enum OpCode {
     CloseUpvalue,
     Class,
     Method,
+    Inherit,
+    GetSuper,
+    Invoke,
+    Dup,
+    BuildList,
+    IndexGet,
+    IndexSet,
 }
 
 impl OpCode {
@@ -43,14 +54,69 @@ impl OpCode {
         match self {
             OpCode::Constant | OpCode::DefineGlobal | OpCode::GetGlobal | OpCode::Closure
             | OpCode::SetGlobal | OpCode::SetLocal | OpCode::GetLocal | OpCode::Call | OpCode::Method
-            | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Class | OpCode::SetProperty | OpCode::GetProperty => 2,
-            OpCode::JumpIfFalse | OpCode::Loop | OpCode::Jump => 3,
+            | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Class | OpCode::SetProperty | OpCode::GetProperty
+            | OpCode::GetSuper | OpCode::BuildList => 2,
+            OpCode::JumpIfFalse | OpCode::Loop | OpCode::Jump | OpCode::Invoke => 3,
             OpCode::ConstantLong => 4,
             _ => 1
         }
     }
 }
 
+impl TryFrom<u8> for OpCode {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            value if value == OpCode::Return as u8 => Ok(OpCode::Return),
+            value if value == OpCode::Constant as u8 => Ok(OpCode::Constant),
+            value if value == OpCode::ConstantLong as u8 => Ok(OpCode::ConstantLong),
+            value if value == OpCode::Negate as u8 => Ok(OpCode::Negate),
+            value if value == OpCode::Add as u8 => Ok(OpCode::Add),
+            value if value == OpCode::Subtract as u8 => Ok(OpCode::Subtract),
+            value if value == OpCode::Multiply as u8 => Ok(OpCode::Multiply),
+            value if value == OpCode::Divide as u8 => Ok(OpCode::Divide),
+            value if value == OpCode::Modulo as u8 => Ok(OpCode::Modulo),
+            value if value == OpCode::True as u8 => Ok(OpCode::True),
+            value if value == OpCode::False as u8 => Ok(OpCode::False),
+            value if value == OpCode::Nil as u8 => Ok(OpCode::Nil),
+            value if value == OpCode::Not as u8 => Ok(OpCode::Not),
+            value if value == OpCode::Greater as u8 => Ok(OpCode::Greater),
+            value if value == OpCode::Equal as u8 => Ok(OpCode::Equal),
+            value if value == OpCode::Less as u8 => Ok(OpCode::Less),
+            value if value == OpCode::GreaterEqual as u8 => Ok(OpCode::GreaterEqual),
+            value if value == OpCode::LessEqual as u8 => Ok(OpCode::LessEqual),
+            value if value == OpCode::Print as u8 => Ok(OpCode::Print),
+            value if value == OpCode::Pop as u8 => Ok(OpCode::Pop),
+            value if value == OpCode::DefineGlobal as u8 => Ok(OpCode::DefineGlobal),
+            value if value == OpCode::GetGlobal as u8 => Ok(OpCode::GetGlobal),
+            value if value == OpCode::SetGlobal as u8 => Ok(OpCode::SetGlobal),
+            value if value == OpCode::GetLocal as u8 => Ok(OpCode::GetLocal),
+            value if value == OpCode::SetLocal as u8 => Ok(OpCode::SetLocal),
+            value if value == OpCode::GetProperty as u8 => Ok(OpCode::GetProperty),
+            value if value == OpCode::SetProperty as u8 => Ok(OpCode::SetProperty),
+            value if value == OpCode::GetUpvalue as u8 => Ok(OpCode::GetUpvalue),
+            value if value == OpCode::SetUpvalue as u8 => Ok(OpCode::SetUpvalue),
+            value if value == OpCode::JumpIfFalse as u8 => Ok(OpCode::JumpIfFalse),
+            value if value == OpCode::Jump as u8 => Ok(OpCode::Jump),
+            value if value == OpCode::Loop as u8 => Ok(OpCode::Loop),
+            value if value == OpCode::Call as u8 => Ok(OpCode::Call),
+            value if value == OpCode::Closure as u8 => Ok(OpCode::Closure),
+            value if value == OpCode::CloseUpvalue as u8 => Ok(OpCode::CloseUpvalue),
+            value if value == OpCode::Class as u8 => Ok(OpCode::Class),
+            value if value == OpCode::Method as u8 => Ok(OpCode::Method),
+            value if value == OpCode::Inherit as u8 => Ok(OpCode::Inherit),
+            value if value == OpCode::GetSuper as u8 => Ok(OpCode::GetSuper),
+            value if value == OpCode::Invoke as u8 => Ok(OpCode::Invoke),
+            value if value == OpCode::Dup as u8 => Ok(OpCode::Dup),
+            value if value == OpCode::BuildList as u8 => Ok(OpCode::BuildList),
+            value if value == OpCode::IndexGet as u8 => Ok(OpCode::IndexGet),
+            value if value == OpCode::IndexSet as u8 => Ok(OpCode::IndexSet),
+            _ => Err(())
+        }
+    }
+}
+
 impl Display for OpCode {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let representation = match self {
@@ -62,6 +128,7 @@ impl Display for OpCode {
             OpCode::Subtract => "OP_SUBTRACT",
             OpCode::Multiply => "OP_MULTIPLY",
             OpCode::Divide => "OP_DIVIDE",
+            OpCode::Modulo => "OP_MODULO",
             OpCode::True => "OP_TRUE",
             OpCode::False => "OP_FALSE",
             OpCode::Nil => "OP_NIL",
@@ -69,6 +136,8 @@ impl Display for OpCode {
             OpCode::Greater => "OP_GREATER",
             OpCode::Equal => "OP_EQUAL",
             OpCode::Less => "OP_LESS",
+            OpCode::GreaterEqual => "OP_GREATER_EQUAL",
+            OpCode::LessEqual => "OP_LESS_EQUAL",
             OpCode::Print => "OP_PRINT",
             OpCode::Pop => "OP_POP",
             OpCode::DefineGlobal => "OP_DEFINE_GLOBAL",
@@ -88,6 +157,13 @@ impl Display for OpCode {
             OpCode::CloseUpvalue => "OP_CLOSE_UPVALUE",
             OpCode::Class => "OP_CLASS",
             OpCode::Method => "OP_METHOD",
+            OpCode::Inherit => "OP_INHERIT",
+            OpCode::GetSuper => "OP_GET_SUPER",
+            OpCode::Invoke => "OP_INVOKE",
+            OpCode::Dup => "OP_DUP",
+            OpCode::BuildList => "OP_BUILD_LIST",
+            OpCode::IndexGet => "OP_INDEX_GET",
+            OpCode::IndexSet => "OP_INDEX_SET",
         };
         write!(f, "{:<16}", representation)
     }
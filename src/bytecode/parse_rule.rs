@@ -6,6 +6,7 @@ use std::cmp::{Ordering, PartialOrd, PartialEq};
 pub enum Precedence {
     None,
     Assignment,
+    Conditional,
     Or,
     And,
     Equality,
@@ -38,6 +39,7 @@ impl TryFrom<u8> for Precedence {
         match value {
             value if value == Precedence::None as u8 => Ok(Precedence::None),
             value if value == Precedence::Assignment as u8 => Ok(Precedence::Assignment),
+            value if value == Precedence::Conditional as u8 => Ok(Precedence::Conditional),
             value if value == Precedence::Or as u8 => Ok(Precedence::Or),
             value if value == Precedence::And as u8 => Ok(Precedence::And),
             value if value == Precedence::Equality as u8 => Ok(Precedence::Equality),
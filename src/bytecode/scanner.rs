@@ -2,6 +2,7 @@ use std::str::Chars;
 use peekmore::{PeekMore, PeekMoreIterator};
 use crate::bytecode::token::{Lexeme, TokenType};
 use super::token::Token;
+use crate::number::radix_of_prefix;
 
 pub struct Scanner<'a> {
     source_iter: PeekMoreIterator<Chars<'a>>,
@@ -25,11 +26,24 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    /// Returns a scanner positioned after the last scanned token, so tokens
+    /// can be looked ahead at without consuming them from this one.
+    pub fn lookahead(&self) -> Scanner<'a> {
+        Self {
+            source_iter: self.source[self.token_start_position..].chars().peekmore(),
+            source: self.source,
+            line: self.line,
+            token_start_position: self.token_start_position,
+        }
+    }
+
     pub fn scan_token(&mut self) -> Result<Token, ScanError> {
         self.token_start_position += self.skip_whitespaces();
         match self.source_iter.next() {
             Some(character) => {
-                let (token_type, length) = self.scan_token_type(&character)?;
+                let (token_type, length) = self.scan_token_type(&character).inspect_err(|_| {
+                    self.token_start_position += character.len_utf8();
+                })?;
                 let mut start_position = self.token_start_position;
                 if token_type == TokenType::String {
                     self.token_start_position += length + 2;
@@ -68,13 +82,23 @@ impl<'a> Scanner<'a> {
             ')' => Ok((TokenType::RightParen, 1)),
             '{' => Ok((TokenType::LeftBrace, 1)),
             '}' => Ok((TokenType::RightBrace, 1)),
+            '[' => Ok((TokenType::LeftBracket, 1)),
+            ']' => Ok((TokenType::RightBracket, 1)),
             ';' => Ok((TokenType::Semicolon, 1)),
             ',' => Ok((TokenType::Comma, 1)),
-            '.' => Ok((TokenType::Dot, 1)),
-            '-' => Ok((TokenType::Minus, 1)),
-            '+' => Ok((TokenType::Plus, 1)),
-            '/' => Ok((TokenType::Slash, 1)),
-            '*' => Ok((TokenType::Star, 1)),
+            '.' => match self.source_iter.peek() {
+                Some(character) if character.is_ascii_digit() => {
+                    Ok((TokenType::Number, 1 + self.consume_digits_of_radix(10)))
+                },
+                _ => Ok((TokenType::Dot, 1)),
+            },
+            '-' => Ok(self.match_token_type('=', || TokenType::MinusEqual, || TokenType::Minus)),
+            '+' => Ok(self.match_token_type('=', || TokenType::PlusEqual, || TokenType::Plus)),
+            '/' => Ok(self.match_token_type('=', || TokenType::SlashEqual, || TokenType::Slash)),
+            '*' => Ok(self.match_token_type('=', || TokenType::StarEqual, || TokenType::Star)),
+            '%' => Ok((TokenType::Percent, 1)),
+            '?' => Ok((TokenType::Question, 1)),
+            ':' => Ok((TokenType::Colon, 1)),
             '!' => Ok(self.match_token_type('=', || TokenType::BangEqual, || TokenType::Bang)),
             '=' => Ok(self.match_token_type('=', || TokenType::EqualEqual, || TokenType::Equal)),
             '<' => Ok(self.match_token_type('=', || TokenType::LessEqual, || TokenType::Less)),
@@ -84,9 +108,9 @@ impl<'a> Scanner<'a> {
                     .map(|size| (TokenType::String, size))
                     .ok_or_else(|| self.make_error("Unterminated string."))
             },
-            character if character.is_digit(10) => Ok((TokenType::Number, self.consume_number())),
+            character if character.is_ascii_digit() => Ok((TokenType::Number, self.consume_number(*character))),
             character if character.is_alphanumeric() => {
-                let length = self.consume_identifier();
+                let length = self.consume_identifier(*character);
                 let keyword = &self.source[self.token_start_position..self.token_start_position + length];
                 Ok((self.identifier_type(keyword), length))
             },
@@ -149,9 +173,9 @@ impl<'a> Scanner<'a> {
                 None => {
                     return skipped
                 },
-                _ => {
+                Some(character) => {
+                    skipped += character.len_utf8();
                     self.source_iter.next();
-                    skipped += 1;
                 }
             }
         }
@@ -171,25 +195,33 @@ impl<'a> Scanner<'a> {
                     self.line += 1;
                     length += 1;
                 },
-                _ => {
-                    length += 1;
+                Some(character) => {
+                    length += character.len_utf8();
                 }
             }
         }
     }
 
-    fn consume_number(&mut self) -> usize {
+    /// Matches the tree-walk scanner: a `.` is only consumed when a digit
+    /// follows it, so `5.` is the number `5` followed by a `Dot` token.
+    fn consume_number(&mut self, first_character: char) -> usize {
+        if first_character == '0' {
+            if let Some(radix) = self.source_iter.peek().and_then(|prefix| radix_of_prefix(*prefix)) {
+                self.source_iter.next();
+                return 2 + self.consume_digits_of_radix(radix);
+            }
+        }
         let mut length: usize = 1;
         loop {
             match self.source_iter.peek() {
-                Some(character) if character.is_digit(10) => {
+                Some(character) if character.is_ascii_digit() || *character == '_' => {
                     length += 1;
                     self.source_iter.next();
                 },
                 Some('.') => {
                     self.source_iter.advance_cursor();
                     if let Some(character) = self.source_iter.peek() {
-                        if character.is_digit(10) {
+                        if character.is_ascii_digit() {
                             length += 1;
                             self.source_iter.reset_cursor();
                             self.source_iter.next();
@@ -205,12 +237,25 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    fn consume_identifier(&mut self) -> usize {
-        let mut length: usize = 1;
+    fn consume_digits_of_radix(&mut self, radix: u32) -> usize {
+        let mut length: usize = 0;
+        while let Some(character) = self.source_iter.peek() {
+            if !character.is_digit(radix) && *character != '_' {
+                break;
+            }
+            length += 1;
+            self.source_iter.next();
+        }
+        length
+    }
+
+    /// Lexeme lengths are in bytes so they can slice the source directly.
+    fn consume_identifier(&mut self, first_character: char) -> usize {
+        let mut length = first_character.len_utf8();
         loop {
             match self.source_iter.peek() {
                 Some(character) if character.is_alphanumeric() => {
-                    length += 1;
+                    length += character.len_utf8();
                     self.source_iter.next();
                 },
                 _ => {
@@ -225,6 +270,7 @@ impl<'a> Scanner<'a> {
         let mut chars = keyword.chars();
         match chars.next().unwrap() {
             'a' => Scanner::check_keyword(&keyword[1..], "nd", TokenType::And),
+            'b' => Scanner::check_keyword(&keyword[1..], "reak", TokenType::Break),
             'c' if keyword.len() > 1 => {
                 match chars.next().unwrap() {
                     'l' => Scanner::check_keyword(&keyword[2..], "ass", TokenType::Class),
@@ -16,17 +16,19 @@ const NOT_INITIALIZED: Local = Local {
     },
     depth: 0,
     is_captured: false,
+    is_hoisted: false,
 };
 
 struct Local {
     token: Token,
     depth: u8,
     is_captured: bool,
+    is_hoisted: bool,
 }
 
 impl Local {
     fn new(token: Token) -> Self {
-        Self { token, depth: 0, is_captured: false }
+        Self { token, depth: 0, is_captured: false, is_hoisted: false }
     }
 }
 
@@ -53,7 +55,7 @@ impl Scope {
         self.upvalues.size()
     }
 
-    pub fn upvalues_iter(&self) -> UpvaluesRefIterator {
+    pub fn upvalues_iter(&self) -> UpvaluesRefIterator<'_> {
         (&self.upvalues).into_iter()
     }
 
@@ -85,12 +87,10 @@ impl Scope {
     }
 
     #[inline]
-    pub fn remove_to_scope(&mut self, scope_depth: u8) -> u8 {
-        let local_count = self.locals_iter()
+    pub fn locals_count_to_scope(&self, scope_depth: u8) -> u8 {
+        self.locals_iter()
             .take_while(|v| v.depth >= scope_depth)
-            .fold(0u8, |acc, _| acc + 1);
-        self.locals_count -= local_count;
-        local_count
+            .fold(0u8, |acc, _| acc + 1)
     }
 
     #[inline]
@@ -98,19 +98,19 @@ impl Scope {
         if self.locals_count == 0 {
             return Ok(None);
         }
-        let lexeme = token.lexeme.as_ref().unwrap().make_slice(source);
+        let lexeme = token.lexeme.as_ref().map(|lexeme| lexeme.make_slice(source));
         let end_index = self.locals_count - 1;
         for (index, local) in self.locals_iter().enumerate() {
             let stored_lexeme = match local.token.lexeme.as_ref() {
                 Some(lexeme) => lexeme.make_slice(source),
-                None if token.token_type == TokenType::This && local.token.token_type == TokenType::This => {
+                None if Scope::is_same_synthetic_local(token, &local.token) => {
                     return Ok(Some(end_index - index as u8));
                 },
                 None => {
                     continue;
                 }
             };
-            if stored_lexeme != lexeme {
+            if Some(stored_lexeme) != lexeme {
                 continue;
             }
             if local.depth == 0 {
@@ -162,21 +162,21 @@ impl Scope {
 
     #[inline]
     pub fn is_redeclaration(&self, token: &Token, source: &str) -> bool {
-        let lexeme = token.lexeme.as_ref().unwrap().make_slice(source);
+        let lexeme = token.lexeme.as_ref().map(|lexeme| lexeme.make_slice(source));
         for local in self.locals_iter() {
             if local.depth != 0 && local.depth < self.scope_depth {
                 return false;
             }
             let stored_lexeme = match local.token.lexeme.as_ref() {
                 Some(lexeme) => lexeme.make_slice(source),
-                None if token.token_type == TokenType::This && local.token.token_type == TokenType::This => {
+                None if Scope::is_same_synthetic_local(token, &local.token) => {
                     return true;
                 },
                 None => {
                     continue;
                 }
             };
-            if stored_lexeme == lexeme {
+            if Some(stored_lexeme) == lexeme {
                 return true;
             }
         }
@@ -201,8 +201,40 @@ impl Scope {
         local.depth = self.scope_depth;
     }
 
+    /// Reserves an initialized slot for a function declared later in the
+    /// current block, so functions declared before it can capture it.
+    pub fn add_hoisted_local(&mut self, token: Token) -> CompilationResult {
+        self.add_local(token)?;
+        self.mark_local_initialized();
+        self.locals[(self.locals_count - 1) as usize].is_hoisted = true;
+        Ok(())
+    }
+
+    /// Returns the slot reserved by `add_hoisted_local` for a function
+    /// declaration, so it is only claimed once.
+    pub fn take_hoisted_local(&mut self, token: &Token, source: &str) -> Option<u8> {
+        let lexeme = token.lexeme.as_ref().map(|lexeme| lexeme.make_slice(source));
+        let scope_depth = self.scope_depth;
+        let (index, local) = self.locals[0..self.locals_count as usize]
+            .iter_mut()
+            .enumerate()
+            .rev()
+            .take_while(|(_, local)| local.depth == scope_depth)
+            .find(|(_, local)| {
+                local.is_hoisted && local.token.lexeme.as_ref().map(|lexeme| lexeme.make_slice(source)) == lexeme
+            })?;
+        local.is_hoisted = false;
+        Some(index as u8)
+    }
+
+    #[inline]
+    fn is_same_synthetic_local(token: &Token, local_token: &Token) -> bool {
+        matches!(token.token_type, TokenType::This | TokenType::Super | TokenType::Class)
+            && token.token_type == local_token.token_type
+    }
+
     #[inline]
-    fn locals_iter(&self) -> Rev<Iter<Local>> {
+    fn locals_iter(&self) -> Rev<Iter<'_, Local>> {
         self.locals[0..self.locals_count as usize].iter().rev()
     }
 }
@@ -1,30 +1,32 @@
-use super::virtual_machine::FRAMES_SIZE;
 use super::value::Value;
 
-const U8_MAX: usize = u8::MAX as usize + 1;
-const STACK_SIZE: usize = FRAMES_SIZE * U8_MAX;
+pub const FRAME_SLOTS: usize = u8::MAX as usize + 1;
 const NOT_INITIALIZED: Value = Value::Nil;
 pub struct Stack {
-    buffer: [Value; STACK_SIZE],
+    buffer: Box<[Value]>,
     top_index: usize,
 }
 
+#[derive(Debug)]
+pub struct StackOverflow;
+
 impl Stack {
-    pub fn new() -> Self {
+    pub fn new(frames_limit: usize) -> Self {
         Self {
-            buffer: [NOT_INITIALIZED; STACK_SIZE],
+            buffer: vec![NOT_INITIALIZED; frames_limit * FRAME_SLOTS].into_boxed_slice(),
             top_index: 0,
         }
     }
 
     pub fn top_index(&self) -> usize { self.top_index }
 
-    pub fn push(&mut self, value: Value) {
-        if self.top_index == STACK_SIZE - 1 {
-            panic!("Stack overflow")
+    pub fn push(&mut self, value: Value) -> Result<(), StackOverflow> {
+        if self.top_index == self.buffer.len() {
+            Err(StackOverflow)
         } else {
             self.buffer[self.top_index] = value;
             self.top_index += 1;
+            Ok(())
         }
     }
 
@@ -37,18 +39,32 @@ impl Stack {
         }
     }
 
+    #[allow(dead_code)]
     pub fn reset(&mut self) {
         self.top_index = 0;
     }
 
     pub fn peek_end(&self, distance: usize) -> Option<&Value> {
-        if self.top_index == 0 || distance > self.top_index {
+        if distance >= self.top_index {
             None
         } else {
             Some(&self.buffer[self.top_index - 1 - distance])
         }
     }
 
+    pub fn peek_mut(&mut self, distance: usize) -> Option<&mut Value> {
+        if distance >= self.top_index {
+            None
+        } else {
+            Some(&mut self.buffer[self.top_index - 1 - distance])
+        }
+    }
+
+    #[inline]
+    pub fn last_values(&self, count: usize) -> &[Value] {
+        &self.buffer[self.top_index - count..self.top_index]
+    }
+
     #[inline]
     pub fn modify_last(&mut self, value: Value) {
         self.modify_at_index(self.top_index - 1, value);
@@ -74,6 +90,7 @@ impl Stack {
         &mut self.buffer[index]
     }
 
+    #[allow(dead_code)]
     pub fn print_debug_info(&self) {
         println!("Index {}, Result {:?}", self.top_index, &self.buffer[..self.top_index])
     }
@@ -23,6 +23,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -30,6 +32,9 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
+    Question,
+    Colon,
     // One or two character tokens.
     Bang,
     BangEqual,
@@ -39,6 +44,10 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
     // Literals.
     Identifier,
     String,
@@ -61,5 +70,6 @@ pub enum TokenType {
     Var,
     While,
     Continue,
+    Break,
     Eof
 }
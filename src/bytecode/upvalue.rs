@@ -121,8 +121,8 @@ impl PartialEq for Upvalues {
             false
         } else {
             self.into_iter()
-                .zip(other.into_iter())
-                .any(|(left, right)| left == right)
+                .zip(other)
+                .all(|(left, right)| left == right)
         }
     }
 }
@@ -169,4 +169,20 @@ mod tests {
             Upvalue { index: 0, is_local: true },
         ])
     }
+
+    #[test]
+    fn test_that_upvalues_equal_only_when_all_entries_match() {
+        let mut left = Upvalues::new();
+        left.push(0, true);
+        left.push(1, true);
+        let mut right = Upvalues::new();
+        right.push(0, true);
+        right.push(2, false);
+        assert!(left != right);
+
+        let mut same = Upvalues::new();
+        same.push(0, true);
+        same.push(1, true);
+        assert!(left == same);
+    }
 }
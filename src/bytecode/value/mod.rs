@@ -1,7 +1,10 @@
 use std::cell::RefCell;
-use std::fmt::{Debug, Formatter};
+use std::collections::HashSet;
+use std::fmt::{Debug, Display, Formatter};
 use std::cmp::PartialEq;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::number::format_number;
 
 pub mod object_function;
 pub mod object_string;
@@ -11,6 +14,7 @@ pub mod object_upvalue;
 pub mod object_class;
 pub mod object_instance;
 pub mod object_bound_method;
+pub mod object_dictionary;
 
 use object_function::ObjectFunction;
 use object_string::ObjectString;
@@ -19,11 +23,21 @@ use object_closure::ObjectClosure;
 use object_class::ObjectClass;
 use object_instance::ObjectInstance;
 use object_bound_method::ObjectBoundMethod;
+use object_dictionary::{DictionaryEntries, DisplayEntries};
 
-#[derive(Clone)]
+static NEXT_FUNCTION_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn next_function_id() -> usize {
+    NEXT_FUNCTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+pub type ListElements = Rc<RefCell<Vec<Value>>>;
+
+#[derive(Clone, Default)]
 pub enum Value {
     Number(f32),
     Bool(bool),
+    #[default]
     Nil,
     String(Rc<ObjectString>),
     Function(Rc<ObjectFunction>),
@@ -32,10 +46,8 @@ pub enum Value {
     Class(Rc<RefCell<ObjectClass>>),
     Instance(Rc<RefCell<ObjectInstance>>),
     BoundMethod(ObjectBoundMethod),
-}
-
-impl Default for Value {
-    fn default() -> Self { Self::Nil }
+    List(Rc<RefCell<Vec<Value>>>),
+    Dictionary(DictionaryEntries),
 }
 
 impl PartialEq for Value {
@@ -47,12 +59,8 @@ impl PartialEq for Value {
             (Value::String(left), Value::String(right)) => {
                 Rc::as_ptr(left) == Rc::as_ptr(right)
             }
-            (Value::Function(left), Value::Function(right)) => {
-                Rc::as_ptr(left) == Rc::as_ptr(right)
-            }
-            (Value::NativeFunction(left), Value::NativeFunction(right)) => {
-                *(left.function) == *(right.function)
-            }
+            (Value::Function(left), Value::Function(right)) => left == right,
+            (Value::NativeFunction(left), Value::NativeFunction(right)) => left == right,
             (Value::Closure(left), Value::Closure(right)) => {
                 Rc::as_ptr(left) == Rc::as_ptr(right)
             }
@@ -63,6 +71,8 @@ impl PartialEq for Value {
                 Rc::as_ptr(left) == Rc::as_ptr(right)
             }
             (Value::BoundMethod(left), Value::BoundMethod(right)) => left.eq(right),
+            (Value::List(left), Value::List(right)) => Rc::ptr_eq(left, right),
+            (Value::Dictionary(left), Value::Dictionary(right)) => Rc::ptr_eq(left, right),
             _ => false
         }
     }
@@ -73,9 +83,9 @@ impl Debug for Value {
         match self {
             Value::Bool(boolean) => write!(formatter, "{:5}", boolean),
             Value::Number(number) => write!(formatter, "{:5}", number),
-            Value::String(object) => object.fmt(formatter),
-            Value::Function(obj) => write!(formatter, "fn<{:?}>", obj.as_ref().name),
-            Value::NativeFunction(_) => write!(formatter, "<native fn>"),
+            Value::String(object) => write!(formatter, "{:?}", object.value),
+            Value::Function(obj) => obj.fmt(formatter),
+            Value::NativeFunction(native) => native.fmt(formatter),
             Value::Closure(obj) => obj.as_ref().function.fmt(formatter),
             Value::Class(class) => write!(formatter, "{:?}", class.as_ref().borrow().name),
             Value::Instance(instance) => {
@@ -83,6 +93,169 @@ impl Debug for Value {
             },
             Value::Nil => write!(formatter, "{:5}", "Nil"),
             Value::BoundMethod(method) => method.fmt(formatter),
+            Value::List(elements) => write!(formatter, "{:?}", elements.as_ref().borrow()),
+            Value::Dictionary(entries) => write!(formatter, "{:?}", entries.as_ref().borrow()),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Bool(boolean) => write!(formatter, "{}", boolean),
+            Value::Number(number) => write!(formatter, "{}", format_number(*number)),
+            Value::String(object) => write!(formatter, "{}", object.value),
+            Value::Function(obj) => write!(formatter, "<fn {}>", obj.name.value),
+            Value::NativeFunction(_) => write!(formatter, "<native fn>"),
+            Value::Closure(obj) => write!(formatter, "<fn {}>", obj.function.name.value),
+            Value::Class(class) => write!(formatter, "{}", class.as_ref().borrow().name.value),
+            Value::Instance(instance) => {
+                write!(formatter, "<{} instance>", instance.as_ref().borrow().class.as_ref().borrow().name.value)
+            },
+            Value::Nil => write!(formatter, "nil"),
+            Value::BoundMethod(method) => write!(formatter, "<fn {}>", method.method.function.name.value),
+            Value::List(elements) => {
+                write!(formatter, "[")?;
+                for (index, element) in elements.as_ref().borrow().iter().enumerate() {
+                    if index > 0 {
+                        write!(formatter, ", ")?;
+                    }
+                    write!(formatter, "{}", element)?;
+                }
+                write!(formatter, "]")
+            }
+            Value::Dictionary(entries) => write!(formatter, "{}", DisplayEntries(&entries.as_ref().borrow())),
+        }
+    }
+}
+
+impl Value {
+    /// Formats the value together with the fields of instances, recursing into field values.
+    /// An instance that is already being printed higher up is shown as `<cycle>`.
+    pub fn inspect(&self) -> String {
+        let mut output = String::new();
+        self.write_inspected(&mut output, &mut HashSet::new());
+        output
+    }
+
+    fn write_inspected(&self, output: &mut String, visited: &mut HashSet<*const RefCell<ObjectInstance>>) {
+        match self {
+            Value::String(object) => output.push_str(&format!("{:?}", object.value)),
+            Value::Instance(instance) => {
+                if !visited.insert(Rc::as_ptr(instance)) {
+                    output.push_str("<cycle>");
+                    return;
+                }
+                let instance_ref = instance.as_ref().borrow();
+                let mut fields: std::vec::Vec<_> = instance_ref.fields().collect();
+                fields.sort_by(|(left, _), (right, _)| left.value.cmp(&right.value));
+                output.push_str(&instance_ref.class.as_ref().borrow().name.value);
+                output.push_str(" {");
+                for (index, (name, value)) in fields.into_iter().enumerate() {
+                    output.push_str(if index == 0 { " " } else { ", " });
+                    output.push_str(&name.value);
+                    output.push_str(": ");
+                    value.write_inspected(output, visited);
+                }
+                output.push_str(" }");
+                visited.remove(&Rc::as_ptr(instance));
+            }
+            value => output.push_str(&value.to_string()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::chunk::Chunk;
+    use super::super::hash_table::HashTable;
+
+    fn make_function(name: &str, arity: u8) -> Rc<ObjectFunction> {
+        let name = Rc::new(ObjectString::from_string(name.to_string()));
+        Rc::new(ObjectFunction::new(name, arity, 0, Chunk::new()))
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Value::Number(3f32).to_string(), "3");
+        assert_eq!(Value::Number(-0.5f32).to_string(), "-0.5");
+        assert_eq!(Value::Bool(true).to_string(), "true");
+        assert_eq!(Value::Bool(false).to_string(), "false");
+        assert_eq!(Value::Nil.to_string(), "nil");
+        let string = ObjectString::from_string("hi".to_string());
+        assert_eq!(Value::String(Rc::new(string)).to_string(), "hi");
+    }
+
+    #[test]
+    fn debug_quotes_strings() {
+        let string = ObjectString::from_string("hi".to_string());
+        assert_eq!(format!("{:?}", Value::String(Rc::new(string))), "\"hi\"");
+    }
+
+    #[test]
+    fn display_class_and_instance() {
+        let name = Rc::new(ObjectString::from_string("Point".to_string()));
+        let class = Rc::new(RefCell::new(ObjectClass::new(name)));
+        let instance = ObjectInstance::new(Rc::clone(&class));
+        assert_eq!(Value::Class(class).to_string(), "Point");
+        assert_eq!(Value::Instance(Rc::new(RefCell::new(instance))).to_string(), "<Point instance>");
+    }
+
+    #[test]
+    fn inspect_self_referential_instance() {
+        let name = Rc::new(ObjectString::from_string("Node".to_string()));
+        let class = Rc::new(RefCell::new(ObjectClass::new(name)));
+        let instance = Rc::new(RefCell::new(ObjectInstance::new(class)));
+        let field = |name: &str| Rc::new(ObjectString::from_string(name.to_string()));
+        instance.as_ref().borrow_mut().set_property(field("self"), Value::Instance(Rc::clone(&instance)));
+        instance.as_ref().borrow_mut().set_property(field("name"), Value::String(field("a")));
+        assert_eq!(Value::Instance(instance).inspect(), "Node { name: \"a\", self: <cycle> }");
+    }
+
+    #[test]
+    fn functions_print_tersely_and_inspect_with_arity() {
+        let function = make_function("greet", 2);
+        let closure = ObjectClosure { function: Rc::clone(&function), upvalues: Vec::new() };
+        let closure = Value::Closure(Rc::new(closure));
+        assert_eq!(closure.to_string(), "<fn greet>");
+        assert_eq!(format!("{:?}", closure), "<fn greet/2>");
+
+        let function = Value::Function(function);
+        assert_eq!(function.to_string(), "<fn greet>");
+        assert_eq!(format!("{:?}", function), "<fn greet/2>");
+    }
+
+    #[test]
+    fn native_function_inspects_with_arity() {
+        let native = Value::NativeFunction(ObjectNativeFunction::new("clock", 0, |_| Ok(Value::Nil)));
+        assert_eq!(native.to_string(), "<native fn>");
+        assert_eq!(format!("{:?}", native), "<native fn clock/0>");
+    }
+
+    #[test]
+    fn native_functions_compare_by_identity() {
+        let make_native = || Value::NativeFunction(ObjectNativeFunction::new("clock", 0, |_| Ok(Value::Nil)));
+        let native = make_native();
+        assert_eq!(native, native.clone());
+        assert_ne!(native, make_native());
+    }
+
+    #[test]
+    fn functions_compare_by_id() {
+        let function = make_function("f", 0);
+        assert_eq!(Value::Function(Rc::clone(&function)), Value::Function(Rc::clone(&function)));
+        assert_ne!(Value::Function(function), Value::Function(make_function("f", 0)));
+    }
+
+    #[test]
+    fn function_as_table_key() {
+        let first = make_function("f", 0);
+        let second = make_function("f", 0);
+        let mut table = HashTable::new();
+        table.insert(Rc::clone(&first), 1u8);
+        table.insert(Rc::clone(&second), 2u8);
+        assert_eq!(table.find(&first), Some(&1));
+        assert_eq!(table.find(&second), Some(&2));
+    }
+}
@@ -7,18 +7,40 @@ use super::object_string::ObjectString;
 pub struct ObjectClass {
     pub name: Rc<ObjectString>,
     methods: HashMap<Rc<ObjectString>, Rc<ObjectClosure>>,
+    method_names: Vec<Rc<ObjectString>>,
 }
 
 impl ObjectClass {
     pub fn new(name: Rc<ObjectString>) -> Self {
         Self {
             name,
-            methods: HashMap::new()
+            methods: HashMap::new(),
+            method_names: Vec::new(),
         }
     }
 
     pub fn add_method(&mut self, name: Rc<ObjectString>, method: Rc<ObjectClosure>) {
-        self.methods.insert(name, method);
+        if self.methods.insert(Rc::clone(&name), method).is_none() {
+            self.method_names.push(name);
+        }
+    }
+
+    pub fn inherit(&mut self, superclass: &ObjectClass) {
+        for name in &superclass.method_names {
+            if !self.methods.contains_key(name) {
+                self.add_method(Rc::clone(name), Rc::clone(&superclass.methods[name]));
+            }
+        }
+    }
+
+    /// Method names in declaration order, inherited methods first.
+    #[cfg(test)]
+    pub fn method_names(&self) -> impl Iterator<Item = &Rc<ObjectString>> {
+        self.method_names.iter()
+    }
+
+    pub fn methods(&self) -> impl Iterator<Item = &Rc<ObjectClosure>> {
+        self.methods.values()
     }
 
     pub fn method(&self, name: &Rc<ObjectString>) -> Option<&Rc<ObjectClosure>> {
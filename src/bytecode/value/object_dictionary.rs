@@ -0,0 +1,87 @@
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use super::Value;
+use super::object_string::ObjectString;
+use super::super::hash_table::{hash_seed, Fnv1a, Hashable, HashTable, Hasher as _};
+
+pub type DictionaryEntries = Rc<RefCell<HashTable<DictionaryKey, Value>>>;
+
+/// Values that can be dictionary keys: the ones compared by content.
+#[derive(Clone, Debug)]
+pub enum DictionaryKey {
+    Number(f32),
+    Bool(bool),
+    Nil,
+    String(Rc<ObjectString>),
+}
+
+impl DictionaryKey {
+    /// NaN is rejected because it is not equal to itself, so a value stored
+    /// under it could never be found again.
+    pub fn from_value(value: &Value) -> Result<Self, &'static str> {
+        match value {
+            Value::Number(number) if number.is_nan() => Err("Dictionary key can't be NaN."),
+            // -0 equals 0, so both must hash alike.
+            Value::Number(number) => Ok(DictionaryKey::Number(if *number == 0f32 { 0f32 } else { *number })),
+            Value::Bool(boolean) => Ok(DictionaryKey::Bool(*boolean)),
+            Value::Nil => Ok(DictionaryKey::Nil),
+            Value::String(string) => Ok(DictionaryKey::String(Rc::clone(string))),
+            _ => Err("Dictionary keys must be numbers, strings, booleans or nil."),
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            DictionaryKey::Number(number) => Value::Number(*number),
+            DictionaryKey::Bool(boolean) => Value::Bool(*boolean),
+            DictionaryKey::Nil => Value::Nil,
+            DictionaryKey::String(string) => Value::String(Rc::clone(string)),
+        }
+    }
+}
+
+impl PartialEq for DictionaryKey {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (DictionaryKey::Number(left), DictionaryKey::Number(right)) => left == right,
+            (DictionaryKey::Bool(left), DictionaryKey::Bool(right)) => left == right,
+            (DictionaryKey::Nil, DictionaryKey::Nil) => true,
+            (DictionaryKey::String(left), DictionaryKey::String(right)) => left.value == right.value,
+            _ => false,
+        }
+    }
+}
+
+impl Hashable for DictionaryKey {
+    fn hash(&self) -> usize {
+        let hasher = Fnv1a::with_seed(hash_seed());
+        match self {
+            DictionaryKey::Number(number) => hasher.hash_bytes(&number.to_le_bytes()),
+            DictionaryKey::Bool(boolean) => hasher.hash_bytes(&[*boolean as u8]),
+            DictionaryKey::Nil => hasher.hash_bytes(&[]),
+            DictionaryKey::String(string) => string.hash(),
+        }
+    }
+}
+
+/// Formats dictionary entries ordered by key text, so the output does not
+/// depend on the hash seed.
+pub struct DisplayEntries<'a>(pub &'a HashTable<DictionaryKey, Value>);
+
+impl Display for DisplayEntries<'_> {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut entries: std::vec::Vec<_> = self.0.iter()
+            .map(|(key, value)| (key.to_value().to_string(), value))
+            .collect();
+        entries.sort_by(|(left, _), (right, _)| left.cmp(right));
+        write!(formatter, "{{")?;
+        for (index, (key, value)) in entries.into_iter().enumerate() {
+            if index > 0 {
+                write!(formatter, ", ")?;
+            }
+            write!(formatter, "{}: {}", key, value)?;
+        }
+        write!(formatter, "}}")
+    }
+}
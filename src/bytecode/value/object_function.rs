@@ -1,17 +1,44 @@
 use std::fmt::{Debug, Formatter};
 use std::rc::Rc;
 use super::super::chunk::Chunk;
+use super::super::hash_table::Hashable;
 use super::object_string::ObjectString;
+use super::next_function_id;
 
 pub struct ObjectFunction {
+    pub id: usize,
     pub name: Rc<ObjectString>,
     pub arity: u8,
     pub upvalue_count: u8,
     pub chunk: Chunk,
 }
 
+impl ObjectFunction {
+    pub fn new(name: Rc<ObjectString>, arity: u8, upvalue_count: u8, chunk: Chunk) -> Self {
+        Self {
+            id: next_function_id(),
+            name,
+            arity,
+            upvalue_count,
+            chunk,
+        }
+    }
+}
+
+impl PartialEq for ObjectFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Hashable for ObjectFunction {
+    fn hash(&self) -> usize {
+        self.id
+    }
+}
+
 impl Debug for ObjectFunction {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "fn<{:?}>", self.name)
+        write!(f, "<fn {}/{}>", self.name.value, self.arity)
     }
 }
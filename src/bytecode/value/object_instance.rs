@@ -23,4 +23,12 @@ impl ObjectInstance {
     pub fn set_property(&mut self, name: Rc<ObjectString>, value: Value) {
         self.fields.insert(name, value);
     }
+
+    pub fn fields(&self) -> impl Iterator<Item = (&Rc<ObjectString>, &Value)> {
+        self.fields.iter()
+    }
+
+    pub fn clear_properties(&mut self) {
+        self.fields.clear();
+    }
 }
@@ -1,6 +1,85 @@
-use super::Value;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+use super::super::hash_table::Hashable;
+use super::super::virtual_machine::VirtualMachine;
+use super::{next_function_id, Value};
+
+pub type NativeFn = dyn Fn(&mut VirtualMachine, &[Value]) -> Result<Value, NativeError>;
+
+pub enum NativeError {
+    /// Reported as a runtime error at the native call.
+    Message(String),
+    /// Raised by a Lox callback, which has already reported it.
+    Callback,
+}
 
 #[derive(Clone)]
 pub struct ObjectNativeFunction {
-    pub function: Box<fn() -> Value>
+    pub id: usize,
+    pub name: &'static str,
+    pub arity: u8,
+    pub max_arity: u8,
+    pub function: Rc<NativeFn>,
+    /// Value the native was bound to, passed ahead of the call arguments.
+    pub receiver: Option<Box<Value>>,
+}
+
+impl ObjectNativeFunction {
+    pub fn new<F>(name: &'static str, arity: u8, function: F) -> Self
+    where F: Fn(&[Value]) -> Result<Value, String> + 'static {
+        ObjectNativeFunction::with_callbacks(name, arity, move |_, arguments| {
+            function(arguments).map_err(NativeError::Message)
+        })
+    }
+
+    /// Creates a native that gets the virtual machine, so it can call the
+    /// Lox functions it is passed.
+    pub fn with_callbacks<F>(name: &'static str, arity: u8, function: F) -> Self
+    where F: Fn(&mut VirtualMachine, &[Value]) -> Result<Value, NativeError> + 'static {
+        Self {
+            id: next_function_id(),
+            name,
+            arity,
+            max_arity: arity,
+            function: Rc::new(function),
+            receiver: None,
+        }
+    }
+
+    /// Makes a method of `receiver` out of this native.
+    pub fn bind(&self, receiver: Value) -> Self {
+        Self {
+            id: next_function_id(),
+            receiver: Some(Box::new(receiver)),
+            ..self.clone()
+        }
+    }
+
+    /// Lets callers omit the last `count` parameters.
+    pub fn with_optional_parameters(mut self, count: u8) -> Self {
+        self.max_arity = self.arity + count;
+        self
+    }
+
+    pub fn accepts(&self, arguments_count: u8) -> bool {
+        (self.arity..=self.max_arity).contains(&arguments_count)
+    }
+}
+
+impl PartialEq for ObjectNativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Hashable for ObjectNativeFunction {
+    fn hash(&self) -> usize {
+        self.id
+    }
+}
+
+impl Debug for ObjectNativeFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}/{}>", self.name, self.arity)
+    }
 }
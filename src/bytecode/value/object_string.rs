@@ -1,11 +1,12 @@
 use std::fmt::{Debug, Formatter};
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
-use super::super::hash_table::{Hashable, HashTable};
+use super::super::hash_table::{hash_seed, Fnv1a, Hashable, HashTable, Hasher as _};
 
 pub const INIT_KEYWORD: &str = "init";
+pub const ANONYMOUS_CLASS_NAME: &str = "anonymous";
 
-#[derive(Clone, Hash, Eq)]
+#[derive(Clone, Eq)]
 pub struct ObjectString {
     pub value: String,
     hash: usize,
@@ -37,11 +38,16 @@ impl ObjectString {
         }
     }
 
+    pub fn char_count(&self) -> usize {
+        self.value.chars().count()
+    }
+
+    pub fn char_at(&self, index: usize) -> Option<char> {
+        self.value.chars().nth(index)
+    }
+
     pub fn hash_string(string: &str) -> usize {
-        let bytes = string.as_bytes();
-        bytes.iter().fold(0xcbf29ce484222325, |acc, byte| {
-            (acc ^ (*byte as usize)).wrapping_mul(0x100000001b3)
-        })
+        Fnv1a::with_seed(hash_seed()).hash_bytes(string.as_bytes())
     }
 }
 
@@ -67,6 +73,12 @@ impl PartialEq for ObjectString {
     }
 }
 
+impl Hash for ObjectString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&self.value, state);
+    }
+}
+
 impl HashTable<Rc<ObjectString>, ()> {
     pub fn find_string_or_insert_new(&mut self, string: String) -> Rc<ObjectString> {
         let hash = ObjectString::hash_string(&string);
@@ -91,6 +103,32 @@ mod tests {
 
     #[test]
     fn test() {
-        assert_eq!(ObjectString::hash_string("feedface"), 0x0a83c86fee952abc);
+        assert_eq!(Fnv1a::default().hash_bytes(b"feedface"), 0x0a83c86fee952abc);
+        assert_eq!(
+            ObjectString::hash_string("feedface"),
+            Fnv1a::with_seed(hash_seed()).hash_bytes(b"feedface")
+        );
+    }
+
+    #[test]
+    fn char_count_counts_code_points() {
+        assert_eq!(ObjectString::from_string("hello".to_string()).char_count(), 5);
+        assert_eq!(ObjectString::from_string("héllo".to_string()).char_count(), 5);
+        assert_eq!(ObjectString::from_string("日本".to_string()).char_count(), 2);
+        assert_eq!(ObjectString::from_string(String::new()).char_count(), 0);
+    }
+
+    #[test]
+    fn char_at_indexes_code_points() {
+        let string = ObjectString::from_string("héllo".to_string());
+        assert_eq!(string.char_at(1), Some('é'));
+        assert_eq!(string.char_at(2), Some('l'));
+        assert_eq!(string.char_at(5), None);
+        let round_trip: String = (0..string.char_count())
+            .filter_map(|index| string.char_at(index))
+            .map(|character| character as u32)
+            .filter_map(char::from_u32)
+            .collect();
+        assert_eq!(round_trip, string.value);
     }
 }
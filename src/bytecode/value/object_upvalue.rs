@@ -45,7 +45,7 @@ impl Eq for ObjectUpvalue {}
 
 impl PartialOrd for ObjectUpvalue {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.location.cmp(&other.location))
+        Some(self.cmp(other))
     }
 }
 
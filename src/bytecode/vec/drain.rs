@@ -0,0 +1,41 @@
+use std::marker::PhantomData;
+use super::Vec;
+use super::raw_val_iter::RawValIter;
+
+pub struct Drain<'a, Element> {
+    vec: PhantomData<&'a mut Vec<Element>>,
+    iter: RawValIter<Element>
+}
+
+impl<'a, Element> Iterator for Drain<'a, Element> {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a, Element> DoubleEndedIterator for Drain<'a, Element> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back()
+    }
+}
+
+impl<'a, Element> Drop for Drain<'a, Element> {
+    fn drop(&mut self) {
+        for _ in &mut *self {}
+    }
+}
+
+impl<Element> Vec<Element> {
+    pub fn drain(&mut self) -> Drain<'_, Element> {
+        unsafe {
+            let iter = RawValIter::new(self);
+            self.length = 0;
+            Drain { vec: PhantomData, iter }
+        }
+    }
+}
@@ -1,13 +1,18 @@
 mod raw_val_iter;
 mod raw_vec;
 mod raw_ref_iter;
+mod raw_mut_iter;
+#[cfg(test)]
+mod drain;
 
-use std::marker::PhantomData;
-use std::ops::{Deref, DerefMut};
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut, Index, IndexMut};
 use std::ptr;
 use std::{slice, mem};
+use std::slice::SliceIndex;
 use std::iter;
 use super::vec::raw_ref_iter::RawRefIter;
+use super::vec::raw_mut_iter::RawMutIter;
 use super::vec::raw_val_iter::RawValIter;
 use super::vec::raw_vec::RawVec;
 
@@ -24,6 +29,27 @@ impl<Element> Vec<Element> {
         }
     }
 
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buffer: RawVec::with_capacity(capacity),
+            length: 0,
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let required = self.length.checked_add(additional).expect("Capacity overflow");
+        if required > self.capacity() {
+            self.buffer.grow_to(required.max(self.capacity() * 2));
+        }
+    }
+
+    /// Releases the capacity not used by the current elements.
+    pub fn shrink_to_fit(&mut self) {
+        if self.capacity() > self.length {
+            self.buffer.shrink_to(self.length);
+        }
+    }
+
     pub fn push(&mut self, element: Element) {
         if self.capacity() == self.length {
             self.buffer.grow();
@@ -62,7 +88,7 @@ impl<Element> Vec<Element> {
     }
 
     pub fn remove(&mut self, index: usize) -> Element {
-        assert!(index <= self.length, "index out of bounds");
+        assert!(index < self.length, "index out of bounds");
         self.length -= 1;
         unsafe {
             let element = ptr::read(self.pointer().add(index));
@@ -75,7 +101,23 @@ impl<Element> Vec<Element> {
         }
     }
 
-    fn capacity(&self) -> usize {
+    pub fn get(&self, index: usize) -> Option<&Element> {
+        if index < self.length {
+            unsafe { Some(&*self.pointer().add(index)) }
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Element> {
+        if index < self.length {
+            unsafe { Some(&mut *self.pointer().add(index)) }
+        } else {
+            None
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
         self.buffer.capacity
     }
 
@@ -86,7 +128,23 @@ impl<Element> Vec<Element> {
 
 impl<Element> Drop for Vec<Element> {
     fn drop(&mut self) {
-        while let Some(_) = self.pop() {}
+        while self.pop().is_some() {}
+    }
+}
+
+impl<Element: Clone> Clone for Vec<Element> {
+    fn clone(&self) -> Self {
+        let mut vec = Vec::with_capacity(self.capacity());
+        for element in self {
+            vec.push(element.clone());
+        }
+        vec
+    }
+}
+
+impl<Element: Debug> Debug for Vec<Element> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&**self, f)
     }
 }
 
@@ -108,6 +166,20 @@ impl<Element> DerefMut for Vec<Element> {
     }
 }
 
+impl<Element, I: SliceIndex<[Element]>> Index<I> for Vec<Element> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        Index::index(&**self, index)
+    }
+}
+
+impl<Element, I: SliceIndex<[Element]>> IndexMut<I> for Vec<Element> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        IndexMut::index_mut(&mut **self, index)
+    }
+}
+
 pub struct IntoIter<Element> {
     _buffer: RawVec<Element>,
     iter: RawValIter<Element>,
@@ -156,45 +228,183 @@ impl<'a, Element> iter::IntoIterator for &'a Vec<Element> {
 
     fn into_iter(self) -> RawRefIter<'a, Element> {
         unsafe {
-            RawRefIter::new(&self)
+            RawRefIter::new(self)
         }
     }
 }
 
-pub struct Drain<'a, Element> {
-    vec: PhantomData<&'a mut Vec<Element>>,
-    iter: RawValIter<Element>
+impl<'a, Element> iter::IntoIterator for &'a mut Vec<Element> {
+    type Item = &'a mut Element;
+    type IntoIter = RawMutIter<'a, Element>;
+
+    fn into_iter(self) -> RawMutIter<'a, Element> {
+        self.iter_mut()
+    }
 }
 
-impl<'a, Element> Iterator for Drain<'a, Element> {
-    type Item = Element;
+impl<Element> Vec<Element> {
+    pub fn iter_mut(&mut self) -> RawMutIter<'_, Element> {
+        unsafe {
+            RawMutIter::new(self)
+        }
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_vec(elements: &[u8]) -> Vec<u8> {
+        let mut vec = Vec::new();
+        for element in elements {
+            vec.push(*element);
+        }
+        vec
     }
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
+
+    #[test]
+    fn get_checks_bounds() {
+        let mut vec = make_vec(&[1, 2, 3]);
+        assert_eq!(vec.get(0), Some(&1));
+        assert_eq!(vec.get(2), Some(&3));
+        assert_eq!(vec.get(3), None);
+        *vec.get_mut(1).unwrap() = 5;
+        assert_eq!(vec.get(1), Some(&5));
+        assert!(vec.get_mut(3).is_none());
+        assert_eq!(Vec::<u8>::new().get(0), None);
     }
-}
 
-impl<'a, Element> DoubleEndedIterator for Drain<'a, Element> {
-    fn next_back(&mut self) -> Option<Self::Item> {
-        self.iter.next_back()
+    #[test]
+    fn index_mut_assigns_in_place() {
+        let mut vec = make_vec(&[1, 2, 3]);
+        vec[0] = 7;
+        vec[2] += 1;
+        assert_eq!(&vec[..], &[7, 2, 4]);
+        assert_eq!(&vec[1..], &[2, 4]);
     }
-}
 
-impl<'a, Element> Drop for Drain<'a, Element> {
-    fn drop(&mut self) {
-        for _ in &mut *self {}
+    #[test]
+    fn iter_mut_updates_in_place() {
+        let mut vec = make_vec(&[1, 2, 3]);
+        for element in vec.iter_mut() {
+            *element *= 2;
+        }
+        assert_eq!(&vec[..], &[2, 4, 6]);
+        if let Some(last) = vec.iter_mut().next_back() {
+            *last = 0;
+        }
+        for element in &mut vec {
+            *element += 1;
+        }
+        assert_eq!(&vec[..], &[3, 5, 1]);
+        assert_eq!(Vec::<u8>::new().iter_mut().next(), None);
     }
-}
 
-impl<Element> Vec<Element> {
-    fn drain(&mut self) -> Drain<Element> {
-        unsafe {
-            let iter = RawValIter::new(&self);
-            self.length = 0;
-            Drain { vec: PhantomData, iter }
+    #[test]
+    fn with_capacity_preallocates() {
+        let mut vec = Vec::with_capacity(10);
+        assert!(vec.capacity() >= 10);
+        let capacity = vec.capacity();
+        let pointer = vec.pointer();
+        for element in 0..10u8 {
+            vec.push(element);
+        }
+        assert_eq!(vec.capacity(), capacity);
+        assert_eq!(vec.pointer(), pointer);
+        assert_eq!(Vec::<u8>::with_capacity(0).capacity(), 0);
+    }
+
+    #[test]
+    fn reserve_makes_room_for_additional_elements() {
+        let mut vec = make_vec(&[1, 2, 3]);
+        vec.reserve(100);
+        assert!(vec.capacity() >= 103);
+        let capacity = vec.capacity();
+        for element in 0..100u8 {
+            vec.push(element);
+        }
+        assert_eq!(vec.capacity(), capacity);
+        assert_eq!(&vec[..3], &[1, 2, 3]);
+        vec.reserve(0);
+        assert_eq!(vec.capacity(), capacity);
+    }
+
+    #[test]
+    fn shrink_to_fit_releases_unused_capacity() {
+        let mut vec = Vec::new();
+        for element in 0..100u8 {
+            vec.push(element);
+        }
+        assert!(vec.capacity() >= 100);
+        for _ in 0..90 {
+            vec.pop();
+        }
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(), 10);
+        assert_eq!(&vec[..], &(0..10u8).collect::<std::vec::Vec<_>>()[..]);
+        vec.push(10);
+        assert_eq!(vec[10], 10);
+
+        assert_eq!(vec.drain().count(), 11);
+        vec.shrink_to_fit();
+        assert_eq!(vec.capacity(), 0);
+        vec.push(1);
+        assert_eq!(&vec[..], &[1]);
+    }
+
+    #[test]
+    fn clone_is_independent() {
+        let vec = make_vec(&[1, 2, 3]);
+        let mut clone = vec.clone();
+        assert_eq!(clone.capacity(), vec.capacity());
+        assert_ne!(clone.pointer(), vec.pointer());
+        clone[0] = 7;
+        clone.push(4);
+        assert_eq!(&vec[..], &[1, 2, 3]);
+        assert_eq!(&clone[..], &[7, 2, 3, 4]);
+        assert_eq!(Vec::<u8>::new().clone().capacity(), 0);
+    }
+
+    #[test]
+    fn debug_prints_like_a_slice() {
+        assert_eq!(format!("{:?}", make_vec(&[1, 2, 3])), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", Vec::<u8>::new()), "[]");
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_bounds_panics() {
+        let vec = make_vec(&[1, 2, 3]);
+        let _ = vec[3];
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn remove_past_the_end_panics() {
+        let mut vec = make_vec(&[1, 2, 3]);
+        vec.remove(3);
+    }
+
+    #[test]
+    fn drop_runs_element_destructors() {
+        use std::cell::Cell;
+
+        struct DropCounter<'a>(&'a Cell<usize>);
+
+        impl Drop for DropCounter<'_> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let drops = Cell::new(0);
+        let mut vec = Vec::new();
+        for _ in 0..5 {
+            vec.push(DropCounter(&drops));
         }
+        drop(vec.pop());
+        assert_eq!(drops.get(), 1);
+        drop(vec);
+        assert_eq!(drops.get(), 5);
     }
 }
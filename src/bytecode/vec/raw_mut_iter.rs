@@ -0,0 +1,56 @@
+use std::marker::PhantomData;
+use std::mem;
+
+pub struct RawMutIter<'a, Element> {
+    start: *mut Element,
+    end: *mut Element,
+    _phantom_data: PhantomData<&'a mut Element>
+}
+
+impl<'a, Element> RawMutIter<'a, Element> {
+    pub unsafe fn new(slice: &mut [Element]) -> Self {
+        Self {
+            start: slice.as_mut_ptr(),
+            end: if slice.is_empty() {
+                slice.as_mut_ptr()
+            } else {
+                slice.as_mut_ptr().add(slice.len())
+            },
+            _phantom_data: PhantomData
+        }
+    }
+}
+
+impl<'a, Element> Iterator for RawMutIter<'a, Element> {
+    type Item = &'a mut Element;
+
+    fn next(&mut self) -> Option<&'a mut Element> {
+        if self.start == self.end {
+            None
+        } else {
+            unsafe {
+                let next = self.start.as_mut();
+                self.start = self.start.offset(1);
+                next
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let length = (self.end as usize - self.start as usize) / mem::size_of::<Element>();
+        (length, Some(length))
+    }
+}
+
+impl<'a, Element> DoubleEndedIterator for RawMutIter<'a, Element> {
+    fn next_back(&mut self) -> Option<&'a mut Element> {
+        if self.start == self.end {
+            None
+        } else {
+            unsafe {
+                self.end = self.end.offset(-1);
+                self.end.as_mut()
+            }
+        }
+    }
+}
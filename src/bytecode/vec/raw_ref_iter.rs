@@ -9,9 +9,9 @@ pub struct RawRefIter<'a, Element> {
 
 impl<'a, Element> RawRefIter<'a, Element> {
     pub unsafe fn new(slice: &[Element]) -> Self {
-        return Self {
+        Self {
             start: slice.as_ptr(),
-            end: if slice.len() == 0 {
+            end: if slice.is_empty() {
                 slice.as_ptr()
             } else {
                 slice.as_ptr().add(slice.len())
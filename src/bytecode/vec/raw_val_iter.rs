@@ -8,9 +8,9 @@ pub struct RawValIter<Element> {
 
 impl<Element> RawValIter<Element> {
     pub unsafe fn new(slice: &[Element]) -> Self {
-        return Self {
+        Self {
             start: slice.as_ptr(),
-            end: if slice.len() == 0 {
+            end: if slice.is_empty() {
                 slice.as_ptr()
             } else {
                 slice.as_ptr().add(slice.len())
@@ -17,14 +17,22 @@ impl<Element> RawVec<Element> {
         }
     }
 
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut raw_vec = RawVec::new();
+        if capacity > 0 {
+            raw_vec.grow_to(capacity);
+        }
+        raw_vec
+    }
+
     pub fn grow(&mut self) {
-        let (new_capacity, new_layout) = if self.capacity == 0 {
-            (1, Layout::array::<Element>(1).unwrap())
-        } else {
-            let new_capacity = self.capacity * 2;
-            let new_layout = Layout::array::<Element>(new_capacity).unwrap();
-            (new_capacity, new_layout)
-        };
+        let new_capacity = if self.capacity == 0 { 1 } else { self.capacity * 2 };
+        self.grow_to(new_capacity);
+    }
+
+    pub fn grow_to(&mut self, new_capacity: usize) {
+        debug_assert!(new_capacity > self.capacity);
+        let new_layout = Layout::array::<Element>(new_capacity).expect("Allocation too large");
         assert!(new_layout.size() <= isize::MAX as usize, "Allocation too large");
 
         let new_pointer = if self.capacity == 0 {
@@ -40,6 +48,31 @@ impl<Element> RawVec<Element> {
         };
         self.capacity = new_capacity;
     }
+
+    /// Reallocates to a smaller buffer, releasing it entirely when
+    /// `new_capacity` is zero. Elements past `new_capacity` must already
+    /// have been moved out.
+    pub fn shrink_to(&mut self, new_capacity: usize) {
+        debug_assert!(new_capacity <= self.capacity);
+        if new_capacity == self.capacity {
+            return;
+        }
+        let old_layout = Layout::array::<Element>(self.capacity).unwrap();
+        let old_pointer = self.pointer.as_ptr() as *mut u8;
+        if new_capacity == 0 {
+            unsafe { alloc::dealloc(old_pointer, old_layout) };
+            self.pointer = NonNull::dangling();
+            self.capacity = 0;
+            return;
+        }
+        let new_layout = Layout::array::<Element>(new_capacity).unwrap();
+        let new_pointer = unsafe { alloc::realloc(old_pointer, old_layout, new_layout.size()) };
+        self.pointer = match NonNull::new(new_pointer as *mut Element) {
+            Some(p) => p,
+            None => alloc::handle_alloc_error(new_layout)
+        };
+        self.capacity = new_capacity;
+    }
 }
 
 impl<Element> Drop for RawVec<Element> {
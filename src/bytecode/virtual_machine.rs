@@ -4,65 +4,142 @@ use super::stack::Stack;
 use super::op_code::OpCode;
 use super::chunk::Chunk;
 use super::hash_table::HashTable;
-use super::value::{Value, object_string::ObjectString};
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::ops::{Sub, Mul, Div};
+use super::value::{ListElements, Value, object_string::ObjectString};
+use std::ops::{Sub, Mul, Div, Rem};
 use std::rc::Rc;
 use std::slice::Iter;
 use std::collections::BinaryHeap;
+use std::io::{self, Write};
 use super::value::object_bound_method::ObjectBoundMethod;
 use super::value::object_instance::ObjectInstance;
 use super::value::object_class::ObjectClass;
 use super::value::object_closure::ObjectClosure;
-use super::value::object_native_function::ObjectNativeFunction;
 use super::value::object_upvalue::ObjectUpvalue;
+use super::value::object_dictionary::DictionaryKey;
+use super::value::object_native_function::{NativeError, ObjectNativeFunction};
+use super::native_functions::{list_methods, native_functions};
+use super::heap::Heap;
+
+pub const DEFAULT_FRAMES_LIMIT: usize = 64;
+const INITIAL_GLOBALS_CAPACITY: usize = 32;
 
-pub const FRAMES_SIZE: usize = 64;
 pub struct VirtualMachine {
     stack: Stack,
     interned_strings: Rc<RefCell<HashTable<Rc<ObjectString>, ()>>>,
     globals: HashTable<Rc<ObjectString>, Value>,
+    list_methods: HashTable<Rc<ObjectString>, Value>,
     frame_count: usize,
+    frames_limit: usize,
     open_upvalues: BinaryHeap<Rc<RefCell<ObjectUpvalue>>>,
+    active_closures: Vec<Rc<ObjectClosure>>,
+    heap: Heap,
+    // Arguments of running natives and the values they hold between
+    // callbacks, which the collector treats as roots.
+    native_roots: Vec<Value>,
     init_string: Rc<ObjectString>,
+    output: Box<dyn Write>,
+    error_output: Box<dyn Write>,
 }
 
 impl VirtualMachine {
+    #[allow(dead_code)]
     pub fn new(interned_strings: Rc<RefCell<HashTable<Rc<ObjectString>, ()>>>,) -> Self {
-        let mut globals = HashTable::new();
-        VirtualMachine::add_native_functions(&mut globals, &interned_strings);
+        VirtualMachine::with_output(interned_strings, Box::new(io::stdout()))
+    }
+
+    pub fn with_output(
+        interned_strings: Rc<RefCell<HashTable<Rc<ObjectString>, ()>>>,
+        output: Box<dyn Write>,
+    ) -> Self {
+        let mut globals = HashTable::with_capacity(INITIAL_GLOBALS_CAPACITY);
+        VirtualMachine::add_native_functions(&mut globals, native_functions(), &interned_strings);
+        let mut methods = HashTable::new();
+        VirtualMachine::add_native_functions(&mut methods, list_methods(), &interned_strings);
         Self {
-            stack: Stack::new(),
+            stack: Stack::new(DEFAULT_FRAMES_LIMIT),
             interned_strings,
             globals,
+            list_methods: methods,
             frame_count: 0,
+            frames_limit: DEFAULT_FRAMES_LIMIT,
             open_upvalues: BinaryHeap::new(),
+            active_closures: Vec::new(),
+            heap: Heap::new(),
+            native_roots: Vec::new(),
             init_string: Rc::new(ObjectString::init_string()),
+            output,
+            error_output: Box::new(io::stderr()),
         }
     }
 
-    pub fn interpret(&mut self, chunk: &Chunk) {
-        let upvalue = Vec::new();
-        if let Err(error) = self.handle_chunk(chunk, 0, &Vec::new(), &upvalue) {
-            eprintln!("[line {}] in script", chunk.line(error.0))
+    /// Redirects runtime error messages and stack traces, which go to
+    /// stderr by default.
+    #[cfg(test)]
+    pub fn set_error_output(&mut self, error_output: Box<dyn Write>) {
+        self.error_output = error_output;
+    }
+
+    /// Sets the maximum call depth, including the top-level script frame.
+    /// Must be called before `interpret` as it reallocates the value stack.
+    pub fn set_frames_limit(&mut self, frames_limit: usize) {
+        self.frames_limit = frames_limit;
+        self.stack = Stack::new(frames_limit);
+    }
+
+    /// Runs a top-level chunk. Can be called repeatedly: globals, interned
+    /// strings and closures created by earlier chunks stay available to
+    /// later ones. The stack is empty between top-level chunks.
+    pub fn interpret(&mut self, chunk: &Chunk) -> InterpretResult {
+        self.run_script(chunk).inspect_err(|error| {
+            writeln!(self.error_output, "[line {}] in script", chunk.line(error.0)).expect("Failed to write to error output")
+        })
+    }
+
+    fn run_script(&mut self, chunk: &Chunk) -> InterpretResult {
+        assert_eq!(self.stack.top_index(), 0, "The stack must be empty between top-level chunks.");
+        self.frame_count += 1;
+        let result = self.handle_chunk(chunk, 0, &Vec::new());
+        self.frame_count -= 1;
+        // A failed call leaves its frames behind, so close what they captured
+        // before dropping them along with the script's return value.
+        if result.is_err() && !self.open_upvalues.is_empty() {
+            self.close_upvalue(0);
         }
+        while self.stack.pop().is_some() {}
+        result
     }
 
-    fn add_native_functions(
-        globals: &mut HashTable<Rc<ObjectString>, Value>,
+    /// Clears instances, lists and upvalues that cannot be reached from the
+    /// stack, globals, running closures, open upvalues or values held by
+    /// running natives, breaking the reference cycles that reference
+    /// counting alone would leak.
+    pub fn collect_garbage(&mut self) -> usize {
+        let stack = self.stack.last_values(self.stack.top_index());
+        let closures = self.active_closures.iter().cloned().map(Value::Closure).collect::<Vec<Value>>();
+        let roots = stack.iter()
+            .chain(self.globals.values())
+            .chain(closures.iter())
+            .chain(self.native_roots.iter());
+        self.heap.collect(roots, self.open_upvalues.iter())
+    }
+
+    /// Keeps `value` reachable for the collector until the running native
+    /// returns. Natives root every value they hold across a callback.
+    pub fn root(&mut self, value: Value) -> Value {
+        self.native_roots.push(value.clone());
+        value
+    }
+
+    fn add_native_functions<I>(
+        table: &mut HashTable<Rc<ObjectString>, Value>,
+        functions: I,
         interned_strings: &Rc<RefCell<HashTable<Rc<ObjectString>, ()>>>
-    ) {
-        let string = ObjectString::from_string("clock".to_string());
-        let rc_string = Rc::new(string);
+    ) where I: IntoIterator<Item = ObjectNativeFunction> {
         let mut mut_interned_strings = interned_strings.as_ref().borrow_mut();
-        mut_interned_strings.insert(Rc::clone(&rc_string),());
-        globals.insert(Rc::clone(&rc_string), Value::NativeFunction(ObjectNativeFunction {
-            function: Box::new(|| {
-                let system_time = SystemTime::now();
-                let milliseconds = system_time.duration_since(UNIX_EPOCH).unwrap().as_millis();
-                Value::Number(milliseconds as f32)
-            })
-        }));
+        for native_function in functions {
+            let name = mut_interned_strings.find_string_or_insert_new(native_function.name.to_string());
+            table.insert(name, Value::NativeFunction(native_function));
+        }
     }
 
     fn handle_chunk(
@@ -70,15 +147,14 @@ impl VirtualMachine {
         chunk: &Chunk,
         slots_start: usize,
         upvalues: &[Rc<RefCell<ObjectUpvalue>>],
-        enclosing_upvalues: &[Rc<RefCell<ObjectUpvalue>>]
     ) -> InterpretResult {
-        self.frame_count += 1;
-        assert!(self.frame_count < FRAMES_SIZE);
-
         let mut iter = chunk.codes.iter();
         let mut offset: usize = 0;
         loop {
             if let Some(code) = iter.next() {
+                if self.heap.should_collect() {
+                    self.collect_garbage();
+                }
                 let op_code = Chunk::byte_to_op_code(*code);
                 let prev_offset = offset;
                 offset += op_code.code_size();
@@ -89,59 +165,76 @@ impl VirtualMachine {
                     },
                     OpCode::Constant => {
                         let constant = chunk.read_constant(&mut iter);
-                        self.stack.push(constant.clone());
+                        self.push(constant.clone(), prev_offset)?;
                     },
                     OpCode::ConstantLong => {
                         let constant = chunk.read_constant_long(&mut iter);
-                        self.stack.push(constant.clone());
+                        self.push(constant.clone(), prev_offset)?;
                     },
                     OpCode::Negate => self.apply_negate_operation(prev_offset)?,
                     OpCode::Add => self.apply_add_operation(prev_offset)?,
                     OpCode::Subtract => self.apply_binary_operation(Sub::sub, prev_offset)?,
                     OpCode::Multiply => self.apply_binary_operation(Mul::mul, prev_offset)?,
                     OpCode::Divide => self.apply_binary_operation(Div::div, prev_offset)?,
-                    OpCode::True => self.stack.push(Value::Bool(true)),
-                    OpCode::False => self.stack.push(Value::Bool(false)),
-                    OpCode::Nil => self.stack.push(Value::Nil),
+                    OpCode::Modulo => self.apply_binary_operation(Rem::rem, prev_offset)?,
+                    OpCode::True => self.push(Value::Bool(true), prev_offset)?,
+                    OpCode::False => self.push(Value::Bool(false), prev_offset)?,
+                    OpCode::Nil => self.push(Value::Nil, prev_offset)?,
                     OpCode::Not => self.apply_not_operation(),
-                    OpCode::Equal => self.apply_equal_operation(),
+                    OpCode::Equal => self.apply_equal_operation(prev_offset)?,
                     OpCode::Greater => self.apply_compare_operation(|a, b| a > b, prev_offset)?,
                     OpCode::Less => self.apply_compare_operation(|a, b| a < b, prev_offset)?,
-                    OpCode::Print => println!("{:?}", self.stack.pop().unwrap()),
+                    OpCode::GreaterEqual => self.apply_compare_operation(|a, b| a >= b, prev_offset)?,
+                    OpCode::LessEqual => self.apply_compare_operation(|a, b| a <= b, prev_offset)?,
+                    OpCode::Print => {
+                        let value = self.stack.pop().unwrap();
+                        writeln!(self.output, "{}", value).expect("Failed to write to output");
+                    }
                     OpCode::Pop => { self.stack.pop(); },
+                    OpCode::Dup => {
+                        let value = self.stack.peek_end(0).expect("Stack is empty").clone();
+                        self.push(value, prev_offset)?;
+                    },
                     OpCode::DefineGlobal => self.define_global_variable(chunk, &mut iter),
                     OpCode::GetGlobal => self.get_global_variable(chunk, &mut iter, prev_offset)?,
                     OpCode::SetGlobal => self.set_global_variable(chunk, &mut iter, prev_offset)?,
-                    OpCode::GetLocal => self.get_local_variable(&mut iter, slots_start),
+                    OpCode::GetLocal => self.get_local_variable(&mut iter, slots_start, prev_offset)?,
                     OpCode::SetLocal => self.set_local_variable(&mut iter, slots_start),
-                    OpCode::GetUpvalue => self.get_upvalue(&mut iter, &upvalues),
+                    OpCode::GetUpvalue => self.get_upvalue(&mut iter, upvalues, prev_offset)?,
                     OpCode::SetUpvalue => self.set_upvalue(&mut iter, upvalues),
                     OpCode::GetProperty => self.get_property(chunk, &mut iter, prev_offset)?,
                     OpCode::SetProperty => self.set_property(chunk, &mut iter, prev_offset)?,
-                    OpCode::JumpIfFalse => self.handle_jump_if_false(&mut iter, &mut offset),
+                    OpCode::BuildList => self.build_list(&mut iter, prev_offset)?,
+                    OpCode::IndexGet => self.index_get(prev_offset)?,
+                    OpCode::IndexSet => self.index_set(prev_offset)?,
+                    OpCode::JumpIfFalse => {
+                        self.handle_jump_if_false(chunk, &mut iter, &mut offset, prev_offset)?
+                    }
                     OpCode::Jump => {
                         let jump_offset = Chunk::read_condition_offset(&mut iter);
-                        if jump_offset > 0 {
-                            iter.nth(jump_offset - 1);
-                            offset += jump_offset;
-                        }
+                        self.jump_forward(chunk, &mut iter, &mut offset, jump_offset, prev_offset)?;
                     }
                     OpCode::Loop => {
                         let jump_offset = Chunk::read_condition_offset(&mut iter);
-                        iter = chunk.codes.iter();
-                        iter.nth(offset - jump_offset - 1);
-                        offset -= jump_offset;
+                        let target = offset.checked_sub(jump_offset).ok_or_else(|| {
+                            self.runtime_error("Jump target out of bounds.".to_string(), prev_offset)
+                        })?;
+                        iter = chunk.codes[target..].iter();
+                        offset = target;
                     }
-                    OpCode::Call => self.handle_call(&mut iter, prev_offset, &upvalues)?,
+                    OpCode::Call => self.handle_call(&mut iter, prev_offset)?,
+                    OpCode::Invoke => self.invoke(chunk, &mut iter, prev_offset)?,
                     OpCode::Closure => {
-                        self.read_closure(chunk, &mut offset, &mut iter, slots_start, enclosing_upvalues)
+                        self.read_closure(chunk, &mut offset, &mut iter, slots_start, upvalues, prev_offset)?
                     },
                     OpCode::CloseUpvalue => {
                         self.close_upvalue(self.stack.top_index());
                         self.stack.pop();
                     },
-                    OpCode::Class => self.read_class(chunk, &mut iter),
+                    OpCode::Class => self.read_class(chunk, &mut iter, prev_offset)?,
                     OpCode::Method => self.define_method(chunk, &mut iter),
+                    OpCode::Inherit => self.inherit(prev_offset)?,
+                    OpCode::GetSuper => self.get_super(chunk, &mut iter, prev_offset)?,
                 }
             } else {
                 break Ok(());
@@ -156,11 +249,10 @@ impl VirtualMachine {
     ) -> InterpretResult where F: FnOnce(f32, f32) -> f32 {
         match (self.stack.pop(), self.stack.pop()) {
             (Some(Value::Number(right)), Some(Value::Number(left))) => {
-                self.stack.push(Value::Number(operation(left, right)));
-                Ok(())
+                self.push(Value::Number(operation(left, right)), offset)
             }
             _ => {
-                Err(VirtualMachine::runtime_error("Operands must be numbers.".to_string(), offset))
+                Err(self.runtime_error("Operands must be numbers.".to_string(), offset))
             }
         }
     }
@@ -168,18 +260,42 @@ impl VirtualMachine {
     fn apply_add_operation(&mut self, offset: usize) -> InterpretResult {
         match (self.stack.pop().unwrap(), self.stack.pop().unwrap()) {
             (Value::Number(right), Value::Number(left)) => {
-                self.stack.push(Value::Number(left + right));
-                Ok(())
+                self.push(Value::Number(left + right), offset)
             }
             (Value::String(right), Value::String(left)) => {
-                let string = left.as_ref().value.clone() + right.as_ref().value.as_str();
+                let string = VirtualMachine::concatenate(left, &right);
                 let mut strings = self.interned_strings.as_ref().borrow_mut();
                 let object = strings.find_string_or_insert_new(string);
-                self.stack.push(Value::String(object));
-                Ok(())
+                drop(strings);
+                self.push(Value::String(object), offset)
             }
             _ => {
-                Err(VirtualMachine::runtime_error("Operands must be two numbers or two strings.".to_string(), offset))
+                Err(self.runtime_error("Operands must be two numbers or two strings.".to_string(), offset))
+            }
+        }
+    }
+
+    fn intern_string(&self, string: Rc<ObjectString>) -> Rc<ObjectString> {
+        let value = Rc::try_unwrap(string)
+            .map(|string| string.value)
+            .unwrap_or_else(|string| string.value.clone());
+        self.interned_strings.as_ref().borrow_mut().find_string_or_insert_new(value)
+    }
+
+    fn concatenate(left: Rc<ObjectString>, right: &ObjectString) -> String {
+        // Interned strings are always referenced by the interned table,
+        // so only a string nobody else holds can be extended in place.
+        match Rc::try_unwrap(left) {
+            Ok(object) => {
+                let mut string = object.value;
+                string.push_str(&right.value);
+                string
+            }
+            Err(left) => {
+                let mut string = String::with_capacity(left.value.len() + right.value.len());
+                string.push_str(&left.value);
+                string.push_str(&right.value);
+                string
             }
         }
     }
@@ -191,23 +307,20 @@ impl VirtualMachine {
     ) -> InterpretResult where F: FnOnce(f32, f32) -> bool {
         match (self.stack.pop().unwrap(), self.stack.pop().unwrap()) {
             (Value::Number(right), Value::Number(left)) => {
-                self.stack.push(Value::Bool(operation(left, right)));
-                Ok(())
+                self.push(Value::Bool(operation(left, right)), offset)
             }
             _ => {
-                Err(VirtualMachine::runtime_error("Operands must be numbers.".to_string(), offset))
+                Err(self.runtime_error("Operands must be numbers.".to_string(), offset))
             }
         }
     }
 
     fn apply_negate_operation(&mut self, offset: usize) -> InterpretResult {
-        let top_value = self.stack.peek_end(0).unwrap();
-        if let Value::Number(number) = top_value {
-            let new_number = -(*number);
-            self.stack.modify_last(Value::Number(new_number));
+        if let Some(Value::Number(number)) = self.stack.peek_mut(0) {
+            *number = -*number;
             Ok(())
         } else {
-            Err(VirtualMachine::runtime_error("Operand must be a number.".to_string(), offset))
+            Err(self.runtime_error("Operand must be a number.".to_string(), offset))
         }
     }
 
@@ -223,10 +336,10 @@ impl VirtualMachine {
         }
     }
 
-    fn apply_equal_operation(&mut self) {
+    fn apply_equal_operation(&mut self, offset: usize) -> InterpretResult {
         let left = self.stack.pop().unwrap();
         let right = self.stack.pop().unwrap();
-        self.stack.push(Value::Bool(left == right));
+        self.push(Value::Bool(left == right), offset)
     }
 
     #[inline]
@@ -250,12 +363,12 @@ impl VirtualMachine {
         if let Value::String(object) = chunk.read_constant(iter) {
             match self.globals.find(object) {
                 Some(variable) => {
-                    self.stack.push(variable.clone());
-                    Ok(())
+                    let variable = variable.clone();
+                    self.push(variable, offset)
                 }
                 None => {
                     let variable = &object.as_ref().value;
-                    Err(VirtualMachine::runtime_error(format!("Undefined variable {:?}", variable), offset))
+                    Err(self.runtime_error(format!("Undefined variable {:?}", variable), offset))
                 }
             }
         } else {
@@ -271,13 +384,15 @@ impl VirtualMachine {
         offset: usize
     ) -> InterpretResult {
         if let Value::String(object) = chunk.read_constant(iter) {
-            if !self.globals.contains(object) {
-                let variable = &object.as_ref().value;
-                Err(VirtualMachine::runtime_error(format!("Undefined variable {:?}", variable), offset))
-            } else {
-                let value = self.stack.peek_end(0).unwrap();
-                self.globals.insert(Rc::clone(object), value.clone());
-                Ok(())
+            match self.globals.find_mut(object) {
+                Some(global) => {
+                    *global = self.stack.peek_end(0).unwrap().clone();
+                    Ok(())
+                }
+                None => {
+                    let variable = &object.as_ref().value;
+                    Err(self.runtime_error(format!("Undefined variable {:?}", variable), offset))
+                }
             }
         } else {
             panic!("Unexpected value type in global variable");
@@ -285,9 +400,9 @@ impl VirtualMachine {
     }
 
     #[inline]
-    fn get_local_variable(&mut self, iter: &mut Iter<u8>, slots_start: usize) {
+    fn get_local_variable(&mut self, iter: &mut Iter<u8>, slots_start: usize, offset: usize) -> InterpretResult {
         let index = *(iter.next().unwrap()) as usize;
-        self.stack.push(self.stack.copy_value(slots_start + index));
+        self.push(self.stack.copy_value(slots_start + index), offset)
     }
 
     #[inline]
@@ -298,9 +413,15 @@ impl VirtualMachine {
     }
 
     #[inline]
-    fn get_upvalue(&mut self, iter: &mut Iter<u8>, upvalues: &[Rc<RefCell<ObjectUpvalue>>]) {
+    fn get_upvalue(
+        &mut self,
+        iter: &mut Iter<u8>,
+        upvalues: &[Rc<RefCell<ObjectUpvalue>>],
+        offset: usize
+    ) -> InterpretResult {
         let slot = *(iter.next().unwrap()) as usize;
-        self.stack.push(upvalues[slot].as_ref().borrow().value().clone())
+        let value = upvalues[slot].as_ref().borrow().value().clone();
+        self.push(value, offset)
     }
 
     #[inline]
@@ -321,11 +442,25 @@ impl VirtualMachine {
                     Some(property) => property.clone(),
                     None => self.bind_method(&instance_ref.class, object, &instance)
                 };
-                self.stack.push(value);
-                Ok(())
+                drop(instance_ref);
+                self.push(value, offset)
+            }
+            (Value::List(list), Value::String(name)) => {
+                let method = self.list_method(list, name, offset)?;
+                self.push(method, offset)
             }
             (Value::Instance(_), _) => panic!("Unexpected value type instead of instance property name"),
-            _ => Err(VirtualMachine::runtime_error("Only instances have properties".to_string(), offset))
+            _ => Err(self.runtime_error("Only instances have properties".to_string(), offset))
+        }
+    }
+
+    fn list_method(&mut self, list: ListElements, name: &Rc<ObjectString>, offset: usize) -> Result<Value, InterpretError> {
+        match self.list_methods.find(name) {
+            Some(Value::NativeFunction(method)) => Ok(Value::NativeFunction(method.bind(Value::List(list)))),
+            _ => {
+                let message = format!("Undefined property '{}'.", name.value);
+                Err(self.runtime_error(message, offset))
+            }
         }
     }
 
@@ -351,11 +486,87 @@ impl VirtualMachine {
         match (instance, property_name) {
             (Value::Instance(instance), Value::String(object)) => {
                 instance.borrow_mut().set_property(Rc::clone(object), value.clone());
-                self.stack.push(value);
-                Ok(())
+                self.push(value, offset)
             }
             (Value::Instance(_), _) => panic!("Unexpected value type instead of instance property name"),
-            _ => Err(VirtualMachine::runtime_error("Only instances have fields.".to_string(), offset))
+            _ => Err(self.runtime_error("Only instances have fields.".to_string(), offset))
+        }
+    }
+
+    fn build_list(&mut self, iter: &mut Iter<u8>, offset: usize) -> InterpretResult {
+        let elements_count = *(iter.next().unwrap()) as usize;
+        let elements = self.stack.last_values(elements_count).to_vec();
+        for _ in 0..elements_count {
+            self.stack.pop();
+        }
+        let list = self.make_list(elements);
+        self.push(list, offset)
+    }
+
+    fn index_get(&mut self, offset: usize) -> InterpretResult {
+        let index = self.stack.pop().unwrap();
+        let list = self.stack.pop().unwrap();
+        match list {
+            Value::String(string) => return self.string_index(&string, index, offset),
+            Value::Dictionary(entries) => {
+                let key = self.dictionary_key(&index, offset)?;
+                let value = entries.as_ref().borrow().find(&key).cloned().unwrap_or_default();
+                return self.push(value, offset);
+            }
+            _ => {}
+        }
+        let (elements, index) = self.list_index(list, index, offset)?;
+        let element = elements.as_ref().borrow()[index].clone();
+        self.push(element, offset)
+    }
+
+    /// Strings are indexed by code point and yield one-character strings.
+    fn string_index(&mut self, string: &ObjectString, index: Value, offset: usize) -> InterpretResult {
+        let index = self.index_number(index, offset)?;
+        let Some(character) = string.char_at(index) else {
+            return Err(self.runtime_error("Index out of bounds.".to_string(), offset));
+        };
+        let character = self.interned_strings.as_ref().borrow_mut().find_string_or_insert_new(character.to_string());
+        self.push(Value::String(character), offset)
+    }
+
+    fn index_set(&mut self, offset: usize) -> InterpretResult {
+        let value = self.stack.pop().unwrap();
+        let index = self.stack.pop().unwrap();
+        let list = self.stack.pop().unwrap();
+        if let Value::Dictionary(entries) = list {
+            let key = self.dictionary_key(&index, offset)?;
+            entries.as_ref().borrow_mut().insert(key, value.clone());
+            return self.push(value, offset);
+        }
+        let (elements, index) = self.list_index(list, index, offset)?;
+        elements.as_ref().borrow_mut()[index] = value.clone();
+        self.push(value, offset)
+    }
+
+    fn dictionary_key(&mut self, index: &Value, offset: usize) -> Result<DictionaryKey, InterpretError> {
+        DictionaryKey::from_value(index).map_err(|message| self.runtime_error(message.to_string(), offset))
+    }
+
+    fn list_index(&mut self, list: Value, index: Value, offset: usize) -> Result<(ListElements, usize), InterpretError> {
+        let elements = match list {
+            Value::List(elements) => elements,
+            _ => return Err(self.runtime_error("Only lists can be indexed.".to_string(), offset)),
+        };
+        let index = self.index_number(index, offset)?;
+        if index >= elements.as_ref().borrow().len() {
+            return Err(self.runtime_error("Index out of bounds.".to_string(), offset));
+        }
+        Ok((elements, index))
+    }
+
+    fn index_number(&mut self, index: Value, offset: usize) -> Result<usize, InterpretError> {
+        match index {
+            Value::Number(index) if index >= 0f32 && index.fract() == 0f32 => Ok(index as usize),
+            _ => {
+                let message = "Index must be a non-negative integer.".to_string();
+                Err(self.runtime_error(message, offset))
+            }
         }
     }
 
@@ -375,57 +586,144 @@ impl VirtualMachine {
     }
 
     #[inline]
-    fn handle_jump_if_false(&mut self, iter: &mut Iter<u8>, offset: &mut usize) {
+    fn inherit(&mut self, offset: usize) -> InterpretResult {
+        let subclass = self.stack.pop().unwrap();
+        let superclass = self.stack.peek_end(0).unwrap();
+        match (superclass, subclass) {
+            (Value::Class(superclass), Value::Class(subclass)) => {
+                subclass.as_ref().borrow_mut().inherit(&superclass.as_ref().borrow());
+                Ok(())
+            }
+            (_, Value::Class(_)) => {
+                Err(self.runtime_error("Superclass must be a class.".to_string(), offset))
+            }
+            _ => panic!("Unexpected value type instead of subclass")
+        }
+    }
+
+    #[inline]
+    fn get_super(&mut self, chunk: &Chunk, iter: &mut Iter<u8>, offset: usize) -> InterpretResult {
+        let method_name = chunk.read_constant(iter);
+        let superclass = self.stack.pop().unwrap();
+        let instance = self.stack.pop().unwrap();
+        match (superclass, instance, method_name) {
+            (Value::Class(superclass), Value::Instance(instance), Value::String(name)) => {
+                match self.bind_method(&superclass, name, &instance) {
+                    Value::Nil => {
+                        let message = format!("Undefined property '{}'.", name.value);
+                        Err(self.runtime_error(message, offset))
+                    }
+                    method => self.push(method, offset)
+                }
+            }
+            _ => panic!("Unexpected value types in super method access")
+        }
+    }
+
+    #[inline]
+    fn handle_jump_if_false(
+        &mut self,
+        chunk: &Chunk,
+        iter: &mut Iter<u8>,
+        offset: &mut usize,
+        prev_offset: usize
+    ) -> InterpretResult {
         let jump_offset = Chunk::read_condition_offset(iter);
         let top_value = self.stack.peek_end(0).unwrap();
         match top_value {
             Value::Bool(false) | Value::Nil => {
-                iter.nth(jump_offset - 1);
-                *offset += jump_offset;
+                self.jump_forward(chunk, iter, offset, jump_offset, prev_offset)
             },
-            _ => {},
+            _ => Ok(()),
         }
     }
 
+    #[inline]
+    fn jump_forward(
+        &mut self,
+        chunk: &Chunk,
+        iter: &mut Iter<u8>,
+        offset: &mut usize,
+        jump_offset: usize,
+        prev_offset: usize
+    ) -> InterpretResult {
+        if *offset + jump_offset > chunk.codes.length {
+            return Err(self.runtime_error("Jump target out of bounds.".to_string(), prev_offset));
+        }
+        if jump_offset > 0 {
+            iter.nth(jump_offset - 1);
+            *offset += jump_offset;
+        }
+        Ok(())
+    }
+
     #[inline]
     fn handle_call(
         &mut self,
         iter: &mut Iter<u8>,
         offset: usize,
-        upvalues: &[Rc<RefCell<ObjectUpvalue>>],
     ) -> InterpretResult {
         let arguments_count = *(iter.next().unwrap());
+        self.call_value(arguments_count, offset)
+    }
+
+    fn call_value(
+        &mut self,
+        arguments_count: u8,
+        offset: usize,
+    ) -> InterpretResult {
         let arguments_count_usize = arguments_count as usize;
         let callee = self.stack.peek_end(arguments_count_usize);
 
         match callee.unwrap() {
-            Value::Closure(closure) if closure.function.arity != arguments_count => {
-                let func = &closure.function;
-                Err(VirtualMachine::runtime_error(
-                    format!("{:?} function expects {} arguments but got {}.", func.name, func.arity, arguments_count),
-                    offset
-                ))
-            }
             Value::Closure(closure) => {
                 let closure = Rc::clone(closure);
-                self.call_closure(&closure, arguments_count_usize, offset, &closure.upvalues, upvalues)
+                self.call_closure_checked(&closure, arguments_count, offset)
             },
             Value::NativeFunction(object) => {
-                let result: Value = (object.function)();
-                self.stack.push(result);
-                Ok(())
+                if !object.accepts(arguments_count) {
+                    let message = if object.arity == object.max_arity {
+                        format!("Expected {} arguments but got {}.", object.arity, arguments_count)
+                    } else {
+                        format!("Expected {} to {} arguments but got {}.", object.arity, object.max_arity, arguments_count)
+                    };
+                    return Err(self.runtime_error(message, offset));
+                }
+                let function = Rc::clone(&object.function);
+                let receiver = object.receiver.as_deref().cloned();
+                let arguments = receiver.into_iter()
+                    .chain(self.stack.last_values(arguments_count_usize).iter().cloned())
+                    .collect::<Vec<Value>>();
+                let roots_start = self.native_roots.len();
+                self.native_roots.extend(arguments.iter().cloned());
+                let result = function(self, &arguments);
+                self.native_roots.truncate(roots_start);
+                let result = result.map_err(|error| match error {
+                    NativeError::Message(message) => self.runtime_error(message, offset),
+                    NativeError::Callback => InterpretError(offset),
+                })?;
+                for _ in 0..=arguments_count_usize {
+                    self.stack.pop();
+                }
+                let result = match result {
+                    Value::String(string) => Value::String(self.intern_string(string)),
+                    value => value,
+                };
+                self.push(result, offset)
             }
             Value::Class(class) => {
                 let instance = ObjectInstance::new(class.clone());
                 let class = Rc::clone(&instance.class);
+                let instance = Rc::new(RefCell::new(instance));
+                self.heap.track_instance(&instance);
                 let instance_index = self.stack.top_index() - arguments_count_usize - 1;
-                self.stack.modify_at_index(instance_index, Value::Instance(Rc::new(RefCell::new(instance))));
+                self.stack.modify_at_index(instance_index, Value::Instance(instance));
                 if let Some(init) = class.as_ref().borrow().method(&self.init_string) {
                     let closure = Rc::clone(init);
-                    self.call_closure(&closure, arguments_count_usize, offset, &closure.upvalues, upvalues)?;
+                    self.call_closure_checked(&closure, arguments_count, offset)?;
                 } else if arguments_count != 0 {
                     let message = format!("Expected 0 arguments but got {}.", arguments_count);
-                    return Err(VirtualMachine::runtime_error(message, offset));
+                    return Err(self.runtime_error(message, offset));
                 }
                 Ok(())
             }
@@ -433,12 +731,111 @@ impl VirtualMachine {
                 let closure = Rc::clone(&bound_method.method);
                 let instance_index = self.stack.top_index() - arguments_count_usize - 1;
                 self.stack.modify_at_index(instance_index, Value::Instance(Rc::clone(&bound_method.receiver)));
-                self.call_closure(&closure, arguments_count_usize, offset, &closure.upvalues, upvalues)
+                self.call_closure_checked(&closure, arguments_count, offset)
             }
             _ => {
-                Err(VirtualMachine::runtime_error("Can only call functions and classes.".to_string(), offset))
+                Err(self.runtime_error("Can only call functions and classes.".to_string(), offset))
+            }
+        }
+    }
+
+    /// Calls `callee` on behalf of a native function. A runtime error in the
+    /// callee is reported before it is returned, so natives only pass it on.
+    pub fn call_from_native(&mut self, callee: &Value, arguments: &[Value]) -> Result<Value, NativeError> {
+        let base_index = self.stack.top_index();
+        let result = std::iter::once(callee).chain(arguments)
+            .try_for_each(|value| self.push(value.clone(), 0))
+            .and_then(|_| self.call_value(arguments.len() as u8, 0))
+            .map(|_| self.stack.pop().unwrap());
+        while self.stack.top_index() > base_index {
+            self.stack.pop();
+        }
+        result.map_err(|_| NativeError::Callback)
+    }
+
+    /// Whether calling `callee` with `arguments_count` arguments would pass
+    /// the arity check, so natives can reject a callback before using it.
+    pub fn can_call(&self, callee: &Value, arguments_count: u8) -> bool {
+        match callee {
+            Value::Closure(closure) => closure.function.arity == arguments_count,
+            Value::BoundMethod(method) => method.method.function.arity == arguments_count,
+            Value::NativeFunction(native) => native.accepts(arguments_count),
+            Value::Class(class) => {
+                let class = class.as_ref().borrow();
+                class.method(&self.init_string).map_or(0, |init| init.function.arity) == arguments_count
+            }
+            _ => false,
+        }
+    }
+
+    /// Makes a list the garbage collector knows about.
+    pub fn make_list(&mut self, elements: Vec<Value>) -> Value {
+        let list = Rc::new(RefCell::new(elements));
+        self.heap.track_list(&list);
+        Value::List(list)
+    }
+
+    /// Makes an empty dictionary the garbage collector knows about.
+    pub fn make_dictionary(&mut self) -> Value {
+        let dictionary = Rc::new(RefCell::new(HashTable::new()));
+        self.heap.track_dictionary(&dictionary);
+        Value::Dictionary(dictionary)
+    }
+
+    fn invoke(
+        &mut self,
+        chunk: &Chunk,
+        iter: &mut Iter<u8>,
+        offset: usize,
+    ) -> InterpretResult {
+        let method_name = chunk.read_constant(iter);
+        let arguments_count = *(iter.next().unwrap());
+        let receiver = self.stack.peek_end(arguments_count as usize).unwrap();
+        let (instance, name) = match (receiver, method_name) {
+            (Value::Instance(instance), Value::String(name)) => (Rc::clone(instance), name),
+            (Value::List(list), Value::String(name)) => {
+                let method = self.list_method(Rc::clone(list), name, offset)?;
+                let callee_index = self.stack.top_index() - arguments_count as usize - 1;
+                self.stack.modify_at_index(callee_index, method);
+                return self.call_value(arguments_count, offset);
             }
+            (Value::Instance(_), _) => panic!("Unexpected value type instead of method name"),
+            _ => return Err(self.runtime_error("Only instances have methods.".to_string(), offset))
+        };
+
+        let instance_ref = instance.as_ref().borrow();
+        if let Some(field) = instance_ref.property(name) {
+            let field = field.clone();
+            drop(instance_ref);
+            let callee_index = self.stack.top_index() - arguments_count as usize - 1;
+            self.stack.modify_at_index(callee_index, field);
+            return self.call_value(arguments_count, offset);
+        }
+        let method = instance_ref.class.as_ref().borrow().method(name).cloned();
+        drop(instance_ref);
+        match method {
+            Some(closure) => self.call_closure_checked(&closure, arguments_count, offset),
+            None => {
+                let message = format!("Undefined property '{}'.", name.value);
+                Err(self.runtime_error(message, offset))
+            }
+        }
+    }
+
+    fn call_closure_checked(
+        &mut self,
+        closure: &Rc<ObjectClosure>,
+        arguments_count: u8,
+        offset: usize,
+    ) -> InterpretResult {
+        let function = &closure.function;
+        if function.arity != arguments_count {
+            return Err(self.runtime_error(
+                format!("Expected {} arguments but got {}.", function.arity, arguments_count),
+                offset
+            ));
         }
+        self.call_closure(closure, arguments_count as usize, offset)
     }
 
     fn read_closure(
@@ -447,14 +844,15 @@ impl VirtualMachine {
         offset: &mut usize,
         iter: &mut Iter<u8>,
         slots_start: usize,
-        enclosing_upvalues: &[Rc<RefCell<ObjectUpvalue>>]
-    ) {
+        enclosing_upvalues: &[Rc<RefCell<ObjectUpvalue>>],
+        prev_offset: usize
+    ) -> InterpretResult {
         let constant = chunk.read_constant(iter);
         if let Value::Function(function) = constant {
             let mut upvalues = Vec::new();
 
             for _ in 0..function.upvalue_count {
-                let is_local = if *(iter.next().unwrap()) == 1u8 { true } else { false };
+                let is_local = *(iter.next().unwrap()) == 1u8;
                 let index = *(iter.next().unwrap());
                 if is_local {
                     let upvalue = self.capture_upvalue(slots_start + index as usize);
@@ -469,8 +867,7 @@ impl VirtualMachine {
                 function: Rc::clone(function),
                 upvalues,
             };
-            self.stack.push(Value::Closure(Rc::new(closure)));
-
+            self.push(Value::Closure(Rc::new(closure)), prev_offset)
         } else {
             panic!("Unexpected value found instead of ObjectFunction")
         }
@@ -488,6 +885,7 @@ impl VirtualMachine {
             Some(upvalue) => upvalue.clone(),
             None => {
                 let captured_upvalue = Rc::new(RefCell::new(object_upvalue));
+                self.heap.track_upvalue(&captured_upvalue);
                 self.open_upvalues.push(captured_upvalue.clone());
                 captured_upvalue
             }
@@ -516,45 +914,53 @@ impl VirtualMachine {
     #[inline]
     fn call_closure(
         &mut self,
-        closure: &ObjectClosure,
+        closure: &Rc<ObjectClosure>,
         arguments_count: usize,
         offset: usize,
-        upvalues: &[Rc<RefCell<ObjectUpvalue>>],
-        enclosing_upvalues: &[Rc<RefCell<ObjectUpvalue>>]
     ) -> InterpretResult {
-        if self.frame_count + 1 == FRAMES_SIZE {
-            return Err(VirtualMachine::runtime_error("Stack overflow.".to_string(), offset));
+        if self.frame_count == self.frames_limit {
+            return Err(self.runtime_error("Stack overflow.".to_string(), offset));
         }
         let cloned_function = Rc::clone(&closure.function);
         let slots_start = self.stack.top_index() - arguments_count - 1;
         let chunk = &cloned_function.as_ref().chunk;
 
-        let result = self.handle_chunk(chunk, slots_start, upvalues, enclosing_upvalues);
+        self.frame_count += 1;
+        self.active_closures.push(Rc::clone(closure));
+        let result = self.handle_chunk(chunk, slots_start, &closure.upvalues);
+        self.active_closures.pop();
         let return_value = self.stack.pop().unwrap();
         while self.stack.top_index() > slots_start {
             self.stack.pop();
         }
-        self.stack.push(return_value);
+        let pushed = self.push(return_value, offset);
         self.frame_count -= 1;
-        result.map_err(|_| {
-            eprintln!("[line {}] in {:?}()", chunk.line(offset), cloned_function.name);
+        result.and(pushed).map_err(|error| {
+            writeln!(self.error_output, "[line {}] in {}()", chunk.line(error.0), cloned_function.name.value)
+                .expect("Failed to write to error output");
             InterpretError(offset)
         })
     }
 
     #[inline]
-    fn read_class(&mut self, chunk: &Chunk, iter: &mut Iter<u8>) {
+    fn read_class(&mut self, chunk: &Chunk, iter: &mut Iter<u8>, offset: usize) -> InterpretResult {
         if let Value::String(object) = chunk.read_constant(iter) {
             let class_object = ObjectClass::new(object.clone());
-            self.stack.push(Value::Class(Rc::new(RefCell::new(class_object))));
+            self.push(Value::Class(Rc::new(RefCell::new(class_object))), offset)
         } else {
             panic!("Unexpected value type instead of class declaration");
         }
     }
 
     #[inline]
-    fn runtime_error(message: String, offset: usize) -> InterpretError {
-        eprintln!("{}", message);
+    fn push(&mut self, value: Value, offset: usize) -> InterpretResult {
+        self.stack.push(value)
+            .map_err(|_| self.runtime_error("Stack overflow.".to_string(), offset))
+    }
+
+    #[inline]
+    fn runtime_error(&mut self, message: String, offset: usize) -> InterpretError {
+        writeln!(self.error_output, "{}", message).expect("Failed to write to error output");
         InterpretError(offset)
     }
 }
@@ -562,3 +968,624 @@ impl VirtualMachine {
 #[derive(Debug)]
 pub struct InterpretError(usize);
 pub type InterpretResult = Result<(), InterpretError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::compiler::{Compiler, CompilerContext, FunctionType};
+    use crate::shared_output::SharedOutput;
+
+    fn run(codes: &[u8]) -> Result<(), String> {
+        let mut chunk = Chunk::new();
+        for code in codes {
+            chunk.push(*code, 1);
+        }
+        let mut virtual_machine = new_virtual_machine();
+        let errors = SharedOutput::default();
+        virtual_machine.set_error_output(Box::new(errors.clone()));
+        virtual_machine.handle_chunk(&chunk, 0, &[]).map_err(|_| errors.take_string())
+    }
+
+    #[test]
+    fn out_of_bounds_jump() {
+        let error = Err("Jump target out of bounds.\n".to_string());
+        assert_eq!(run(&[OpCode::Jump as u8, 0, 10, OpCode::Nil as u8, OpCode::Return as u8]), error);
+        assert_eq!(run(&[OpCode::False as u8, OpCode::JumpIfFalse as u8, 1, 0, OpCode::Return as u8]), error);
+        assert_eq!(run(&[OpCode::Nil as u8, OpCode::Loop as u8, 0, 10, OpCode::Return as u8]), error);
+    }
+
+    #[test]
+    fn jump_within_bounds() {
+        assert!(run(&[OpCode::Jump as u8, 0, 1, OpCode::Nil as u8, OpCode::Nil as u8, OpCode::Return as u8]).is_ok());
+        assert!(run(&[OpCode::True as u8, OpCode::JumpIfFalse as u8, 0, 1, OpCode::Nil as u8, OpCode::Return as u8]).is_ok());
+    }
+
+    fn run_source(source: &str, globals: &[(&str, Value)]) -> VirtualMachine {
+        run_source_with_output(source, globals, SharedOutput::default())
+    }
+
+    fn new_virtual_machine() -> VirtualMachine {
+        let interned_strings = Rc::new(RefCell::new(HashTable::new()));
+        VirtualMachine::with_output(interned_strings, Box::new(SharedOutput::default()))
+    }
+
+    /// Runs `source`, which must fail, and returns the error message it reported.
+    fn runtime_error(source: &str) -> String {
+        reported_error(&mut new_virtual_machine(), source)
+    }
+
+    fn reported_error(virtual_machine: &mut VirtualMachine, source: &str) -> String {
+        let errors = SharedOutput::default();
+        virtual_machine.set_error_output(Box::new(errors.clone()));
+        assert!(execute(virtual_machine, source).is_err(), "{}", source);
+        errors.take_string().lines().next().unwrap_or_default().to_string()
+    }
+
+    fn run_source_with_output(source: &str, globals: &[(&str, Value)], output: SharedOutput) -> VirtualMachine {
+        let interned_strings = Rc::new(RefCell::new(HashTable::new()));
+        let mut virtual_machine = VirtualMachine::with_output(interned_strings, Box::new(output));
+        for (name, value) in globals {
+            let name = virtual_machine.intern(name);
+            virtual_machine.globals.insert(name, value.clone());
+        }
+        assert!(execute(&mut virtual_machine, source).is_ok());
+        virtual_machine
+    }
+
+    fn execute(virtual_machine: &mut VirtualMachine, source: &str) -> InterpretResult {
+        let parse_rules = Compiler::make_parse_rules();
+        let interned_strings = Rc::clone(&virtual_machine.interned_strings);
+        let context = CompilerContext::new(source, &parse_rules, interned_strings, false, FunctionType::Script);
+        let mut compiler = Compiler::new(context);
+        let chunk = compiler.compile().unwrap();
+        virtual_machine.run_script(chunk)
+    }
+
+    fn error_line(source: &str) -> usize {
+        let mut virtual_machine = new_virtual_machine();
+        let parse_rules = Compiler::make_parse_rules();
+        let interned_strings = Rc::clone(&virtual_machine.interned_strings);
+        let context = CompilerContext::new(source, &parse_rules, interned_strings, false, FunctionType::Script);
+        let mut compiler = Compiler::new(context);
+        let chunk = compiler.compile().unwrap();
+        let error = virtual_machine.run_script(chunk).unwrap_err();
+        chunk.line(error.0)
+    }
+
+    impl VirtualMachine {
+        fn intern(&self, name: &str) -> Rc<ObjectString> {
+            self.interned_strings.as_ref().borrow_mut().find_string_or_insert_new(name.to_string())
+        }
+
+        fn global(&self, name: &str) -> Option<Value> {
+            self.globals.find(&self.intern(name)).cloned()
+        }
+    }
+
+    #[test]
+    fn loop_to_chunk_start() {
+        let virtual_machine = run_source("while (i < 3) i = i + 1;", &[("i", Value::Number(0f32))]);
+        assert_eq!(virtual_machine.global("i"), Some(Value::Number(3f32)));
+    }
+
+    #[test]
+    fn loop_result() {
+        let source = "var sum = 0; for (var i = 0; i < 100; i = i + 1) { if (i == 50) continue; sum = sum + i; }";
+        let virtual_machine = run_source(source, &[]);
+        assert_eq!(virtual_machine.global("sum"), Some(Value::Number(4900f32)));
+    }
+
+    #[test]
+    fn concatenation() {
+        let source = "var a = \"x\"; var b = a + \"y\"; var c = a + \"z\"; var d = \"\"; \
+            for (var i = 0; i < 3; i = i + 1) d = d + \"o\";";
+        let virtual_machine = run_source(source, &[]);
+        let string = |name| match virtual_machine.global(name) {
+            Some(Value::String(object)) => object.value.clone(),
+            _ => panic!("Expected string global {}", name),
+        };
+        assert_eq!(string("a"), "x");
+        assert_eq!(string("b"), "xy");
+        assert_eq!(string("c"), "xz");
+        assert_eq!(string("d"), "ooo");
+    }
+
+    #[test]
+    fn concatenation_of_uniquely_owned_string() {
+        let right = ObjectString::from_string("b".to_string());
+        let unique = Rc::new(ObjectString::from_string("a".to_string()));
+        assert_eq!(VirtualMachine::concatenate(unique, &right), "ab");
+
+        let shared = Rc::new(ObjectString::from_string("a".to_string()));
+        let other = Rc::clone(&shared);
+        assert_eq!(VirtualMachine::concatenate(shared, &right), "ab");
+        assert_eq!(other.value, "a");
+    }
+
+    fn printed(source: &str) -> String {
+        let output = SharedOutput::default();
+        run_source_with_output(source, &[], output.clone());
+        output.take_string()
+    }
+
+    #[test]
+    fn frames_limit() {
+        let source = "fun f(n) { if (n == 0) return 0; return f(n - 1) + 1; } var r = f(100);";
+        let mut virtual_machine = new_virtual_machine();
+        assert_eq!(reported_error(&mut virtual_machine, source), "Stack overflow.");
+
+        virtual_machine.set_frames_limit(102);
+        assert!(execute(&mut virtual_machine, source).is_ok());
+        assert_eq!(virtual_machine.global("r"), Some(Value::Number(100f32)));
+
+        virtual_machine.set_frames_limit(101);
+        assert_eq!(reported_error(&mut virtual_machine, source), "Stack overflow.");
+    }
+
+    #[test]
+    fn stack_overflow_is_runtime_error() {
+        let elements = vec!["1"; 255].join(", ");
+        let mut virtual_machine = new_virtual_machine();
+        virtual_machine.set_frames_limit(1);
+        assert!(execute(&mut virtual_machine, &format!("print [1, [{}]];", elements)).is_ok());
+        assert_eq!(reported_error(&mut virtual_machine, &format!("print [1, 1, [{}]];", elements)), "Stack overflow.");
+        assert!(execute(&mut virtual_machine, "print -2;").is_ok());
+    }
+
+    #[test]
+    fn set_property() {
+        assert_eq!(printed("class A {} var a = A(); a.x = 5; print a.x;"), "5\n");
+        assert_eq!(printed("class A {} var a = A(); print a.x = 5;"), "5\n");
+    }
+
+    #[test]
+    fn set_property_on_non_instance() {
+        assert_eq!(runtime_error("var a = 1; a.x = 5;"), "Only instances have fields.");
+    }
+
+    #[test]
+    fn class_method() {
+        assert_eq!(printed("class Greeter { hello() { print \"hi\"; } } Greeter().hello();"), "hi\n");
+        let source = "class A { first() { return 1; } second() { return 2; } } var a = A(); print a.first() + a.second();";
+        assert_eq!(printed(source), "3\n");
+    }
+
+    #[test]
+    fn field_shadows_method() {
+        let source = "class A { m() { return 1; } } var a = A(); a.m = 2; print a.m;";
+        assert_eq!(printed(source), "2\n");
+    }
+
+    #[test]
+    fn bound_method_reads_field_through_this() {
+        let source = "class Box { get() { return this.value; } } \
+            var box = Box(); box.value = 7; var get = box.get; print get();";
+        assert_eq!(printed(source), "7\n");
+    }
+
+    #[test]
+    fn native_function_equals_itself() {
+        assert_eq!(printed("print clock == clock; var c = clock; print c == clock;"), "true\ntrue\n");
+    }
+
+    #[test]
+    fn inherited_method() {
+        assert_eq!(printed("class A { f() { return 1; } } class B < A {} print B().f();"), "1\n");
+        let source = "class A { f() { return 1; } } class B < A { f() { return 2; } } print B().f();";
+        assert_eq!(printed(source), "2\n");
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(printed("var a = [1,2,3]; a[1] = 9; print a[1];"), "9\n");
+        let source = "var a = [\"x\", [], nil]; print a; a[1] = [a[0]]; print a[1][0]; \
+            fun f() { var b = [1, 2]; b[0] = b[0] + b[1]; return b; } print f(); print [] == [];";
+        assert_eq!(printed(source), "[x, [], nil]\nx\n[3, 2]\nfalse\n");
+    }
+
+    #[test]
+    fn list_index_errors_report_line() {
+        assert_eq!(error_line("var a = [1, 2];\nprint a[2];"), 2);
+        assert_eq!(error_line("var a = [1, 2];\n\na[-1] = 0;"), 3);
+        assert_eq!(error_line("var a = [1];\nprint a[0.5];"), 2);
+        assert_eq!(error_line("var a = \"ab\";\nprint a[2];"), 2);
+    }
+
+    #[test]
+    fn strings_index_by_code_point() {
+        let source = "var s = \"h\u{e9}llo\"; print s[1]; print len(s); \
+            var copy = \"\"; for (var i = 0; i < len(s); i = i + 1) copy = copy + s[i]; print copy == s;";
+        assert_eq!(printed(source), "\u{e9}\n5\ntrue\n");
+        assert_eq!(runtime_error("print \"h\u{e9}llo\"[5];"), "Index out of bounds.");
+        assert_eq!(runtime_error("print \"ab\"[-1];"), "Index must be a non-negative integer.");
+        assert_eq!(runtime_error("var n = 1; print n[0];"), "Only lists can be indexed.");
+    }
+
+    #[test]
+    fn anonymous_classes() {
+        let source = "var Widget = class { init(size) { this.size = size; } draw() { return \"w\" + this.size; } }; \
+            var widget = Widget(\"1\"); print widget.draw(); print Widget; \
+            print 1 + (class { f() { return 2; } })().f();";
+        assert_eq!(printed(source), "w1\nanonymous\n3\n");
+        let source = "class Base { name() { return \"base\"; } } \
+            fun make(prefix) { return class < Base { name() { return prefix + super.name(); } }; } \
+            print make(\"made \")().name();";
+        assert_eq!(printed(source), "made base\n");
+    }
+
+    #[test]
+    fn closures_capture_variables_of_outer_functions() {
+        let source = "fun outer(a) { fun middle() { fun inner() { return a; } return inner; } return middle()(); } \
+            print outer(1);";
+        assert_eq!(printed(source), "1\n");
+    }
+
+    #[test]
+    fn inherit_copies_methods_without_touching_superclass() {
+        let classes = "class A { f() { return \"A.f\"; } g() { return \"A.g\"; } } \
+            class B < A { f() { return \"B.f\"; } h() { return \"B.h\"; } }";
+        let source = format!("{} var b = B(); print b.f(); print b.g(); print b.h(); print A().f();", classes);
+        assert_eq!(printed(&source), "B.f\nA.g\nB.h\nA.f\n");
+
+        assert_eq!(runtime_error(&format!("{} A().h();", classes)), "Undefined property 'h'.");
+    }
+
+    #[test]
+    fn super_method_call() {
+        let source = "class A { f() { return \"A\"; } } \
+            class B < A { f() { return \"B\" + super.f(); } } \
+            class C < B { f() { return \"C\" + super.f(); } } print C().f();";
+        assert_eq!(printed(source), "CBA\n");
+        let source = "class A { f() { return this.x; } } class B < A { g() { var m = super.f; return m(); } } \
+            var b = B(); b.x = 3; print b.g();";
+        assert_eq!(printed(source), "3\n");
+    }
+
+    #[test]
+    fn superclass_must_be_a_class() {
+        assert_eq!(runtime_error("var A = 1; class B < A {}"), "Superclass must be a class.");
+    }
+
+    #[test]
+    fn undefined_super_method() {
+        assert_eq!(runtime_error("class C {} class D < C { f() { return super.g(); } } D().f();"), "Undefined property 'g'.");
+    }
+
+    #[test]
+    fn invoke_method() {
+        let source = "class A { add(a, b) { return a + b; } } print A().add(1, 2);";
+        assert_eq!(printed(source), "3\n");
+        let source = "fun g() { return 5; } class B {} var b = B(); b.f = g; print b.f();";
+        assert_eq!(printed(source), "5\n");
+    }
+
+    #[test]
+    fn invoke_method_in_tight_loop() {
+        let source = "class Counter { init() { this.count = 0; } increment() { this.count = this.count + 1; } } \
+            var counter = Counter(); for (var i = 0; i < 10000; i = i + 1) counter.increment(); print counter.count;";
+        assert_eq!(printed(source), "10000\n");
+    }
+
+    #[test]
+    fn invoke_errors() {
+        assert_eq!(runtime_error("class A {} A().missing();"), "Undefined property 'missing'.");
+        assert_eq!(runtime_error("class A { f(a) {} } A().f();"), "Expected 1 arguments but got 0.");
+    }
+
+    #[test]
+    fn interpret_chunks_sharing_globals() {
+        let parse_rules = Compiler::make_parse_rules();
+        let interned_strings = Rc::new(RefCell::new(HashTable::new()));
+        let output = SharedOutput::default();
+        let mut virtual_machine = VirtualMachine::with_output(Rc::clone(&interned_strings), Box::new(output.clone()));
+        let sources = [
+            "var count = 1; fun counter() { var n = 0; fun next() { n = n + 1; return n; } return next; }",
+            "var next = counter(); count = count + next();",
+            "{ var local = 1; print nil + local; }",
+            "print count + next();",
+        ];
+        for source in sources {
+            let context = CompilerContext::new(source, &parse_rules, Rc::clone(&interned_strings), false, FunctionType::Script);
+            let mut compiler = Compiler::new(context);
+            let result = virtual_machine.interpret(compiler.compile().unwrap());
+            assert_eq!(result.is_ok(), !source.contains("nil +"));
+            assert_eq!(virtual_machine.stack.top_index(), 0);
+        }
+        assert_eq!(output.take_string(), "4\n");
+    }
+
+    #[test]
+    fn property_access_on_number() {
+        assert_eq!(runtime_error("print 5.foo;"), "Only instances have properties");
+    }
+
+    #[test]
+    fn break_statement() {
+        assert_eq!(printed("while (true) { break; } print \"done\";"), "done\n");
+        let source = "for (var i = 0; i < 10; i = i + 1) { var j = i * 2; if (i == 3) break; print j; } print \"end\";";
+        assert_eq!(printed(source), "0\n2\n4\nend\n");
+        let source = "var i = 0; while (i < 3) { i = i + 1; var k = 0; while (true) { k = k + 1; if (k == i) break; } print k; }";
+        assert_eq!(printed(source), "1\n2\n3\n");
+    }
+
+    #[test]
+    fn continue_keeps_block_locals() {
+        let source = "for (var i = 0; i < 3; i = i + 1) { var a = i; if (i == 1) continue; print a; }";
+        assert_eq!(printed(source), "0\n2\n");
+    }
+
+    #[test]
+    fn modulo() {
+        assert_eq!(printed("print 7 % 3; print 7.5 % 2; print 2 + 7 % 4 * 2;"), "1\n1.5\n8\n");
+        assert_eq!(runtime_error("print \"a\" % 2;"), "Operands must be numbers.");
+    }
+
+    #[test]
+    fn repl_line_without_semicolon() {
+        let output = SharedOutput::default();
+        let interned_strings = Rc::new(RefCell::new(HashTable::new()));
+        let mut virtual_machine = VirtualMachine::with_output(Rc::clone(&interned_strings), Box::new(output.clone()));
+        let parse_rules = Compiler::make_parse_rules();
+        let mut context = CompilerContext::new("var a = 2; print a * 3", &parse_rules, interned_strings, false, FunctionType::Script);
+        context.set_implicit_semicolon(true);
+        let mut compiler = Compiler::new(context);
+        let chunk = compiler.compile().unwrap();
+        assert!(virtual_machine.run_script(chunk).is_ok());
+        assert_eq!(String::from_utf8(output.0.as_ref().borrow().clone()).unwrap(), "6\n");
+    }
+
+    #[test]
+    fn conditional_expression() {
+        assert_eq!(printed("print 1 > 0 ? \"yes\" : \"no\";"), "yes\n");
+        assert_eq!(printed("print 1 < 0 ? \"yes\" : \"no\";"), "no\n");
+        assert_eq!(printed("var a = 2; print a == 1 ? \"one\" : a == 2 ? \"two\" : \"many\";"), "two\n");
+        assert_eq!(printed("var a = nil; print a or 1 ? 2 : 3; a = true ? 4 : 5; print a;"), "2\n4\n");
+    }
+
+    #[test]
+    fn conditional_expression_skips_untaken_branch() {
+        let explode = Value::NativeFunction(ObjectNativeFunction::new("explode", 0, |_| panic!("untaken branch evaluated")));
+        let output = SharedOutput::default();
+        let globals = [("explode", explode)];
+        run_source_with_output("print true ? 1 : explode(); print false ? explode() : 2;", &globals, output.clone());
+        assert_eq!(String::from_utf8(output.0.as_ref().borrow().clone()).unwrap(), "1\n2\n");
+    }
+
+    #[test]
+    fn native_functions() {
+        assert_eq!(printed("print sqrt(9);"), "3\n");
+        assert_eq!(printed("print floor(2.7); print floor(-2.5);"), "2\n-3\n");
+        assert_eq!(printed("print len(\"hello\"); print len(\"\");"), "5\n0\n");
+        assert_eq!(printed("print len(\"héllo\"); print len(\"日本\") + len(\"!\");"), "5\n3\n");
+        assert_eq!(printed("// héllo\nvar café = \"é\" + \"!\"; print café;"), "é!\n");
+        assert_eq!(printed("print str(12) + \"!\"; print str(nil); print str(true) == \"true\";"), "12!\nnil\ntrue\n");
+        assert_eq!(printed("print num(\"2.5\") * 2; print num(4);"), "5\n4\n");
+    }
+
+    #[test]
+    fn sort_native() {
+        assert_eq!(printed("var a = [3, 1, 2, 0 / 0, -1]; sort(a); print a;"), "[-1, 1, 2, 3, nan]\n");
+        assert_eq!(printed("print sort([\"pear\", \"apple\", \"fig\"]);"), "[apple, fig, pear]\n");
+        let source = "fun descending(a, b) { return b - a; } print sort([1, 3, 2], descending); print sort([]);";
+        assert_eq!(printed(source), "[3, 2, 1]\n[]\n");
+        let source = "fun byLength(a, b) { return len(a) - len(b); } print sort([\"ccc\", \"a\", \"bb\", \"d\"], byLength);";
+        assert_eq!(printed(source), "[a, d, bb, ccc]\n");
+        let errors = [
+            ("sort([1, \"a\"]);", "sort can only compare numbers with numbers and strings with strings."),
+            ("sort(1);", "First argument of sort must be a list."),
+            ("fun f(a, b) { return nil; } sort([1, 2], f);", "Comparator of sort must return a number."),
+            ("fun f(a) { return 0; } sort([1, 2], f);", "Expected 1 arguments but got 2."),
+            ("fun f(a, b) { return a + nil; } sort([1, 2], f);", "Operands must be two numbers or two strings."),
+            ("sort([1], nil, 1);", "Expected 1 to 2 arguments but got 3."),
+        ];
+        for (source, message) in errors {
+            let mut virtual_machine = new_virtual_machine();
+            assert_eq!(reported_error(&mut virtual_machine, source), message);
+            assert_eq!(virtual_machine.stack.top_index(), 0);
+        }
+    }
+
+    #[test]
+    fn higher_order_natives() {
+        let source = "fun double(n) { return n * 2; } fun isEven(n) { return n % 2 == 0; } \
+            fun add(a, b) { return a + b; } class Box { init(value) { this.value = value; } } \
+            print map([1, 2, 3], double); print filter([1, 2, 3, 4], isEven); print reduce([1, 2, 3], add, 0); \
+            print reduce([], add, \"empty\"); print reduce([\"a\", \"b\"], add, \"\"); print map([1], Box)[0].value; \
+            print map([1, 4], sqrt); print filter([nil, 0, false, \"\"], str);";
+        assert_eq!(printed(source), "[2, 4, 6]\n[2, 4]\n6\nempty\nab\n1\n[1, 2]\n[nil, 0, false, ]\n");
+        let errors = [
+            ("fun f(a, b) { return a; } map([1], f);", "Second argument of map must be a function of 1 arguments."),
+            ("filter([1], nil);", "Second argument of filter must be a function of 1 arguments."),
+            ("fun f(a) { return a; } reduce([1], f, 0);", "Second argument of reduce must be a function of 2 arguments."),
+            ("reduce(1, sqrt, 0);", "First argument of reduce must be a list."),
+            ("fun f(a) { return a + nil; } map([1], f);", "Operands must be two numbers or two strings."),
+        ];
+        for (source, message) in errors {
+            assert_eq!(runtime_error(source), message);
+        }
+    }
+
+    #[test]
+    fn list_methods() {
+        let source = "var list = [1]; list.push(2); list.push(list.length()); print list; \
+            print list.pop(); print list; var push = list.push; push(nil); print list.length(); \
+            fun add(list) { list.push(\"x\"); } add(list); print list;";
+        assert_eq!(printed(source), "[1, 2, 2]\n2\n[1, 2]\n3\n[1, 2, nil, x]\n");
+        assert_eq!(runtime_error("[].pop();"), "Can't pop from an empty list.");
+        assert_eq!(runtime_error("[].missing();"), "Undefined property 'missing'.");
+        assert_eq!(runtime_error("print [].size;"), "Undefined property 'size'.");
+        assert_eq!(runtime_error("[].push();"), "Expected 1 arguments but got 0.");
+        assert_eq!(runtime_error("[1].length(1);"), "Expected 0 arguments but got 1.");
+    }
+
+    #[test]
+    fn native_function_errors() {
+        assert_eq!(runtime_error("sqrt(\"a\");"), "Argument of sqrt must be a number.");
+        assert_eq!(runtime_error("len(1);"), "Argument of len must be a string.");
+        assert_eq!(runtime_error("num(\"abc\");"), "Can't convert \"abc\" to a number.");
+        assert_eq!(runtime_error("sqrt();"), "Expected 1 arguments but got 0.");
+        assert_eq!(runtime_error("sqrt(1, 2);"), "Expected 1 arguments but got 2.");
+    }
+
+    #[test]
+    fn print_uses_display() {
+        assert_eq!(printed("print \"hi\";"), "hi\n");
+        assert_eq!(printed("print 1; print 2.5; print true; print nil;"), "1\n2.5\ntrue\nnil\n");
+        assert_eq!(printed("class Point {} print Point();"), "<Point instance>\n");
+    }
+
+    #[test]
+    fn compound_assignment() {
+        assert_eq!(printed("var g = 10; g += 5; g -= 1; g *= 2; g /= 4; print g;"), "7\n");
+        assert_eq!(printed("{ var l = 1; print l += 2; print l; }"), "3\n3\n");
+        assert_eq!(printed("fun f() { var c = 1; fun g() { c *= 3; } g(); print c; } f();"), "3\n");
+        assert_eq!(printed("var s = \"a\"; s += \"b\"; print s;"), "ab\n");
+        let source = "class A {} var a = A(); a.n = 1; print a.n += 2; a.n *= 10; print a.n;";
+        assert_eq!(printed(source), "3\n30\n");
+    }
+
+    #[test]
+    fn inspect_instance_fields() {
+        let source = "class A {} class B {} var a = A(); var b = B(); a.b = b; b.a = a; b.n = 1; print inspect(a);";
+        assert_eq!(printed(source), "A { b: B { a: <cycle>, n: 1 } }\n");
+        assert_eq!(printed("class A {} var a = A(); var b = A(); a.x = b; a.y = b; print inspect(a);"), "A { x: A { }, y: A { } }\n");
+    }
+
+    #[test]
+    fn class_methods_keep_declaration_order() {
+        let mut virtual_machine = new_virtual_machine();
+        let source = "class A { c() {} a() {} } class B < A { b() {} a() {} d() {} }";
+        assert!(execute(&mut virtual_machine, source).is_ok());
+        let method_names = |name: &str| match virtual_machine.global(name) {
+            Some(Value::Class(class)) => class
+                .as_ref()
+                .borrow()
+                .method_names()
+                .map(|name| name.value.clone())
+                .collect::<std::vec::Vec<_>>(),
+            _ => panic!("{} is not a class", name),
+        };
+        assert_eq!(method_names("A"), vec!["c", "a"]);
+        assert_eq!(method_names("B"), vec!["c", "a", "b", "d"]);
+    }
+
+    #[test]
+    fn native_function_arity() {
+        assert_eq!(runtime_error("clock(1);"), "Expected 0 arguments but got 1.");
+        assert_eq!(runtime_error("clock(1, 2, 3);"), "Expected 0 arguments but got 3.");
+        let source = "{ var a = 1; clock(); var b = 2; print a + b; }";
+        assert_eq!(printed(source), "3\n");
+    }
+
+    #[test]
+    fn runtime_error_line() {
+        assert_eq!(error_line("var a = 1;\nvar b = \"x\";\nprint a / b;"), 3);
+        assert_eq!(error_line("var a = 1;\n\nprint a + b;"), 3);
+        assert_eq!(error_line("var a = 1;\n\nb = a;"), 3);
+        assert_eq!(error_line("var a = 1;\nprint a;\nprint -\"a\";"), 3);
+        assert_eq!(error_line("var a = 1;\nprint a;\nprint 1 < nil;"), 3);
+        assert_eq!(error_line("var a = 1;\nprint a;\na.x = 1;"), 3);
+        assert_eq!(error_line("var a = 1;\nprint a;\nprint a.x;"), 3);
+        assert_eq!(error_line("var a = 1;\nprint a;\na();"), 3);
+        assert_eq!(error_line("fun f() {\n  var x = 1;\n  return x / nil;\n}\n\nf();"), 6);
+        assert_eq!(error_line("fun f(x) {\n  return x;\n}\nprint f(1)\n  + nil;"), 5);
+        assert_eq!(error_line("class A {\n  m() {}\n}\nvar a = A();\na.m(\n  1);"), 5);
+        assert_eq!(error_line("fun f() {\n  var x = 1;\n  fun g() { return x; }\n  return g;\n}\nvar h = f();\nprint h() + nil;"), 7);
+        assert_eq!(error_line("for (var i = 0; i < 2; i = i + 1) {\n  if (i > 5) print i;\n}\nprint -nil;"), 4);
+        assert_eq!(error_line("class A { m() { return 1; } }\nclass B < A { m() { return super.m(); } }\nprint B().m();\nprint -B();"), 4);
+    }
+
+    fn global(virtual_machine: &VirtualMachine, name: &str) -> Value {
+        let (_, value) = virtual_machine.globals.iter().find(|(key, _)| key.value == name).unwrap();
+        value.clone()
+    }
+
+    #[test]
+    fn garbage_collector_reclaims_cycles() {
+        let mut virtual_machine = new_virtual_machine();
+        let source = "class Node {} var a = Node(); var b = Node(); a.next = b; b.next = a; \
+            var list = [a]; list[0] = list; fun f() { var x = a; fun g() { return x; } return g; } var g = f();";
+        assert!(execute(&mut virtual_machine, source).is_ok());
+        let weak_instance = match global(&virtual_machine, "b") {
+            Value::Instance(instance) => Rc::downgrade(&instance),
+            _ => panic!("b is not an instance"),
+        };
+        let weak_list = match global(&virtual_machine, "list") {
+            Value::List(list) => Rc::downgrade(&list),
+            _ => panic!("list is not a list"),
+        };
+        assert_eq!(virtual_machine.collect_garbage(), 0);
+        assert!(weak_instance.upgrade().is_some());
+
+        assert!(execute(&mut virtual_machine, "a = nil; b = nil; list = nil;").is_ok());
+        assert_eq!(virtual_machine.collect_garbage(), 1);
+        assert!(weak_list.upgrade().is_none());
+        assert!(weak_instance.upgrade().is_some());
+
+        assert!(execute(&mut virtual_machine, "g = nil;").is_ok());
+        virtual_machine.collect_garbage();
+        assert!(weak_instance.upgrade().is_none());
+        assert_eq!(virtual_machine.heap.tracked_count(), 0);
+    }
+
+    #[test]
+    fn garbage_collector_reclaims_dictionary_cycles() {
+        let mut virtual_machine = new_virtual_machine();
+        assert!(execute(&mut virtual_machine, "var d = dict(); d[\"self\"] = d; d[1] = [d];").is_ok());
+        let weak_dictionary = match global(&virtual_machine, "d") {
+            Value::Dictionary(dictionary) => Rc::downgrade(&dictionary),
+            _ => panic!("d is not a dictionary"),
+        };
+        assert_eq!(virtual_machine.collect_garbage(), 0);
+
+        assert!(execute(&mut virtual_machine, "d = nil;").is_ok());
+        assert_eq!(virtual_machine.collect_garbage(), 2);
+        assert!(weak_dictionary.upgrade().is_none());
+        assert_eq!(virtual_machine.heap.tracked_count(), 0);
+    }
+
+    #[test]
+    fn garbage_collector_runs_during_execution() {
+        let interned_strings = Rc::new(RefCell::new(HashTable::new()));
+        let output = SharedOutput::default();
+        let mut virtual_machine = VirtualMachine::with_output(interned_strings, Box::new(output.clone()));
+        let source = "class Pair {} var kept = Pair(); kept.self = kept; \
+            for (var i = 0; i < 5000; i = i + 1) { var p = Pair(); var q = Pair(); p.other = q; q.other = p; } \
+            print kept.self == kept;";
+        assert!(execute(&mut virtual_machine, source).is_ok());
+        assert!(virtual_machine.heap.tracked_count() < 2048);
+        assert_eq!(output.take_string(), "true\n");
+    }
+
+    #[test]
+    fn garbage_collector_keeps_open_upvalues() {
+        // Once `g` is gone, only the open upvalue list still refers to the
+        // upvalue capturing `x`, which points at a live stack slot.
+        let source = "class Box {} fun f() { var x = \"kept\"; { fun g() { return x; } } \
+            for (var i = 0; i < 3000; i = i + 1) { var b = Box(); b.self = b; } print x; } f();";
+        assert_eq!(printed(source), "kept\n");
+    }
+
+    #[test]
+    fn garbage_collector_runs_inside_native_callbacks() {
+        let output = SharedOutput::default();
+        let mut virtual_machine = VirtualMachine::with_output(Rc::new(RefCell::new(HashTable::new())), Box::new(output.clone()));
+        let peak_tracked = Rc::new(std::cell::Cell::new(0));
+        let peak = Rc::clone(&peak_tracked);
+        let probe = ObjectNativeFunction::with_callbacks("probe", 0, move |virtual_machine, _| {
+            peak.set(peak.get().max(virtual_machine.heap.tracked_count()));
+            Ok(Value::Nil)
+        });
+        VirtualMachine::add_native_functions(&mut virtual_machine.globals, [probe], &virtual_machine.interned_strings);
+        let source = "class Box {} var numbers = []; for (var i = 0; i < 3000; i = i + 1) numbers.push(i); \
+            fun churn(i) { var p = Box(); var q = Box(); p.other = q; q.other = p; probe(); \
+                var kept = Box(); kept.value = i; kept.self = kept; return kept; } \
+            fun add(sum, box) { var garbage = Box(); garbage.self = garbage; return sum + box.self.value; } \
+            print reduce(map(numbers, churn), add, 0);";
+        assert!(execute(&mut virtual_machine, source).is_ok());
+        assert_eq!(output.take_string(), "4498500\n");
+        // Without collections inside `map` every one of the 9000 instances
+        // would still be tracked when the last callback runs.
+        assert!(peak_tracked.get() < 6000, "{}", peak_tracked.get());
+    }
+}
@@ -1,2 +1,9 @@
 pub mod tree_walk;
 pub mod bytecode;
+pub mod limits;
+pub mod number;
+pub mod run_error;
+#[cfg(test)]
+mod shared_output;
+
+pub use run_error::RunError;
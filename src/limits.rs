@@ -0,0 +1,19 @@
+/// Default for the deepest expression nesting either backend accepts. A
+/// grouping, a unary operator, the right operand of a binary operator and
+/// every expression parsed on its own (arguments, list elements, indices,
+/// assigned values, returned values) each add one level; a unary operator
+/// applied to a grouping counts once.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 256;
+
+/// Native stack reserved per nesting level. The deepest case, a debug build
+/// of the tree-walk backend nesting class expressions, takes about 40 KB.
+const STACK_BYTES_PER_NESTING_LEVEL: usize = 64 * 1024;
+const BASE_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Stack size for a thread that parses and compiles scripts nested up to
+/// `max_nesting_depth` levels.
+pub fn stack_size(max_nesting_depth: usize) -> usize {
+    max_nesting_depth
+        .saturating_mul(STACK_BYTES_PER_NESTING_LEVEL)
+        .saturating_add(BASE_STACK_SIZE)
+}
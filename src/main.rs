@@ -1,44 +1,107 @@
-use std::{env, process};
-use rlox::tree_walk;
-use rlox::bytecode;
+use std::{env, panic, process, thread};
+use rlox::{bytecode, limits, tree_walk};
 use std::{fs, io, result::Result};
 use io::{BufRead, Error as IOError, Write};
 
 fn main() {
-    let args: Vec<String> = env::args().skip(1).collect();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let frames_limit = remove_value_flag(&mut args, "--frames-limit")
+        .map(|value| value.parse::<usize>().unwrap_or_else(|_| print_usage()));
+    let dump_tokens = remove_flag(&mut args, "--dump-tokens");
+    let dump_ast = remove_flag(&mut args, "--dump-ast");
+    let tree_walk = remove_flag(&mut args, "--tree-walk");
+    let max_token_length = remove_value_flag(&mut args, "--max-token-length")
+        .map(|value| value.parse::<usize>().unwrap_or_else(|_| print_usage()));
+    let max_nesting_depth = remove_value_flag(&mut args, "--max-nesting-depth")
+        .map(|value| value.parse::<usize>().unwrap_or_else(|_| print_usage()))
+        .unwrap_or(limits::DEFAULT_MAX_NESTING_DEPTH);
+    let tree_walk_options = tree_walk::Options {
+        warnings: !remove_flag(&mut args, "--no-warnings"),
+        max_token_length: max_token_length.unwrap_or(tree_walk::DEFAULT_MAX_TOKEN_LENGTH),
+        max_nesting_depth,
+    };
+    let options = bytecode::Options {
+        verify: remove_flag(&mut args, "--verify"),
+        frames_limit,
+        repl: false,
+        trace: remove_flag(&mut args, "--trace"),
+        max_nesting_depth,
+    };
 
-    match args.len() {
-        0 => {
-            if let Err(error) = run_prompt() {
-                eprintln!("{}", error);
+    // Both parsers recurse once per nesting level, so scripts run on a thread
+    // whose stack fits the deepest nesting allowed.
+    let runner = thread::Builder::new()
+        .stack_size(limits::stack_size(max_nesting_depth))
+        .spawn(move || match args.len() {
+            0 => {
+                // The prompt always runs the bytecode backend.
+                if tree_walk || !tree_walk_options.warnings || max_token_length.is_some() {
+                    print_usage();
+                }
+                if let Err(error) = run_prompt(options, dump_tokens, dump_ast) {
+                    eprintln!("{}", error);
+                }
             }
-        }
-        1 => {
-            let path = args[0].to_string();
-            let content = fs::read_to_string(path).expect("File not found");
-            run_interpreter(content);
-        }
-        _ => {
-            println!("Usage: rlox [script]");
-            process::exit(64);
-        }
+            1 => {
+                let path = args[0].to_string();
+                let content = fs::read_to_string(path).expect("File not found");
+                let result = if dump_tokens {
+                    bytecode::dump_tokens(&content, &mut io::stdout())
+                } else if dump_ast {
+                    tree_walk::dump_ast(&content, max_nesting_depth, &mut io::stdout());
+                    Ok(())
+                } else if tree_walk {
+                    tree_walk::run_interpreter(content, tree_walk_options)
+                } else {
+                    bytecode::run_interpreter(content, options)
+                };
+                if let Err(error) = result {
+                    process::exit(error.exit_code());
+                }
+            }
+            _ => print_usage(),
+        })
+        .expect("Failed to start the interpreter thread");
+    if let Err(error) = runner.join() {
+        panic::resume_unwind(error);
     }
 }
 
-fn run_prompt() -> Result<(), IOError> {
+fn print_usage() -> ! {
+    println!("Usage: rlox [--verify] [--trace] [--dump-tokens] [--dump-ast] [--tree-walk [--no-warnings] [--max-token-length=<count>]] [--frames-limit=<count>] [--max-nesting-depth=<count>] [script]");
+    process::exit(64);
+}
+
+fn remove_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let length = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != length
+}
+
+fn remove_value_flag(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let prefix = format!("{}=", flag);
+    let index = args.iter().position(|arg| arg.starts_with(&prefix))?;
+    Some(args.remove(index)[prefix.len()..].to_string())
+}
+
+fn run_prompt(options: bytecode::Options, dump_tokens: bool, dump_ast: bool) -> Result<(), IOError> {
+    let mut session = bytecode::Session::new(bytecode::Options { repl: true, ..options });
     print!("> ");
     io::stdout().flush().unwrap();
 
     for read_result in io::stdin().lock().lines() {
         let line = read_result?;
-        run_interpreter(line);
+        let _ = if dump_tokens {
+            bytecode::dump_tokens(&line, &mut io::stdout())
+        } else if dump_ast {
+            tree_walk::dump_ast(&line, options.max_nesting_depth, &mut io::stdout());
+            Ok(())
+        } else {
+            session.run(&line)
+        };
 
         print!("> ");
         io::stdout().flush().unwrap();
     }
     Ok(())
 }
-
-fn run_interpreter(script: String) {
-    bytecode::run_interpreter(script);
-}
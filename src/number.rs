@@ -0,0 +1,171 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::str::FromStr;
+
+pub fn format_number<N>(number: N) -> String where N: Into<f64> + Display + Copy {
+    let value: f64 = number.into();
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0f64 { "inf".to_string() } else { "-inf".to_string() }
+    } else {
+        // Display for floats prints the shortest representation that parses back to the same value.
+        format!("{}", number)
+    }
+}
+
+/// Floating point types a number literal can be parsed into.
+pub trait Number: FromStr + Into<f64> + Copy {
+    fn from_integer(value: u64) -> Self;
+}
+
+impl Number for f32 {
+    fn from_integer(value: u64) -> Self {
+        value as f32
+    }
+}
+
+impl Number for f64 {
+    fn from_integer(value: u64) -> Self {
+        value as f64
+    }
+}
+
+/// Radix selected by the character following a leading `0`, as in `0x1F` or `0b1010`.
+pub fn radix_of_prefix(prefix: char) -> Option<u32> {
+    match prefix {
+        'x' | 'X' => Some(16),
+        'b' | 'B' => Some(2),
+        _ => None,
+    }
+}
+
+pub fn parse_number<N>(literal: &str) -> Result<N, &'static str> where N: Number {
+    let radix = literal
+        .strip_prefix('0')
+        .and_then(|rest| rest.chars().next())
+        .and_then(radix_of_prefix);
+    if let Some(radix) = radix {
+        return parse_integer(&literal[2..], radix);
+    }
+    let digits = strip_digit_separators(literal, u8::is_ascii_digit)?;
+    let number: N = digits.parse().map_err(|_| "Invalid number literal.")?;
+    if number.into().is_finite() {
+        Ok(number)
+    } else {
+        Err("Number literal is too large.")
+    }
+}
+
+fn parse_integer<N>(digits: &str, radix: u32) -> Result<N, &'static str> where N: Number {
+    if digits.is_empty() {
+        return Err("Expected digits after number prefix.");
+    }
+    let digits = strip_digit_separators(digits, |byte| (*byte as char).is_digit(radix))?;
+    if !digits.chars().all(|character| character.is_digit(radix)) {
+        return Err("Invalid number literal.");
+    }
+    u64::from_str_radix(&digits, radix)
+        .map(N::from_integer)
+        .map_err(|_| "Number literal is too large.")
+}
+
+/// Removes `_` separators, which are only allowed between two digits.
+fn strip_digit_separators<F>(literal: &str, is_digit: F) -> Result<Cow<'_, str>, &'static str>
+    where F: Fn(&u8) -> bool {
+    if !literal.contains('_') {
+        return Ok(Cow::Borrowed(literal));
+    }
+    let bytes = literal.as_bytes();
+    let is_digit_at = |index: Option<usize>| {
+        index.and_then(|index| bytes.get(index)).is_some_and(&is_digit)
+    };
+    let is_valid = bytes
+        .iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte == b'_')
+        .all(|(index, _)| is_digit_at(index.checked_sub(1)) && is_digit_at(Some(index + 1)));
+    if is_valid {
+        Ok(Cow::Owned(literal.replace('_', "")))
+    } else {
+        Err("Digit separator '_' must be placed between digits.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, Rng};
+
+    #[test]
+    fn format_integers_without_fraction() {
+        assert_eq!(format_number(3f64), "3");
+        assert_eq!(format_number(-12f32), "-12");
+        assert_eq!(format_number(0.5f64), "0.5");
+    }
+
+    #[test]
+    fn format_special_values() {
+        assert_eq!(format_number(f64::NAN), "nan");
+        assert_eq!(format_number(f64::INFINITY), "inf");
+        assert_eq!(format_number(f32::NEG_INFINITY), "-inf");
+    }
+
+    #[test]
+    fn out_of_range_literal_is_rejected() {
+        let literal = format!("1{}", "0".repeat(400));
+        assert_eq!(parse_number::<f64>(&literal).err(), Some("Number literal is too large."));
+        assert_eq!(parse_number::<f32>(&literal).err(), Some("Number literal is too large."));
+        assert_eq!(parse_number::<f32>("340282350000000000000000000000000000000").ok(), Some(f32::MAX));
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(parse_number::<f64>("1_000").ok(), Some(1000f64));
+        assert_eq!(parse_number::<f32>("1_000_000.2_5").ok(), Some(1_000_000.25f32));
+        let error = Some("Digit separator '_' must be placed between digits.");
+        for literal in ["1__0", "_1", "1_", "1_.5", "1._5"] {
+            assert_eq!(parse_number::<f64>(literal).err(), error, "{}", literal);
+        }
+    }
+
+    #[test]
+    fn radix_literals() {
+        assert_eq!(parse_number::<f64>("0xff").ok(), Some(255f64));
+        assert_eq!(parse_number::<f32>("0x1F").ok(), Some(31f32));
+        assert_eq!(parse_number::<f64>("0b1010").ok(), Some(10f64));
+        assert_eq!(parse_number::<f64>("0xdead_beef").ok(), Some(3735928559f64));
+        assert_eq!(parse_number::<f64>("0x").err(), Some("Expected digits after number prefix."));
+        assert_eq!(parse_number::<f64>("0b").err(), Some("Expected digits after number prefix."));
+        assert_eq!(parse_number::<f64>("0b102").err(), Some("Invalid number literal."));
+        assert_eq!(parse_number::<f64>("0x+1").err(), Some("Invalid number literal."));
+        assert_eq!(parse_number::<f64>("0x_1").err(), Some("Digit separator '_' must be placed between digits."));
+        assert_eq!(parse_number::<f64>(&format!("0x1{}", "0".repeat(16))).err(), Some("Number literal is too large."));
+    }
+
+    #[test]
+    fn f64_round_trip() {
+        let mut rng = thread_rng();
+        for _ in 0..10_000 {
+            let number = f64::from_bits(rng.gen());
+            if !number.is_finite() {
+                continue;
+            }
+            let parsed: f64 = parse_number(&format_number(number)).unwrap();
+            assert_eq!(parsed.to_bits(), number.to_bits());
+        }
+    }
+
+    #[test]
+    fn f32_round_trip() {
+        let mut rng = thread_rng();
+        for _ in 0..10_000 {
+            let number = f32::from_bits(rng.gen());
+            if !number.is_finite() {
+                continue;
+            }
+            let parsed: f32 = parse_number(&format_number(number)).unwrap();
+            assert_eq!(parsed.to_bits(), number.to_bits());
+        }
+    }
+}
@@ -0,0 +1,14 @@
+/// Why running a script failed, mapped to the exit codes used by the Lox book.
+pub enum RunError {
+    Compile,
+    Runtime,
+}
+
+impl RunError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunError::Compile => 65,
+            RunError::Runtime => 70,
+        }
+    }
+}
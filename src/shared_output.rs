@@ -0,0 +1,24 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Output sink for tests that keeps what was written for later inspection.
+#[derive(Clone, Default)]
+pub struct SharedOutput(pub Rc<RefCell<Vec<u8>>>);
+
+impl SharedOutput {
+    /// Removes and returns everything written so far.
+    pub fn take_string(&self) -> String {
+        String::from_utf8(self.0.take()).unwrap()
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.0.as_ref().borrow_mut().write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+use super::expression::{Expression, LiteralExpression, VariableExpression, Visitor as ExpressionVisitor};
+use super::lox_function::LoxFunction;
+use super::statement::{Statement, Visitor as StatementVisitor};
+use super::token::Token;
+use crate::number::format_number;
+use std::rc::Rc;
+
+pub struct AstPrinter;
+
+impl AstPrinter {
+    pub fn print_statement(&mut self, statement: &Statement) -> String {
+        statement.accept(self)
+    }
+
+    fn parenthesize(&mut self, name: &str, expressions: &[&Expression]) -> String {
+        let mut result = format!("({}", name);
+        for expression in expressions {
+            result.push(' ');
+            result.push_str(&expression.accept(self));
+        }
+        result.push(')');
+        result
+    }
+
+    fn print_function(&mut self, keyword: &str, func: &LoxFunction) -> String {
+        let mut result = if func.is_getter {
+            format!("(getter {}", func.name)
+        } else {
+            format!("({} {} ({})", keyword, func.name, func.parameters.join(" "))
+        };
+        for statement in &func.body {
+            result.push(' ');
+            result.push_str(&statement.accept(self));
+        }
+        result.push(')');
+        result
+    }
+
+    fn print_class(
+        &mut self,
+        mut result: String,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: &Option<VariableExpression>,
+    ) -> String {
+        if let Some(superclass) = superclass {
+            result.push_str(&format!(" < {}", superclass.name));
+        }
+        for method in methods {
+            result.push(' ');
+            result.push_str(&self.print_function("method", method));
+        }
+        for method in static_methods {
+            result.push(' ');
+            result.push_str(&self.print_function("class", method));
+        }
+        result.push(')');
+        result
+    }
+}
+
+fn lexeme(token: &Token) -> String {
+    token.lexeme.iter().collect()
+}
+
+impl ExpressionVisitor<String> for AstPrinter {
+    fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+        self.parenthesize(&lexeme(operator), &[left, right])
+    }
+
+    fn visit_grouping(&mut self, expression: &Expression) -> String {
+        self.parenthesize("group", &[expression])
+    }
+
+    fn visit_literal(&mut self, literal: &LiteralExpression) -> String {
+        match literal {
+            LiteralExpression::False => "false".to_string(),
+            LiteralExpression::True => "true".to_string(),
+            LiteralExpression::Nil => "nil".to_string(),
+            LiteralExpression::String(value) => format!("{:?}", value),
+            LiteralExpression::Number(value) => format_number(*value),
+        }
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expression) -> String {
+        self.parenthesize(&lexeme(operator), &[right])
+    }
+
+    fn visit_variable(&mut self, literal: &str, _token: &Token, _id: usize) -> String {
+        literal.to_string()
+    }
+
+    fn visit_assignment(&mut self, token: &Token, right: &Expression, _id: usize) -> String {
+        format!("(= {} {})", lexeme(token), right.accept(self))
+    }
+
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> String {
+        self.parenthesize(&lexeme(operator), &[left, right])
+    }
+
+    fn visit_call(&mut self, callee: &Expression, _close_paren: &Token, arguments: &[Expression]) -> String {
+        let mut expressions = vec![callee];
+        expressions.extend(arguments.iter());
+        self.parenthesize("call", &expressions)
+    }
+
+    fn visit_get(&mut self, name: &str, expression: &Expression) -> String {
+        format!("(. {} {})", expression.accept(self), name)
+    }
+
+    fn visit_set(&mut self, name: &str, object: &Expression, value: &Expression) -> String {
+        format!("(= (. {} {}) {})", object.accept(self), name, value.accept(self))
+    }
+
+    fn visit_this(&mut self, _token: &Token, _id: usize) -> String {
+        "this".to_string()
+    }
+
+    fn visit_super(&mut self, _keyword_token: &Token, method: &str, _id: usize) -> String {
+        format!("(. super {})", method)
+    }
+
+    fn visit_lambda(&mut self, func: &Rc<LoxFunction>) -> String {
+        let mut result = format!("(lambda ({})", func.parameters.join(" "));
+        for statement in &func.body {
+            result.push(' ');
+            result.push_str(&statement.accept(self));
+        }
+        result.push(')');
+        result
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> String {
+        let elements: Vec<&Expression> = elements.iter().collect();
+        self.parenthesize("list", &elements)
+    }
+
+    fn visit_index(&mut self, object: &Expression, index: &Expression, _close_bracket: &Token) -> String {
+        self.parenthesize("[]", &[object, index])
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Expression,
+        index: &Expression,
+        value: &Expression,
+        _close_bracket: &Token,
+    ) -> String {
+        format!("(= ([] {} {}) {})", object.accept(self), index.accept(self), value.accept(self))
+    }
+
+    fn visit_class_expr(
+        &mut self,
+        _line: u32,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: &Option<VariableExpression>,
+    ) -> String {
+        self.print_class("(class".to_string(), methods, static_methods, superclass)
+    }
+}
+
+impl StatementVisitor<String> for AstPrinter {
+    fn visit_print(&mut self, expression: &Expression) -> String {
+        self.parenthesize("print", &[expression])
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) -> String {
+        self.parenthesize(";", &[expression])
+    }
+
+    fn visit_variable_stmt(&mut self, name: &str, value: &Option<Expression>, _line: u32) -> String {
+        match value {
+            Some(value) => format!("(var {} {})", name, value.accept(self)),
+            None => format!("(var {})", name),
+        }
+    }
+
+    fn visit_block(&mut self, statements: &[Statement]) -> String {
+        let mut result = String::from("(block");
+        for statement in statements {
+            result.push(' ');
+            result.push_str(&statement.accept(self));
+        }
+        result.push(')');
+        result
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: &Option<Box<Statement>>,
+    ) -> String {
+        let condition = condition.accept(self);
+        let then_branch = then_branch.accept(self);
+        match else_branch {
+            Some(else_branch) => format!("(if {} {} {})", condition, then_branch, else_branch.accept(self)),
+            None => format!("(if {} {})", condition, then_branch),
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expression, body: &Statement, increment: &Option<Expression>) -> String {
+        let condition = condition.accept(self);
+        let body = body.accept(self);
+        match increment {
+            Some(increment) => format!("(while {} {} {})", condition, body, increment.accept(self)),
+            None => format!("(while {} {})", condition, body),
+        }
+    }
+
+    fn visit_break(&mut self, _line: u32) -> String {
+        "(break)".to_string()
+    }
+
+    fn visit_continue(&mut self, _line: u32) -> String {
+        "(continue)".to_string()
+    }
+
+    fn visit_function(&mut self, func: Rc<LoxFunction>) -> String {
+        self.print_function("fun", &func)
+    }
+
+    fn visit_return(&mut self, expression: &Expression) -> String {
+        self.parenthesize("return", &[expression])
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &str,
+        _line: u32,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: &Option<VariableExpression>,
+    ) -> String {
+        self.print_class(format!("(class {}", name), methods, static_methods, superclass)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::parser::Parser;
+    use super::super::scanner::Scanner;
+
+    fn print(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source).scan_tokens();
+        Parser::new(&tokens)
+            .parse()
+            .iter()
+            .map(|statement| AstPrinter.print_statement(statement))
+            .collect()
+    }
+
+    #[test]
+    fn function_with_if_statement() {
+        let source = "fun sign(n) { if (n < 0) return -1; else return 1; } print sign(-2.5);";
+        assert_eq!(
+            print(source),
+            vec![
+                "(fun sign (n) (if (< n 0) (return (- 1)) (return 1)))",
+                "(print (call sign (- 2.5)))",
+            ]
+        );
+    }
+
+    #[test]
+    fn class_with_superclass() {
+        let source = "class B < A { init(x) { this.x = x; } size { return 1; } } var b = B(\"a\"); b.x;";
+        assert_eq!(
+            print(source),
+            vec![
+                "(class B < A (method init (x) (; (= (. this x) x))) (getter size (return 1)))",
+                "(var b (call B \"a\"))",
+                "(; (. b x))",
+            ]
+        );
+    }
+
+    #[test]
+    fn loop_with_break_and_continue() {
+        assert_eq!(
+            print("for (var i = 0; i < 3; i = i + 1) { if (i == 1) continue; break; }"),
+            vec!["(block (var i 0) (while (< i 3) (block (if (== i 1) (continue)) (break)) (= i (+ i 1))))"]
+        );
+        assert_eq!(print("while (true) break;"), vec!["(while true (break))"]);
+    }
+
+    #[test]
+    fn lambda_expression() {
+        assert_eq!(
+            print("var f = fun (a, b) { return a + b; }; fun g() {}"),
+            vec!["(var f (lambda (a b) (return (+ a b))))", "(fun g ())"]
+        );
+    }
+
+    #[test]
+    fn list_literal_and_indexing() {
+        assert_eq!(
+            print("var a = [1, \"b\", []]; a[0] = a[1 + 1][0];"),
+            vec!["(var a (list 1 \"b\" (list)))", "(; (= ([] a 0) ([] ([] a (+ 1 1)) 0)))"]
+        );
+    }
+
+    #[test]
+    fn class_expression() {
+        assert_eq!(
+            print("var w = class < A { draw() { return 1; } }; class {};"),
+            vec!["(var w (class < A (method draw () (return 1))))", "(; (class))"]
+        );
+    }
+}
@@ -31,7 +31,7 @@ impl Callable {
         arguments: &[Object],
     ) -> Result<Object, InterpreterError> {
         match self {
-            Callable::NativeFn(func) => Ok(func.call(arguments)),
+            Callable::NativeFn(func) => func.call(interpreter, arguments),
             Callable::LoxFn(lox_fn) => {
                 let result = lox_fn.declaration.call(interpreter, arguments, lox_fn.closure.clone());
                 if lox_fn.is_initializer {
@@ -7,11 +7,12 @@ impl Object {
     pub fn make_clock_fn() -> Object {
         let native_fn = NativeFunction {
             arity: 0,
-            on_call: Box::new(|_| {
+            on_call: Box::new(|_, _| {
                 let system_time = SystemTime::now();
                 let milliseconds = system_time.duration_since(UNIX_EPOCH).unwrap().as_millis();
-                Object::Number(milliseconds as f64)
+                Ok(Object::Number(milliseconds as f64))
             }),
+            receiver: None,
         };
         Object::Callable(Callable::NativeFn(native_fn))
     }
@@ -0,0 +1,352 @@
+use super::expression::{Expression, LiteralExpression, VariableExpression, Visitor as ExpressionVisitor};
+use super::lox_function::LoxFunction;
+use super::statement::{Statement, Visitor as StatementVisitor};
+use super::token::Token;
+use super::token_type::{ExpressionOperatorTokenType, SingleCharTokenType, TokenType};
+use std::rc::Rc;
+
+/// Replaces arithmetic, comparison and negation of number literals with their
+/// result before the program is resolved. Operations that fail at runtime,
+/// such as division by zero, are left in place so the error is still reported
+/// when the expression is evaluated. Branches whose condition folds to a
+/// literal are eliminated: `if` keeps only the branch that would run and a
+/// `while` with a falsy condition is dropped.
+pub struct ConstantFolder;
+
+impl ConstantFolder {
+    pub fn fold_statements(&mut self, statements: &[Statement]) -> Vec<Statement> {
+        statements.iter().map(|statement| statement.accept(self)).collect()
+    }
+
+    fn fold_function(&mut self, func: &LoxFunction) -> Rc<LoxFunction> {
+        Rc::new(LoxFunction {
+            id: func.id,
+            name: func.name.clone(),
+            line: func.line,
+            parameters: func.parameters.clone(),
+            body: self.fold_statements(&func.body),
+            is_getter: func.is_getter,
+        })
+    }
+
+    fn fold_functions(&mut self, functions: &[Rc<LoxFunction>]) -> Vec<Rc<LoxFunction>> {
+        functions.iter().map(|func| self.fold_function(func)).collect()
+    }
+
+    fn fold_numbers(operator: &TokenType, left: f64, right: f64) -> Option<LiteralExpression> {
+        let number = match operator {
+            TokenType::SingleChar(SingleCharTokenType::Plus) => left + right,
+            TokenType::SingleChar(SingleCharTokenType::Minus) => left - right,
+            TokenType::SingleChar(SingleCharTokenType::Star) => left * right,
+            TokenType::SingleChar(SingleCharTokenType::Slash) if right != 0f64 => left / right,
+            TokenType::SingleChar(SingleCharTokenType::Percent) if right != 0f64 => left % right,
+            TokenType::ExpressionOperator(operator) => {
+                let result = match operator {
+                    ExpressionOperatorTokenType::Greater => left > right,
+                    ExpressionOperatorTokenType::GreaterEqual => left >= right,
+                    ExpressionOperatorTokenType::Less => left < right,
+                    ExpressionOperatorTokenType::LessEqual => left <= right,
+                    ExpressionOperatorTokenType::EqualEqual => left == right,
+                    ExpressionOperatorTokenType::NotEqual => left != right,
+                    _ => return None,
+                };
+                return Some(ConstantFolder::bool_literal(result));
+            }
+            _ => return None,
+        };
+        Some(LiteralExpression::Number(number))
+    }
+
+    fn bool_literal(value: bool) -> LiteralExpression {
+        if value { LiteralExpression::True } else { LiteralExpression::False }
+    }
+
+    fn is_truthy(literal: &LiteralExpression) -> bool {
+        !matches!(literal, LiteralExpression::Nil | LiteralExpression::False)
+    }
+
+    fn empty_statement() -> Statement {
+        Statement::Block(Vec::new())
+    }
+}
+
+impl ExpressionVisitor<Expression> for ConstantFolder {
+    fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> Expression {
+        let left = left.accept(self);
+        let right = right.accept(self);
+        if let (
+            Expression::Literal(LiteralExpression::Number(left)),
+            Expression::Literal(LiteralExpression::Number(right)),
+        ) = (&left, &right) {
+            if let Some(literal) = ConstantFolder::fold_numbers(&operator.token_type, *left, *right) {
+                return Expression::Literal(literal);
+            }
+        }
+        Expression::Binary(Box::new(left), operator.clone(), Box::new(right))
+    }
+
+    fn visit_grouping(&mut self, expression: &Expression) -> Expression {
+        match expression.accept(self) {
+            literal @ Expression::Literal(_) => literal,
+            expression => Expression::Grouping(Box::new(expression)),
+        }
+    }
+
+    fn visit_literal(&mut self, literal: &LiteralExpression) -> Expression {
+        Expression::Literal(literal.clone())
+    }
+
+    fn visit_unary(&mut self, operator: &Token, right: &Expression) -> Expression {
+        let right = right.accept(self);
+        match (&operator.token_type, &right) {
+            (
+                TokenType::SingleChar(SingleCharTokenType::Minus),
+                Expression::Literal(LiteralExpression::Number(number)),
+            ) => Expression::Literal(LiteralExpression::Number(-number)),
+            (TokenType::ExpressionOperator(ExpressionOperatorTokenType::Not), Expression::Literal(literal)) => {
+                Expression::Literal(ConstantFolder::bool_literal(!ConstantFolder::is_truthy(literal)))
+            }
+            _ => Expression::Unary(operator.clone(), Box::new(right)),
+        }
+    }
+
+    fn visit_variable(&mut self, literal: &str, token: &Token, id: usize) -> Expression {
+        Expression::Variable(VariableExpression {
+            name: literal.to_string(),
+            token: token.clone(),
+            id,
+        })
+    }
+
+    fn visit_assignment(&mut self, token: &Token, right: &Expression, id: usize) -> Expression {
+        Expression::Assignment {
+            token: token.clone(),
+            value: Box::new(right.accept(self)),
+            id,
+        }
+    }
+
+    fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> Expression {
+        Expression::Logical(Box::new(left.accept(self)), operator.clone(), Box::new(right.accept(self)))
+    }
+
+    fn visit_call(&mut self, callee: &Expression, close_paren: &Token, arguments: &[Expression]) -> Expression {
+        Expression::Call {
+            callee: Box::new(callee.accept(self)),
+            close_paren: close_paren.clone(),
+            arguments: arguments.iter().map(|argument| argument.accept(self)).collect(),
+        }
+    }
+
+    fn visit_get(&mut self, name: &str, expression: &Expression) -> Expression {
+        Expression::Get {
+            name: name.to_string(),
+            expression: Box::new(expression.accept(self)),
+        }
+    }
+
+    fn visit_set(&mut self, name: &str, object: &Expression, value: &Expression) -> Expression {
+        Expression::Set {
+            name: name.to_string(),
+            object: Box::new(object.accept(self)),
+            value: Box::new(value.accept(self)),
+        }
+    }
+
+    fn visit_this(&mut self, token: &Token, id: usize) -> Expression {
+        Expression::This { token: token.clone(), id }
+    }
+
+    fn visit_super(&mut self, keyword_token: &Token, method: &str, id: usize) -> Expression {
+        Expression::Super {
+            keyword_token: keyword_token.clone(),
+            method: method.to_string(),
+            id,
+        }
+    }
+
+    fn visit_lambda(&mut self, func: &Rc<LoxFunction>) -> Expression {
+        Expression::Function(self.fold_function(func))
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> Expression {
+        Expression::List(elements.iter().map(|element| element.accept(self)).collect())
+    }
+
+    fn visit_index(&mut self, object: &Expression, index: &Expression, close_bracket: &Token) -> Expression {
+        Expression::Index {
+            object: Box::new(object.accept(self)),
+            index: Box::new(index.accept(self)),
+            close_bracket: close_bracket.clone(),
+        }
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Expression,
+        index: &Expression,
+        value: &Expression,
+        close_bracket: &Token,
+    ) -> Expression {
+        Expression::SetIndex {
+            object: Box::new(object.accept(self)),
+            index: Box::new(index.accept(self)),
+            value: Box::new(value.accept(self)),
+            close_bracket: close_bracket.clone(),
+        }
+    }
+
+    fn visit_class_expr(
+        &mut self,
+        line: u32,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: &Option<VariableExpression>,
+    ) -> Expression {
+        Expression::ClassExpr {
+            line,
+            methods: self.fold_functions(methods),
+            static_methods: self.fold_functions(static_methods),
+            superclass: superclass.clone(),
+        }
+    }
+}
+
+impl StatementVisitor<Statement> for ConstantFolder {
+    fn visit_print(&mut self, expression: &Expression) -> Statement {
+        Statement::Print(expression.accept(self))
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) -> Statement {
+        Statement::Expression(expression.accept(self))
+    }
+
+    fn visit_variable_stmt(&mut self, name: &str, value: &Option<Expression>, line: u32) -> Statement {
+        Statement::Variable {
+            name: name.to_string(),
+            value: value.as_ref().map(|value| value.accept(self)),
+            line,
+        }
+    }
+
+    fn visit_block(&mut self, statements: &[Statement]) -> Statement {
+        Statement::Block(self.fold_statements(statements))
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Statement,
+        else_branch: &Option<Box<Statement>>,
+    ) -> Statement {
+        let condition = condition.accept(self);
+        match &condition {
+            Expression::Literal(literal) if ConstantFolder::is_truthy(literal) => then_branch.accept(self),
+            Expression::Literal(_) => else_branch
+                .as_ref()
+                .map(|branch| branch.accept(self))
+                .unwrap_or_else(ConstantFolder::empty_statement),
+            _ => Statement::If {
+                condition,
+                then_branch: Box::new(then_branch.accept(self)),
+                else_branch: else_branch.as_ref().map(|branch| Box::new(branch.accept(self))),
+            },
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expression, body: &Statement, increment: &Option<Expression>) -> Statement {
+        let condition = condition.accept(self);
+        match &condition {
+            Expression::Literal(literal) if !ConstantFolder::is_truthy(literal) => ConstantFolder::empty_statement(),
+            _ => Statement::While {
+                condition,
+                body: Box::new(body.accept(self)),
+                increment: increment.as_ref().map(|increment| increment.accept(self)),
+            },
+        }
+    }
+
+    fn visit_break(&mut self, line: u32) -> Statement {
+        Statement::Break(line)
+    }
+
+    fn visit_continue(&mut self, line: u32) -> Statement {
+        Statement::Continue(line)
+    }
+
+    fn visit_function(&mut self, func: Rc<LoxFunction>) -> Statement {
+        Statement::Function(self.fold_function(&func))
+    }
+
+    fn visit_return(&mut self, expression: &Expression) -> Statement {
+        Statement::Return(expression.accept(self))
+    }
+
+    fn visit_class(
+        &mut self,
+        name: &str,
+        line: u32,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: &Option<VariableExpression>,
+    ) -> Statement {
+        Statement::Class {
+            name: name.to_string(),
+            line,
+            methods: self.fold_functions(methods),
+            static_methods: self.fold_functions(static_methods),
+            superclass: superclass.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ast_printer::AstPrinter;
+    use super::super::parser::Parser;
+    use super::super::scanner::Scanner;
+
+    fn fold(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source).scan_tokens();
+        let statements = Parser::new(&tokens).parse();
+        ConstantFolder
+            .fold_statements(&statements)
+            .iter()
+            .map(|statement| AstPrinter.print_statement(statement))
+            .collect()
+    }
+
+    #[test]
+    fn fold_arithmetic() {
+        assert_eq!(fold("print 2 + 3 * 4;"), vec!["(print 14)"]);
+        assert_eq!(fold("print -(1 - 3) / 4;"), vec!["(print 0.5)"]);
+        assert_eq!(fold("print 1 < 2; print !nil; print 1 != 1;"), vec!["(print true)", "(print true)", "(print false)"]);
+        assert_eq!(fold("fun f(a) { return a * (2 + 2); }"), vec!["(fun f (a) (return (* a 4)))"]);
+    }
+
+    #[test]
+    fn keep_operations_that_fail_at_runtime() {
+        assert_eq!(fold("print 1 / 0;"), vec!["(print (/ 1 0))"]);
+        assert_eq!(fold("print 5 % (1 - 1);"), vec!["(print (% 5 0))"]);
+        assert_eq!(fold("print -\"a\";"), vec!["(print (- \"a\"))"]);
+        assert_eq!(fold("print 1 + \"a\";"), vec!["(print (+ 1 \"a\"))"]);
+    }
+
+    #[test]
+    fn eliminate_dead_branches() {
+        assert_eq!(fold("if (false) { print 1; } else { print 2; }"), vec!["(block (print 2))"]);
+        assert_eq!(fold("if (1 > 2) print 1;"), vec!["(block)"]);
+        assert_eq!(fold("if (\"yes\") print 1; else print 2;"), vec!["(print 1)"]);
+        assert_eq!(fold("while (nil) { print 1; }"), vec!["(block)"]);
+        assert_eq!(fold("for (var i = 0; false; i = i + 1) print i;"), vec!["(block (var i 0) (block))"]);
+    }
+
+    #[test]
+    fn keep_branches_with_side_effecting_conditions() {
+        assert_eq!(fold("if (f()) print 1;"), vec!["(if (call f) (print 1))"]);
+        assert_eq!(fold("if (a = false) print 1;"), vec!["(if (= a false) (print 1))"]);
+        assert_eq!(fold("while (a and false) print 1;"), vec!["(while (and a false) (print 1))"]);
+        assert_eq!(fold("while (true) print 1;"), vec!["(while true (print 1))"]);
+    }
+}
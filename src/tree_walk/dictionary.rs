@@ -0,0 +1,75 @@
+use super::callable::Callable;
+use super::native_function::NativeFunction;
+use super::object::Object;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+pub type DictionaryEntries = Rc<RefCell<HashMap<DictionaryKey, Object>>>;
+
+/// Objects that can be dictionary keys: the ones compared by content.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DictionaryKey {
+    Nil,
+    Boolean(bool),
+    String(String),
+    /// Bits of the number, with -0 stored as 0 since the two are equal.
+    Number(u64),
+}
+
+impl DictionaryKey {
+    /// NaN is rejected because it is not equal to itself, so a value stored
+    /// under it could never be found again.
+    pub fn from_object(object: &Object) -> Result<Self, &'static str> {
+        match object {
+            Object::Number(number) if number.is_nan() => Err("Dictionary key can't be NaN."),
+            Object::Number(number) => Ok(DictionaryKey::Number(if *number == 0f64 { 0f64 } else { *number }.to_bits())),
+            Object::Boolean(boolean) => Ok(DictionaryKey::Boolean(*boolean)),
+            Object::Nil => Ok(DictionaryKey::Nil),
+            Object::String(string) => Ok(DictionaryKey::String(string.clone())),
+            _ => Err("Dictionary keys must be numbers, strings, booleans or nil."),
+        }
+    }
+
+    pub fn to_object(&self) -> Object {
+        match self {
+            DictionaryKey::Nil => Object::Nil,
+            DictionaryKey::Boolean(boolean) => Object::Boolean(*boolean),
+            DictionaryKey::String(string) => Object::String(string.clone()),
+            DictionaryKey::Number(bits) => Object::Number(f64::from_bits(*bits)),
+        }
+    }
+}
+
+/// Formats dictionary entries ordered by key text, so the output does not
+/// depend on the hasher.
+pub struct DisplayEntries<'a>(pub &'a HashMap<DictionaryKey, Object>);
+
+impl Display for DisplayEntries<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut entries: Vec<_> = self.0.iter()
+            .map(|(key, value)| (key.to_object().to_string(), value))
+            .collect();
+        entries.sort_by(|(left, _), (right, _)| left.cmp(right));
+        write!(f, "{{")?;
+        for (index, (key, value)) in entries.into_iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}: {}", key, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl Object {
+    pub fn make_dict_fn() -> Object {
+        let native_fn = NativeFunction {
+            arity: 0,
+            on_call: Box::new(|_, _| Ok(Object::Dictionary(Rc::new(RefCell::new(HashMap::new()))))),
+            receiver: None,
+        };
+        Object::Callable(Callable::NativeFn(native_fn))
+    }
+}
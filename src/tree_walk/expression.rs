@@ -1,13 +1,15 @@
+use super::lox_function::LoxFunction;
 use super::token::Token;
 use std::fmt::Debug;
+use std::rc::Rc;
 
 pub trait Visitor<Result> {
     fn visit_binary(&mut self, left: &Expression, operator: &Token, right: &Expression) -> Result;
     fn visit_grouping(&mut self, expression: &Expression) -> Result;
     fn visit_literal(&mut self, literal: &LiteralExpression) -> Result;
     fn visit_unary(&mut self, operator: &Token, right: &Expression) -> Result;
-    fn visit_variable(&mut self, literal: &str, token: &Token) -> Result;
-    fn visit_assignment(&mut self, token: &Token, right: &Expression) -> Result;
+    fn visit_variable(&mut self, literal: &str, token: &Token, id: usize) -> Result;
+    fn visit_assignment(&mut self, token: &Token, right: &Expression, id: usize) -> Result;
     fn visit_logical(&mut self, left: &Expression, operator: &Token, right: &Expression) -> Result;
     fn visit_call(
         &mut self,
@@ -17,18 +19,39 @@ pub trait Visitor<Result> {
     ) -> Result;
     fn visit_get(&mut self, name: &str, expression: &Expression) -> Result;
     fn visit_set(&mut self, name: &str, object: &Expression, value: &Expression) -> Result;
-    fn visit_this(&mut self, token: &Token) -> Result;
-    fn visit_super(&mut self, keyword_token: &Token, method: &str) -> Result;
+    fn visit_this(&mut self, token: &Token, id: usize) -> Result;
+    fn visit_super(&mut self, keyword_token: &Token, method: &str, id: usize) -> Result;
+    fn visit_lambda(&mut self, func: &Rc<LoxFunction>) -> Result;
+    fn visit_list(&mut self, elements: &[Expression]) -> Result;
+    fn visit_index(&mut self, object: &Expression, index: &Expression, close_bracket: &Token) -> Result;
+    fn visit_set_index(
+        &mut self,
+        object: &Expression,
+        index: &Expression,
+        value: &Expression,
+        close_bracket: &Token,
+    ) -> Result;
+    fn visit_class_expr(
+        &mut self,
+        line: u32,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: &Option<VariableExpression>,
+    ) -> Result;
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum Expression {
     Binary(Box<Expression>, Token, Box<Expression>),
     Grouping(Box<Expression>),
     Literal(LiteralExpression),
     Unary(Token, Box<Expression>),
     Variable(VariableExpression),
-    Assignment(Token, Box<Expression>),
+    Assignment {
+        token: Token,
+        value: Box<Expression>,
+        id: usize,
+    },
     Logical(Box<Expression>, Token, Box<Expression>),
     Call {
         callee: Box<Expression>,
@@ -44,11 +67,34 @@ pub enum Expression {
         object: Box<Expression>,
         value: Box<Expression>
     },
-    This(Token),
+    This {
+        token: Token,
+        id: usize,
+    },
     Super {
         keyword_token: Token,
-        method: String
-    }
+        method: String,
+        id: usize,
+    },
+    Function(Rc<LoxFunction>),
+    List(Vec<Expression>),
+    Index {
+        object: Box<Expression>,
+        index: Box<Expression>,
+        close_bracket: Token,
+    },
+    SetIndex {
+        object: Box<Expression>,
+        index: Box<Expression>,
+        value: Box<Expression>,
+        close_bracket: Token,
+    },
+    ClassExpr {
+        line: u32,
+        methods: Vec<Rc<LoxFunction>>,
+        static_methods: Vec<Rc<LoxFunction>>,
+        superclass: Option<VariableExpression>,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -60,10 +106,94 @@ pub enum LiteralExpression {
     Number(f64),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+/// `id` identifies the expression for variable resolution. The parser
+/// assigns it, so it does not depend on how the source was scanned.
+#[derive(Debug, Clone)]
 pub struct VariableExpression {
     pub name: String,
     pub token: Token,
+    pub id: usize,
+}
+
+impl PartialEq for VariableExpression {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.token == other.token
+    }
+}
+
+/// Compares structure only: ids are unique per parsed node, so they would
+/// make any two separately parsed expressions unequal.
+impl PartialEq for Expression {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Expression::Binary(left, operator, right), Expression::Binary(other_left, other_operator, other_right))
+            | (Expression::Logical(left, operator, right), Expression::Logical(other_left, other_operator, other_right)) => {
+                left == other_left && operator == other_operator && right == other_right
+            }
+            (Expression::Grouping(expression), Expression::Grouping(other_expression)) => expression == other_expression,
+            (Expression::Literal(literal), Expression::Literal(other_literal)) => literal == other_literal,
+            (Expression::Unary(operator, right), Expression::Unary(other_operator, other_right)) => {
+                operator == other_operator && right == other_right
+            }
+            (Expression::Variable(variable), Expression::Variable(other_variable)) => variable == other_variable,
+            (
+                Expression::Assignment { token, value, .. },
+                Expression::Assignment { token: other_token, value: other_value, .. },
+            ) => token == other_token && value == other_value,
+            (
+                Expression::Call { callee, close_paren, arguments },
+                Expression::Call { callee: other_callee, close_paren: other_close_paren, arguments: other_arguments },
+            ) => callee == other_callee && close_paren == other_close_paren && arguments == other_arguments,
+            (
+                Expression::Get { name, expression },
+                Expression::Get { name: other_name, expression: other_expression },
+            ) => name == other_name && expression == other_expression,
+            (
+                Expression::Set { name, object, value },
+                Expression::Set { name: other_name, object: other_object, value: other_value },
+            ) => name == other_name && object == other_object && value == other_value,
+            (Expression::This { token, .. }, Expression::This { token: other_token, .. }) => token == other_token,
+            (
+                Expression::Super { keyword_token, method, .. },
+                Expression::Super { keyword_token: other_keyword_token, method: other_method, .. },
+            ) => keyword_token == other_keyword_token && method == other_method,
+            (Expression::Function(func), Expression::Function(other_func)) => func == other_func,
+            (Expression::List(elements), Expression::List(other_elements)) => elements == other_elements,
+            (
+                Expression::Index { object, index, close_bracket },
+                Expression::Index { object: other_object, index: other_index, close_bracket: other_close_bracket },
+            ) => object == other_object && index == other_index && close_bracket == other_close_bracket,
+            (
+                Expression::SetIndex { object, index, value, close_bracket },
+                Expression::SetIndex {
+                    object: other_object,
+                    index: other_index,
+                    value: other_value,
+                    close_bracket: other_close_bracket,
+                },
+            ) => {
+                object == other_object
+                    && index == other_index
+                    && value == other_value
+                    && close_bracket == other_close_bracket
+            }
+            (
+                Expression::ClassExpr { line, methods, static_methods, superclass },
+                Expression::ClassExpr {
+                    line: other_line,
+                    methods: other_methods,
+                    static_methods: other_static_methods,
+                    superclass: other_superclass,
+                },
+            ) => {
+                line == other_line
+                    && methods == other_methods
+                    && static_methods == other_static_methods
+                    && superclass == other_superclass
+            }
+            _ => false,
+        }
+    }
 }
 
 impl Expression {
@@ -75,8 +205,8 @@ impl Expression {
             Expression::Grouping(expression) => visitor.visit_grouping(expression),
             Expression::Literal(literal) => visitor.visit_literal(literal),
             Expression::Unary(operator, right) => visitor.visit_unary(operator, right),
-            Expression::Variable(expr) => visitor.visit_variable(&expr.name, &expr.token),
-            Expression::Assignment(token, expr) => visitor.visit_assignment(token, expr),
+            Expression::Variable(expr) => visitor.visit_variable(&expr.name, &expr.token, expr.id),
+            Expression::Assignment { token, value, id } => visitor.visit_assignment(token, value, *id),
             Expression::Logical(left, token, right) => visitor.visit_logical(left, token, right),
             Expression::Call {
                 callee,
@@ -85,8 +215,72 @@ impl Expression {
             } => visitor.visit_call(callee, close_paren, arguments),
             Expression::Get { name, expression } => visitor.visit_get(name, expression),
             Expression::Set { name, object, value } => visitor.visit_set(name, object, value),
-            Expression::This(token) => visitor.visit_this(token),
-            Expression::Super { keyword_token, method } => visitor.visit_super(keyword_token, method),
+            Expression::This { token, id } => visitor.visit_this(token, *id),
+            Expression::Super { keyword_token, method, id } => visitor.visit_super(keyword_token, method, *id),
+            Expression::Function(func) => visitor.visit_lambda(func),
+            Expression::List(elements) => visitor.visit_list(elements),
+            Expression::Index { object, index, close_bracket } => visitor.visit_index(object, index, close_bracket),
+            Expression::SetIndex { object, index, value, close_bracket } => {
+                visitor.visit_set_index(object, index, value, close_bracket)
+            }
+            Expression::ClassExpr { line, methods, static_methods, superclass } => {
+                visitor.visit_class_expr(*line, methods, static_methods, superclass)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ast_printer::AstPrinter;
+    use super::super::parser::Parser;
+    use super::super::scanner::Scanner;
+    use super::super::statement::Statement;
+    use super::super::token::Token;
+    use super::super::token_type::{LiteralTokenType, SingleCharTokenType, TokenType};
+    use super::{Expression, LiteralExpression, VariableExpression};
+
+    #[test]
+    fn clone_nested_expression() {
+        let tokens = Scanner::new("print (1 + 2) * -point.scale(factor, \"x\"); fun f(a) { return a; }").scan_tokens();
+        let statements = Parser::new(&tokens).parse();
+        let expression = match &statements[0] {
+            Statement::Print(expression) => expression,
+            statement => panic!("Expected print statement, got {:?}", statement),
+        };
+        assert_eq!(expression.clone(), *expression);
+
+        for statement in &statements {
+            assert_eq!(AstPrinter.print_statement(&statement.clone()), AstPrinter.print_statement(statement));
         }
     }
+
+    #[test]
+    fn equality_ignores_expression_ids() {
+        let make_expression = |first_id, second_id| {
+            let variable = |name: &str, id| Expression::Variable(VariableExpression {
+                name: name.to_string(),
+                token: Token::new(TokenType::Literal(LiteralTokenType::Identifier(name.to_string())), name.to_string(), 1),
+                id,
+            });
+            Expression::Binary(
+                Box::new(variable("a", first_id)),
+                Token::new_single_char(TokenType::SingleChar(SingleCharTokenType::Plus), '+', 1),
+                Box::new(Expression::Grouping(Box::new(variable("b", second_id)))),
+            )
+        };
+        assert_eq!(make_expression(0, 2), make_expression(10, 42));
+        assert_ne!(make_expression(0, 2), Expression::Literal(LiteralExpression::Nil));
+
+        let print_expression = |source| {
+            let tokens = Scanner::new(source).scan_tokens();
+            match Parser::new(&tokens).parse().pop() {
+                Some(Statement::Print(expression)) => expression,
+                statement => panic!("Expected print statement, got {:?}", statement),
+            }
+        };
+        assert_eq!(print_expression("print a + f(b);"), print_expression("var x = 1; print a + f(b);"));
+        assert_ne!(print_expression("print a + f(b);"), print_expression("print a + f(c);"));
+        assert_ne!(print_expression("print a = 1;"), print_expression("print a.b = 1;"));
+    }
 }
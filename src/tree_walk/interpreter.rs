@@ -1,17 +1,19 @@
 use super::callable::{LoxFn, Callable};
 use super::environment::Environment;
-use super::error::{Error, InterpreterError};
+use super::dictionary::DictionaryKey;
+use super::error::InterpreterError;
 use super::expression::{self, Expression, LiteralExpression, VariableExpression, Visitor};
 use super::lox_function::LoxFunction;
-use super::object::Object;
+use super::object::{ListElements, Object};
 use super::statement::{self, Statement};
 use super::token::Token;
 use super::token_type::{
     ExpressionOperatorTokenType, KeywordTokenType, SingleCharTokenType, TokenType,
 };
-use super::lox_class::{CONSTRUCTOR_KEYWORD, LoxClass, SUPER_KEYWORD, THIS_KEYWORD};
+use super::lox_class::{ANONYMOUS_CLASS_NAME, CONSTRUCTOR_KEYWORD, Instance, LoxClass, SUPER_KEYWORD, THIS_KEYWORD, TO_STRING_METHOD};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{self, Write};
 use std::rc::Rc;
 use std::result;
 
@@ -19,11 +21,14 @@ pub struct Interpreter {
     pub globals: Rc<RefCell<Environment>>,
     pub environment: Rc<RefCell<Environment>>,
     pub locals: HashMap<usize, usize>,
+    output: Box<dyn Write>,
 }
 
 pub enum InterpretedValue {
     Some(Object),
     Return(Object),
+    Break,
+    Continue,
     None,
 }
 
@@ -32,26 +37,32 @@ type ExprInterpretResult = Result<Object, InterpreterError>;
 
 impl Interpreter {
     pub fn new() -> Self {
+        Interpreter::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
         let globals = Interpreter::make_globals();
         let globals = Rc::new(RefCell::new(globals));
         Self {
             globals: globals.clone(),
             environment: globals,
             locals: HashMap::new(),
+            output,
         }
     }
 
-    pub fn interpret(&mut self, statements: &[Statement]) {
+    /// Executes statements in order and stops at the first runtime error.
+    pub fn interpret(&mut self, statements: &[Statement]) -> Result<(), InterpreterError> {
         for statement in statements {
-            if let Err(error) = statement.accept(self) {
-                eprintln!("{}", error.description());
-            }
+            statement.accept(self)?;
         }
+        Ok(())
     }
 
     fn make_globals() -> Environment {
         let mut env = Environment::new();
         env.define("clock".to_string(), Object::make_clock_fn());
+        env.define("dict".to_string(), Object::make_dict_fn());
         env
     }
 
@@ -68,9 +79,9 @@ impl Interpreter {
                     self.environment = previous_env;
                     return Err(error);
                 },
-                Ok(InterpretedValue::Return(stmt)) => {
+                Ok(value @ (InterpretedValue::Return(_) | InterpretedValue::Break | InterpretedValue::Continue)) => {
                     self.environment = previous_env;
-                    return Ok(InterpretedValue::Some(stmt));
+                    return Ok(value);
                 }
                 _ => {}
             }
@@ -84,7 +95,7 @@ impl statement::Visitor<StmtInterpretResult> for Interpreter {
     fn visit_print(&mut self, expression: &Expression) -> StmtInterpretResult {
         match expression.accept(self) {
             Ok(object) => {
-                println!("{}", object);
+                writeln!(self.output, "{}", object).expect("Failed to write to output");
                 Ok(InterpretedValue::None)
             }
             Err(err) => Err(err),
@@ -95,7 +106,7 @@ impl statement::Visitor<StmtInterpretResult> for Interpreter {
         expression.accept(self).map(InterpretedValue::Some)
     }
 
-    fn visit_variable_stmt(&mut self, name: &str, value: &Option<Expression>) -> StmtInterpretResult {
+    fn visit_variable_stmt(&mut self, name: &str, value: &Option<Expression>, _line: u32) -> StmtInterpretResult {
         let object = value
             .as_ref()
             .map(|expr| expr.accept(self))
@@ -129,15 +140,26 @@ impl statement::Visitor<StmtInterpretResult> for Interpreter {
         }
     }
 
-    fn visit_while(&mut self, condition: &Expression, body: &Statement) -> StmtInterpretResult {
-        loop {
-            let condition = condition.accept(self)?;
-            if condition.is_truthy() {
-                body.accept(self)?;
-            } else {
-                return Ok(InterpretedValue::None);
+    fn visit_while(&mut self, condition: &Expression, body: &Statement, increment: &Option<Expression>) -> StmtInterpretResult {
+        while condition.accept(self)?.is_truthy() {
+            match body.accept(self)? {
+                InterpretedValue::Break => break,
+                value @ InterpretedValue::Return(_) => return Ok(value),
+                _ => {}
+            }
+            if let Some(increment) = increment {
+                increment.accept(self)?;
             }
         }
+        Ok(InterpretedValue::None)
+    }
+
+    fn visit_break(&mut self, _line: u32) -> StmtInterpretResult {
+        Ok(InterpretedValue::Break)
+    }
+
+    fn visit_continue(&mut self, _line: u32) -> StmtInterpretResult {
+        Ok(InterpretedValue::Continue)
     }
 
     fn visit_function(&mut self, func: Rc<LoxFunction>) -> StmtInterpretResult {
@@ -161,6 +183,7 @@ impl statement::Visitor<StmtInterpretResult> for Interpreter {
     fn visit_class(
         &mut self,
         name: &str,
+        _line: u32,
         methods: &[Rc<LoxFunction>],
         static_methods: &[Rc<LoxFunction>],
         superclass: &Option<VariableExpression>
@@ -171,37 +194,19 @@ impl statement::Visitor<StmtInterpretResult> for Interpreter {
             None
         };
 
-        let mut env = self.environment.as_ref().borrow_mut();
-        env.define(name.to_string(), Object::Nil);
+        self.environment.as_ref().borrow_mut().define(name.to_string(), Object::Nil);
+        let class = self.make_class(name, methods, static_methods, superclass);
+        let class_object = Object::Callable(Callable::LoxClass(class));
 
-        let methods_env = if let Some(expr) = superclass.as_ref() {
-            let mut env = Environment::from(self.environment.clone());
-            let class_object = Object::Callable(Callable::LoxClass(expr.clone()));
-            env.define(SUPER_KEYWORD.to_string(), class_object);
-            Rc::new(RefCell::new(env))
-        } else {
-            self.environment.clone()
-        };
-        let is_new_env_added = superclass.as_ref().map(|_| true).unwrap_or(false);
-
-        let methods = Interpreter::make_methods_map(&methods_env, methods);
-        let static_methods = Interpreter::make_methods_map(&self.environment, static_methods);
-
-        let class = LoxClass {
-            name: name.to_string(),
-            methods,
-            static_methods,
-            superclass
-        };
-        let class_object = Object::Callable(Callable::LoxClass(Rc::new(class)));
-
-        env.assign(name.to_string(), class_object).map_err(|err_msg| {
-            InterpreterError::new(0, err_msg) // TODO: pass real line
-        })?;
-        drop(env);
-        if is_new_env_added {
-            self.environment = methods_env.as_ref().borrow().enclosing.as_ref().unwrap().clone();
-        }
+        // The borrow is scoped to the assignment so nothing holds the environment
+        // while methods that reference the class are later called.
+        self.environment
+            .as_ref()
+            .borrow_mut()
+            .assign(name.to_string(), class_object)
+            .map_err(|err_msg| {
+                InterpreterError::new(0, err_msg) // TODO: pass real line
+            })?;
         Ok(InterpretedValue::None)
     }
 }
@@ -213,8 +218,22 @@ impl expression::Visitor<ExprInterpretResult> for Interpreter {
         operator: &Token,
         right: &Expression,
     ) -> ExprInterpretResult {
-        let left = left.accept(self)?;
-        let right = right.accept(self)?;
+        let mut left = left.accept(self)?;
+        let mut right = right.accept(self)?;
+        if operator.token_type == TokenType::SingleChar(SingleCharTokenType::Plus) {
+            match (&left, &right) {
+                (Object::String(_), Object::Instance(_)) => right = self.concatenation_operand(right)?,
+                (Object::Instance(instance), Object::String(_)) if !Interpreter::has_method(instance, "plus") => {
+                    left = self.concatenation_operand(left)?
+                }
+                _ => {}
+            }
+        }
+        if let Object::Instance(instance) = &left {
+            if let Some(method_name) = Interpreter::operator_method_name(&operator.token_type) {
+                return self.call_operator_method(instance, method_name, operator, right);
+            }
+        }
         match operator.token_type {
             TokenType::SingleChar(ref token_type) => {
                 let result = self.apply_single_char_binary_operation(token_type, &left, &right);
@@ -262,8 +281,8 @@ impl expression::Visitor<ExprInterpretResult> for Interpreter {
         }
     }
 
-    fn visit_variable(&mut self, literal: &str, token: &Token) -> ExprInterpretResult {
-        let result = match self.locals.get(&token.id) {
+    fn visit_variable(&mut self, literal: &str, token: &Token, id: usize) -> ExprInterpretResult {
+        let result = match self.locals.get(&id) {
             Some(distance) => self
                 .environment
                 .borrow()
@@ -273,10 +292,10 @@ impl expression::Visitor<ExprInterpretResult> for Interpreter {
         result.map_err(|message| InterpreterError::new(token.line as usize, message))
     }
 
-    fn visit_assignment(&mut self, token: &Token, right: &Expression) -> ExprInterpretResult {
+    fn visit_assignment(&mut self, token: &Token, right: &Expression, id: usize) -> ExprInterpretResult {
         let object = right.accept(self)?;
         let name: String = token.lexeme.iter().collect();
-        let result = match self.locals.get(&token.id) {
+        let result = match self.locals.get(&id) {
             Some(distance) => self.environment.as_ref().borrow_mut().assign_at_distance(
                 *distance,
                 name,
@@ -317,7 +336,7 @@ impl expression::Visitor<ExprInterpretResult> for Interpreter {
             let arg_len = arguments.len();
             let arity = callable.arity();
             if arity != arg_len {
-                let message = format!("Expected {} arguments but got {}", arity, arg_len);
+                let message = format!("Expected {} arguments but got {}.", arity, arg_len);
                 return Err(InterpreterError::new_from_token(close_paren, message));
             }
             let mut obj_arguments = Vec::with_capacity(arg_len);
@@ -337,11 +356,10 @@ impl expression::Visitor<ExprInterpretResult> for Interpreter {
     fn visit_get(&mut self, name: &str, expression: &Expression) -> ExprInterpretResult {
         match expression.accept(self)? {
             Object::Instance(instance) => {
-                let borrowed_instance = instance.as_ref().borrow();
-                let object = borrowed_instance.get(name, instance.clone()).map_err(|err_msg| {
+                let object = instance.as_ref().borrow().get(name, instance.clone()).map_err(|err_msg| {
                     InterpreterError::new(0, err_msg) // TODO: pass real line number
                 })?;
-                Ok(object)
+                self.call_if_getter(object)
             },
             Object::Callable(Callable::LoxClass(class)) => {
                 let class = class.as_ref();
@@ -350,6 +368,11 @@ impl expression::Visitor<ExprInterpretResult> for Interpreter {
                 })?;
                 Ok(Object::Callable(Callable::LoxFn(lox_fn.clone())))
             }
+            Object::List(elements) => {
+                Object::make_list_method(name, &elements).ok_or_else(|| {
+                    InterpreterError::new(0, format!("Undefined property '{}'.", name)) // TODO: pass real line number
+                })
+            }
             _ => {
                 // TODO: pass real line number
                 Err(InterpreterError::new(0, "Only instances have properties.".to_string()))
@@ -368,12 +391,12 @@ impl expression::Visitor<ExprInterpretResult> for Interpreter {
         }
     }
 
-    fn visit_this(&mut self, token: &Token) -> ExprInterpretResult {
-        self.visit_variable("this", token)
+    fn visit_this(&mut self, token: &Token, id: usize) -> ExprInterpretResult {
+        self.visit_variable("this", token, id)
     }
 
-    fn visit_super(&mut self, keyword_token: &Token, method: &str) -> ExprInterpretResult {
-        let distance = self.locals.get(&keyword_token.id).unwrap();
+    fn visit_super(&mut self, keyword_token: &Token, method: &str, id: usize) -> ExprInterpretResult {
+        let distance = self.locals.get(&id).unwrap();
         let object = self.environment
             .as_ref()
             .borrow()
@@ -390,11 +413,84 @@ impl expression::Visitor<ExprInterpretResult> for Interpreter {
             },
             _ => None
         };
-        lox_fn
+        let object = lox_fn
             .map(|lox_fn| Object::Callable(Callable::LoxFn(lox_fn)))
             .ok_or_else(|| {
                 InterpreterError::new_from_token(keyword_token, format!("Undefined property {}.", method))
-            })
+            })?;
+        self.call_if_getter(object)
+    }
+
+    fn visit_lambda(&mut self, func: &Rc<LoxFunction>) -> ExprInterpretResult {
+        Ok(Object::Callable(Callable::LoxFn(LoxFn {
+            declaration: Rc::clone(func),
+            closure: self.environment.clone(),
+            is_initializer: false
+        })))
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> ExprInterpretResult {
+        let elements = elements
+            .iter()
+            .map(|element| element.accept(self))
+            .collect::<Result<Vec<Object>, InterpreterError>>()?;
+        Ok(Object::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_index(&mut self, object: &Expression, index: &Expression, close_bracket: &Token) -> ExprInterpretResult {
+        let object = object.accept(self)?;
+        let index = index.accept(self)?;
+        if let Object::String(string) = object {
+            // Strings are indexed by code point and yield one-character strings.
+            let index = Interpreter::index_number(index, close_bracket)?;
+            return string.chars().nth(index)
+                .map(|character| Object::String(character.to_string()))
+                .ok_or_else(|| InterpreterError::new_from_static_str(close_bracket, "Index out of bounds."));
+        }
+        if let Object::Dictionary(entries) = object {
+            let key = Interpreter::dictionary_key(&index, close_bracket)?;
+            return Ok(entries.as_ref().borrow().get(&key).cloned().unwrap_or(Object::Nil));
+        }
+        let (elements, index) = Interpreter::list_index(object, index, close_bracket)?;
+        let element = elements.as_ref().borrow()[index].clone();
+        Ok(element)
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Expression,
+        index: &Expression,
+        value: &Expression,
+        close_bracket: &Token,
+    ) -> ExprInterpretResult {
+        let object = object.accept(self)?;
+        let index = index.accept(self)?;
+        if let Object::Dictionary(entries) = object {
+            let key = Interpreter::dictionary_key(&index, close_bracket)?;
+            let value = value.accept(self)?;
+            entries.as_ref().borrow_mut().insert(key, value.clone());
+            return Ok(value);
+        }
+        let (elements, index) = Interpreter::list_index(object, index, close_bracket)?;
+        let value = value.accept(self)?;
+        elements.as_ref().borrow_mut()[index] = value.clone();
+        Ok(value)
+    }
+
+    fn visit_class_expr(
+        &mut self,
+        _line: u32,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: &Option<VariableExpression>,
+    ) -> ExprInterpretResult {
+        let superclass = if let Some(expr) = superclass {
+            Some(self.visit_superclass(expr)?)
+        } else {
+            None
+        };
+        let class = self.make_class(ANONYMOUS_CLASS_NAME, methods, static_methods, superclass);
+        Ok(Object::Callable(Callable::LoxClass(class)))
     }
 }
 
@@ -403,6 +499,33 @@ impl Interpreter {
         self.locals.insert(expression_id, depth);
     }
 
+    fn list_index(
+        object: Object,
+        index: Object,
+        close_bracket: &Token,
+    ) -> result::Result<(ListElements, usize), InterpreterError> {
+        let elements = match object {
+            Object::List(elements) => elements,
+            _ => return Err(InterpreterError::new_from_static_str(close_bracket, "Only lists can be indexed.")),
+        };
+        let index = Interpreter::index_number(index, close_bracket)?;
+        if index >= elements.as_ref().borrow().len() {
+            return Err(InterpreterError::new_from_static_str(close_bracket, "Index out of bounds."));
+        }
+        Ok((elements, index))
+    }
+
+    fn dictionary_key(index: &Object, close_bracket: &Token) -> result::Result<DictionaryKey, InterpreterError> {
+        DictionaryKey::from_object(index).map_err(|message| InterpreterError::new_from_static_str(close_bracket, message))
+    }
+
+    fn index_number(index: Object, close_bracket: &Token) -> result::Result<usize, InterpreterError> {
+        match index {
+            Object::Number(index) if index >= 0f64 && index.fract() == 0f64 => Ok(index as usize),
+            _ => Err(InterpreterError::new_from_static_str(close_bracket, "Index must be a non-negative integer.")),
+        }
+    }
+
     fn apply_single_char_binary_operation(
         &self,
         single_char_token_type: &SingleCharTokenType,
@@ -423,6 +546,13 @@ impl Interpreter {
             (SingleCharTokenType::Star, Object::Number(left), Object::Number(right)) => {
                 Ok(Object::Number(left * right))
             }
+            (SingleCharTokenType::Percent, Object::Number(left), Object::Number(right)) => {
+                if *right == 0f64 {
+                    Err("Division by zero")
+                } else {
+                    Ok(Object::Number(left % right))
+                }
+            }
             (SingleCharTokenType::Plus, Object::Number(left), Object::Number(right)) => {
                 Ok(Object::Number(left + right))
             }
@@ -470,8 +600,73 @@ impl Interpreter {
         }
     }
 
+    /// Name of the method an instance's class defines to overload `operator`.
+    /// Equality is not overloadable and keeps comparing instances by identity.
+    fn operator_method_name(operator: &TokenType) -> Option<&'static str> {
+        let name = match operator {
+            TokenType::SingleChar(SingleCharTokenType::Plus) => "plus",
+            TokenType::SingleChar(SingleCharTokenType::Minus) => "minus",
+            TokenType::SingleChar(SingleCharTokenType::Star) => "times",
+            TokenType::SingleChar(SingleCharTokenType::Slash) => "divide",
+            TokenType::SingleChar(SingleCharTokenType::Percent) => "modulo",
+            TokenType::ExpressionOperator(ExpressionOperatorTokenType::Greater) => "greater",
+            TokenType::ExpressionOperator(ExpressionOperatorTokenType::GreaterEqual) => "greaterEqual",
+            TokenType::ExpressionOperator(ExpressionOperatorTokenType::Less) => "less",
+            TokenType::ExpressionOperator(ExpressionOperatorTokenType::LessEqual) => "lessEqual",
+            _ => return None,
+        };
+        Some(name)
+    }
+
+    fn call_operator_method(
+        &mut self,
+        instance: &Rc<RefCell<Instance>>,
+        method_name: &str,
+        operator: &Token,
+        right: Object,
+    ) -> ExprInterpretResult {
+        let method = instance.as_ref().borrow().find_method(method_name, instance.clone());
+        match method {
+            Some(Object::Callable(callable)) if callable.arity() == 1 => callable.call(self, &[right]),
+            Some(_) => {
+                let message = format!("Operator method '{}' must take exactly one argument.", method_name);
+                Err(InterpreterError::new_from_token(operator, message))
+            }
+            None => {
+                let operator_lexeme: String = operator.lexeme.iter().collect();
+                let message = format!(
+                    "Operator '{}' needs a '{}' method on {:?}.",
+                    operator_lexeme,
+                    method_name,
+                    instance.as_ref().borrow()
+                );
+                Err(InterpreterError::new_from_token(operator, message))
+            }
+        }
+    }
+
+    fn has_method(instance: &Rc<RefCell<Instance>>, name: &str) -> bool {
+        instance.as_ref().borrow().find_method(name, instance.clone()).is_some()
+    }
+
+    /// Replaces an instance concatenated with a string by the result of its
+    /// `toString` method, when the class defines one.
+    fn concatenation_operand(&mut self, object: Object) -> ExprInterpretResult {
+        let method = match &object {
+            Object::Instance(instance) => instance.as_ref().borrow().find_method(TO_STRING_METHOD, instance.clone()),
+            _ => None,
+        };
+        match method {
+            Some(Object::Callable(callable)) if callable.arity() == 0 => {
+                let string = callable.call(self, &[])?;
+                Ok(Object::String(string.to_string()))
+            }
+            _ => Ok(object),
+        }
+    }
+
     fn make_methods_map(env: &Rc<RefCell<Environment>>, methods: &[Rc<LoxFunction>]) -> HashMap<String, LoxFn> {
-        methods.into_iter().fold(HashMap::new(), |mut methods, method, | {
+        methods.iter().fold(HashMap::new(), |mut methods, method, | {
             let func = LoxFn {
                 declaration: method.clone(),
                 closure: env.clone(),
@@ -482,8 +677,17 @@ impl Interpreter {
         })
     }
 
+    fn call_if_getter(&mut self, object: Object) -> ExprInterpretResult {
+        match object {
+            Object::Callable(Callable::LoxFn(lox_fn)) if lox_fn.declaration.is_getter => {
+                Callable::LoxFn(lox_fn).call(self, &[])
+            }
+            object => Ok(object),
+        }
+    }
+
     fn visit_superclass(&mut self, expression: &VariableExpression) -> Result<Rc<LoxClass>, InterpreterError> {
-        let superclass = self.visit_variable(&expression.name, &expression.token)?;
+        let superclass = self.visit_variable(&expression.name, &expression.token, expression.id)?;
         if let Object::Callable(Callable::LoxClass(class)) = superclass {
             Ok(class.clone())
         } else {
@@ -491,6 +695,33 @@ impl Interpreter {
             Err(error)
         }
     }
+
+    fn make_class(
+        &self,
+        name: &str,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: Option<Rc<LoxClass>>
+    ) -> Rc<LoxClass> {
+        let methods_env = if let Some(expr) = superclass.as_ref() {
+            let mut env = Environment::from(self.environment.clone());
+            let class_object = Object::Callable(Callable::LoxClass(expr.clone()));
+            env.define(SUPER_KEYWORD.to_string(), class_object);
+            Rc::new(RefCell::new(env))
+        } else {
+            self.environment.clone()
+        };
+
+        let methods = Interpreter::make_methods_map(&methods_env, methods);
+        let static_methods = Interpreter::make_methods_map(&self.environment, static_methods);
+
+        Rc::new(LoxClass {
+            name: name.to_string(),
+            methods,
+            static_methods,
+            superclass
+        })
+    }
 }
 
 impl Object {
@@ -505,9 +736,14 @@ impl Object {
     fn is_equal(&self, to_object: &Object) -> bool {
         match (self, to_object) {
             (Object::Nil, Object::Nil) => true,
-            (Object::Number(left), Object::Number(right)) => (left - right).abs() == 0f64,
+            (Object::Number(left), Object::Number(right)) => left == right,
             (Object::String(left), Object::String(right)) => left.eq(right),
             (Object::Boolean(left), Object::Boolean(right)) => left == right,
+            (Object::Callable(Callable::LoxFn(left)), Object::Callable(Callable::LoxFn(right))) => {
+                left.declaration == right.declaration && Rc::ptr_eq(&left.closure, &right.closure)
+            }
+            (Object::List(left), Object::List(right)) => Rc::ptr_eq(left, right),
+            (Object::Dictionary(left), Object::Dictionary(right)) => Rc::ptr_eq(left, right),
             _ => false,
         }
     }
@@ -526,3 +762,284 @@ impl Callable {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::constant_folder::ConstantFolder;
+    use super::super::error::Error;
+    use super::super::native_function::NativeFunction;
+    use super::super::parser::Parser;
+    use super::super::resolver::Resolver;
+    use super::super::scanner::Scanner;
+    use crate::shared_output::SharedOutput;
+
+    fn printed(source: &str) -> String {
+        let tokens = Scanner::new(source).scan_tokens();
+        interpret_statements(Parser::new(&tokens).parse())
+    }
+
+    fn interpret_statements(statements: Vec<Statement>) -> String {
+        let output = SharedOutput::default();
+        let interpreter = Rc::new(RefCell::new(Interpreter::with_output(Box::new(output.clone()))));
+        let mut resolver = Resolver::new(interpreter.clone());
+        assert!(resolver.resolve_statements(&statements).is_ok());
+        let _ = interpreter.as_ref().borrow_mut().interpret(&statements);
+        output.take_string()
+    }
+
+    #[test]
+    fn native_calls_lox_function() {
+        fn apply(interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, InterpreterError> {
+            match &arguments[0] {
+                Object::Callable(callable) => callable.call(interpreter, &arguments[1..]),
+                _ => Err(InterpreterError::new(0, "Expected a function.".to_string())),
+            }
+        }
+
+        let source = "fun double(x) { return x * 2; } print apply(double, 21);";
+        let statements = Parser::new(&Scanner::new(source).scan_tokens()).parse();
+        let output = SharedOutput::default();
+        let interpreter = Rc::new(RefCell::new(Interpreter::with_output(Box::new(output.clone()))));
+        let apply = NativeFunction { arity: 2, on_call: Box::new(apply), receiver: None };
+        interpreter.as_ref().borrow().globals.borrow_mut()
+            .define("apply".to_string(), Object::Callable(Callable::NativeFn(apply)));
+        let mut resolver = Resolver::new(interpreter.clone());
+        assert!(resolver.resolve_statements(&statements).is_ok());
+        assert!(interpreter.as_ref().borrow_mut().interpret(&statements).is_ok());
+        assert_eq!(output.take_string(), "42\n");
+    }
+
+    #[test]
+    fn constant_folding_keeps_results() {
+        let sources = [
+            "print 2 + 3 * 4;",
+            "print -(1 - 3) / 4 >= 0.5;",
+            "fun f(a) { return a * (2 + 2); } print f(3) + 10 % 4;",
+            "print !(1 == 2) and \"a\" + 1;",
+        ];
+        for source in sources {
+            let tokens = Scanner::new(source).scan_tokens();
+            let statements = Parser::new(&tokens).parse();
+            let folded = ConstantFolder.fold_statements(&statements);
+            assert_eq!(interpret_statements(folded), printed(source), "{}", source);
+        }
+        assert_eq!(runtime_error("print 1 / 0;"), Some("Division by zero".to_string()));
+    }
+
+    fn runtime_error(source: &str) -> Option<String> {
+        let tokens = Scanner::new(source).scan_tokens();
+        let statements = Parser::new(&tokens).parse();
+        let interpreter = Rc::new(RefCell::new(Interpreter::with_output(Box::new(SharedOutput::default()))));
+        let mut resolver = Resolver::new(interpreter.clone());
+        assert!(resolver.resolve_statements(&statements).is_ok());
+        let mut interpreter = interpreter.as_ref().borrow_mut();
+        statements
+            .iter()
+            .find_map(|statement| statement.accept(&mut *interpreter).err())
+            .map(|error| error.message().to_string())
+    }
+
+    #[test]
+    fn property_access_on_number() {
+        assert_eq!(runtime_error("print 5.foo;"), Some("Only instances have properties.".to_string()));
+    }
+
+    #[test]
+    fn functions_compare_by_identity() {
+        assert_eq!(printed("fun f() {} print f == f; var g = f; print g == f;"), "true\ntrue\n");
+        assert_eq!(printed("fun f() {} fun g() {} print f == g;"), "false\n");
+        assert_eq!(printed("fun make() { fun f() {} return f; } print make() == make();"), "false\n");
+    }
+
+    #[test]
+    fn stored_function_equals_itself() {
+        let source = "class Box {} fun f() {} var box = Box(); box.value = f; print box.value == f;";
+        assert_eq!(printed(source), "true\n");
+    }
+
+    #[test]
+    fn method_instantiates_own_class() {
+        let source = "class Node { init(depth) { this.depth = depth; } \
+            child() { return Node(this.depth + 1); } } \
+            print Node(0).child().child().depth;";
+        assert_eq!(printed(source), "2\n");
+        let source = "class Base {} class Node < Base { make() { return Node(); } } \
+            fun build() { return Node().make(); } print build().make();";
+        assert_eq!(printed(source), "Node instance\n");
+    }
+
+    #[test]
+    fn getters() {
+        let source = "class Circle { init(radius) { this.radius = radius; } \
+            area { return 3 * this.radius * this.radius; } } \
+            print Circle(2).area;";
+        assert_eq!(printed(source), "12\n");
+        let source = "class Counter { init() { this.count = 0; } next { this.count = this.count + 1; return this.count; } } \
+            var counter = Counter(); counter.next; print counter.next;";
+        assert_eq!(printed(source), "2\n");
+    }
+
+    #[test]
+    fn super_getter() {
+        let source = "class Shape { init() { this.sides = 3; } name { return \"shape\"; } sideCount { return this.sides; } } \
+            class Square < Shape { init() { super.init(); this.sides = 4; } \
+            name { return \"square \" + super.name; } sideCount { return super.sideCount * 10; } } \
+            var square = Square(); print square.name; print square.sideCount;";
+        assert_eq!(printed(source), "square shape\n40\n");
+    }
+
+    #[test]
+    fn modulo() {
+        assert_eq!(printed("print 7 % 3; print 7.5 % 2; print 2 + 7 % 4 * 2;"), "1\n1.5\n8\n");
+        assert_eq!(printed("print \"a\" % 2;"), "");
+        assert_eq!(printed("print 1 % 0;"), "");
+    }
+
+    #[test]
+    fn logical_operators_evaluate_operands_once() {
+        assert_eq!(printed("print nil or \"default\"; print \"a\" and \"b\";"), "default\nb\n");
+        let source = "var calls = 0; \
+            fun count(value) { calls = calls + 1; return value; } \
+            print count(nil) or count(\"default\"); print calls; \
+            print count(\"a\") and count(\"b\"); print calls; \
+            print count(\"c\") or count(\"d\"); print calls; \
+            print count(false) and count(\"e\"); print calls;";
+        assert_eq!(printed(source), "default\n2\nb\n4\nc\n5\nfalse\n6\n");
+    }
+
+    #[test]
+    fn break_and_continue() {
+        let source = "var i = 0; while (true) { i = i + 1; if (i == 2) continue; if (i > 4) break; print i; } print i;";
+        assert_eq!(printed(source), "1\n3\n4\n5\n");
+        let source = "for (var i = 0; i < 6; i = i + 1) { if (i == 1) continue; { if (i == 4) break; } print i; }";
+        assert_eq!(printed(source), "0\n2\n3\n");
+        let source = "var i = 0; while (i < 2) { i = i + 1; var j = 0; while (true) { j = j + 1; if (j == 2) break; } print i + j; }";
+        assert_eq!(printed(source), "3\n4\n");
+    }
+
+    #[test]
+    fn return_from_nested_statements() {
+        let source = "fun f() { { return 1; } print \"unreachable\"; } print f(); \
+            fun g() { var i = 0; while (i < 3) { if (i == 1) return i; i = i + 1; } return 9; } print g(); \
+            fun h() { for (var i = 0; ; i = i + 1) { if (i == 2) return i; } } print h();";
+        assert_eq!(printed(source), "1\n1\n2\n");
+    }
+
+    #[test]
+    fn resolution_does_not_depend_on_scanning() {
+        let resolved_locals = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens();
+            let statements = Parser::new(&tokens).parse();
+            let interpreter = Rc::new(RefCell::new(Interpreter::with_output(Box::new(SharedOutput::default()))));
+            assert!(Resolver::new(interpreter.clone()).resolve_statements(&statements).is_ok());
+            let mut locals: Vec<(usize, usize)> = interpreter.as_ref().borrow().locals.iter()
+                .map(|(id, depth)| (*id, *depth))
+                .collect();
+            locals.sort();
+            locals
+        };
+        let source = "fun f(a) { var b = a; { b = b + 1; return b; } }";
+        let spaced = "// counter\nfun   f ( a )\n{\n  var b = a; // copy\n  {\n    b = b + 1;\n    return b;\n  }\n}\n";
+        assert!(!resolved_locals(source).is_empty());
+        assert_eq!(resolved_locals(source), resolved_locals(spaced));
+        assert_eq!(printed(&format!("{} print f(1);", spaced)), "2\n");
+    }
+
+    #[test]
+    fn lambdas() {
+        assert_eq!(printed("print (fun (x) { return x*x; })(4);"), "16\n");
+        let source = "var f = fun (a) { return a + 1; }; print f(1); print f; \
+            fun counter() { var count = 0; return fun () { count = count + 1; return count; }; } \
+            var next = counter(); next(); print next(); \
+            fun apply(g, value) { return g(value); } print apply(fun (x) { return x * 2; }, 5); \
+            fun (x) { print x; }(\"statement\");";
+        assert_eq!(printed(source), "2\n<fn lambda>\n2\n10\nstatement\n");
+    }
+
+    #[test]
+    fn lists() {
+        let source = "var list = [1, \"two\", nil, []]; print list; print list[1]; \
+            list[0] = list[0] + 10; list[3] = [3]; print list; print list[3][0]; \
+            var alias = list; alias[2] = true; print list[2]; print alias == list; print [] == [];";
+        assert_eq!(printed(source), "[1, two, nil, []]\ntwo\n[11, two, nil, [3]]\n3\ntrue\ntrue\nfalse\n");
+    }
+
+    #[test]
+    fn list_methods() {
+        let source = "var list = [1]; list.push(2); list.push(list.length()); print list; \
+            print list.pop(); print list; var push = list.push; push(nil); print list.length(); \
+            fun add(list) { list.push(\"x\"); } add(list); print list;";
+        assert_eq!(printed(source), "[1, 2, 2]\n2\n[1, 2]\n3\n[1, 2, nil, x]\n");
+        assert_eq!(runtime_error("[].pop();"), Some("Can't pop from an empty list.".to_string()));
+        assert_eq!(runtime_error("[].missing();"), Some("Undefined property 'missing'.".to_string()));
+        assert_eq!(runtime_error("[].push();"), Some("Expected 1 arguments but got 0.".to_string()));
+    }
+
+    #[test]
+    fn strings_index_by_code_point() {
+        let source = "var s = \"h\u{e9}llo\"; print s[1]; \
+            var copy = \"\"; for (var i = 0; i < 5; i = i + 1) copy = copy + s[i]; print copy == s;";
+        assert_eq!(printed(source), "\u{e9}\ntrue\n");
+        assert_eq!(runtime_error("print \"h\u{e9}llo\"[5];"), Some("Index out of bounds.".to_string()));
+    }
+
+    #[test]
+    fn class_expressions() {
+        let source = "var Widget = class { init(size) { this.size = size; } draw() { return \"w\" + this.size; } }; \
+            var widget = Widget(\"1\"); print widget.draw(); print Widget; print widget; \
+            class Base { name() { return \"base\"; } } \
+            var Derived = class < Base { name() { return \"derived of \" + super.name(); } }; \
+            print Derived().name(); print (class { class make() { return 2; } }).make();";
+        assert_eq!(printed(source), "w1\nanonymous\nanonymous instance\nderived of base\n2\n");
+    }
+
+    #[test]
+    fn operator_overloading() {
+        let source = "class Vector { init(x, y) { this.x = x; this.y = y; } \
+            plus(other) { return Vector(this.x + other.x, this.y + other.y); } \
+            less(other) { return this.x < other.x; } \
+            greaterEqual(other) { return this.x >= other.x; } } \
+            var v = Vector(1, 2) + Vector(3, 4); print v.x; print v.y; \
+            print Vector(1, 0) < Vector(2, 0); print Vector(1, 0) >= Vector(1, 5); print Vector(1, 0) == Vector(1, 0);";
+        assert_eq!(printed(source), "4\n6\ntrue\ntrue\nfalse\n");
+        assert_eq!(
+            runtime_error("class A {} print A() <= A();"),
+            Some("Operator '<=' needs a 'lessEqual' method on A instance.".to_string())
+        );
+        assert_eq!(
+            runtime_error("class A {} print A() - A();"),
+            Some("Operator '-' needs a 'minus' method on A instance.".to_string())
+        );
+        assert_eq!(
+            runtime_error("class A { times() { return 1; } } print A() * 2;"),
+            Some("Operator method 'times' must take exactly one argument.".to_string())
+        );
+    }
+
+    #[test]
+    fn string_concatenation_uses_to_string() {
+        let source = "class Point { init(x) { this.x = x; } toString() { return \"Point(\" + this.x + \")\"; } } \
+            class Plain {} \
+            print \"value: \" + Point(1); print Point(2) + \"!\"; print \"plain: \" + Plain(); \
+            class Both { toString() { return \"both\"; } plus(other) { return \"plus \" + other; } } \
+            print Both() + \"x\"; print \"x\" + Both();";
+        assert_eq!(
+            printed(source),
+            "value: Point(1)\nPoint(2)!\nplain: Plain instance\nplus x\nxboth\n"
+        );
+    }
+
+    #[test]
+    fn list_index_errors() {
+        assert_eq!(runtime_error("var a = [1, 2]; print a[2];"), Some("Index out of bounds.".to_string()));
+        assert_eq!(runtime_error("var a = []; a[0] = 1;"), Some("Index out of bounds.".to_string()));
+        assert_eq!(
+            runtime_error("print [1][-1];"),
+            Some("Index must be a non-negative integer.".to_string())
+        );
+        assert_eq!(runtime_error("print [1][0.5];"), Some("Index must be a non-negative integer.".to_string()));
+        assert_eq!(runtime_error("var n = 1; print n[0];"), Some("Only lists can be indexed.".to_string()));
+        assert_eq!(runtime_error("print \"ab\"[2];"), Some("Index out of bounds.".to_string()));
+    }
+}
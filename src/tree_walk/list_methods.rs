@@ -0,0 +1,45 @@
+use super::callable::Callable;
+use super::error::InterpreterError;
+use super::interpreter::Interpreter;
+use super::native_function::{NativeFn, NativeFunction};
+use super::object::{ListElements, Object};
+
+impl Object {
+    /// Binds the built-in list method called `name` to `elements`.
+    pub fn make_list_method(name: &str, elements: &ListElements) -> Option<Object> {
+        let (arity, on_call): (usize, NativeFn) = match name {
+            "push" => (1, push),
+            "pop" => (0, pop),
+            "length" => (0, length),
+            _ => return None,
+        };
+        let native_fn = NativeFunction {
+            arity,
+            on_call: Box::new(on_call),
+            receiver: Some(Box::new(Object::List(elements.clone()))),
+        };
+        Some(Object::Callable(Callable::NativeFn(native_fn)))
+    }
+}
+
+fn receiver_elements(arguments: &[Object]) -> &ListElements {
+    match &arguments[0] {
+        Object::List(elements) => elements,
+        _ => panic!("List method is bound to a non-list receiver"),
+    }
+}
+
+fn push(_: &mut Interpreter, arguments: &[Object]) -> Result<Object, InterpreterError> {
+    receiver_elements(arguments).as_ref().borrow_mut().push(arguments[1].clone());
+    Ok(Object::Nil)
+}
+
+fn pop(_: &mut Interpreter, arguments: &[Object]) -> Result<Object, InterpreterError> {
+    receiver_elements(arguments).as_ref().borrow_mut().pop().ok_or_else(|| {
+        InterpreterError::new(0, "Can't pop from an empty list.".to_string()) // TODO: pass real line number
+    })
+}
+
+fn length(_: &mut Interpreter, arguments: &[Object]) -> Result<Object, InterpreterError> {
+    Ok(Object::Number(receiver_elements(arguments).as_ref().borrow().len() as f64))
+}
@@ -5,9 +5,11 @@ use std::rc::Rc;
 use super::callable::{Callable, LoxFn};
 use super::object::Object;
 
-pub const CONSTRUCTOR_KEYWORD: &'static str = "init";
-pub const THIS_KEYWORD: &'static str = "this";
-pub const SUPER_KEYWORD: &'static str = "super";
+pub const CONSTRUCTOR_KEYWORD: &str = "init";
+pub const THIS_KEYWORD: &str = "this";
+pub const SUPER_KEYWORD: &str = "super";
+pub const ANONYMOUS_CLASS_NAME: &str = "anonymous";
+pub const TO_STRING_METHOD: &str = "toString";
 
 pub struct LoxClass {
     pub name: String,
@@ -45,7 +47,7 @@ impl Instance {
     pub fn get(&self, name: &str, this: Rc<RefCell<Instance>>) -> Result<Object, String> {
         self.fields
             .get(name)
-            .map(|v| v.clone())
+            .cloned()
             .or_else(|| self.find_method(name, this))
             .ok_or_else(|| format!("Undefined property {}.", name))
     }
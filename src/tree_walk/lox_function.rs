@@ -5,20 +5,37 @@ use super::object::Object;
 use super::statement::Statement;
 use std::cell::RefCell;
 use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
 
 pub struct LoxFunction {
+    pub id: usize,
     pub name: String,
+    pub line: u32,
     pub parameters: Vec<String>,
     pub body: Vec<Statement>,
+    pub is_getter: bool,
 }
 
 impl LoxFunction {
-    pub fn new(name: String, parameters: Vec<String>, body: Vec<Statement>) -> Self {
+    pub fn new(name: String, line: u32, parameters: Vec<String>, body: Vec<Statement>) -> Self {
         Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
             name,
+            line,
             parameters,
             body,
+            is_getter: false,
+        }
+    }
+
+    pub fn new_getter(name: String, line: u32, body: Vec<Statement>) -> Self {
+        Self {
+            is_getter: true,
+            ..LoxFunction::new(name, line, Vec::new(), body)
         }
     }
 
@@ -34,7 +51,7 @@ impl LoxFunction {
         }
         let result = interpreter.execute_block(&self.body, Rc::new(RefCell::new(environment)))?;
         let object = match result {
-            InterpretedValue::None => Object::Nil,
+            InterpretedValue::None | InterpretedValue::Break | InterpretedValue::Continue => Object::Nil,
             InterpretedValue::Some(obj)=> obj,
             InterpretedValue::Return(obj) => obj
         };
@@ -46,6 +63,20 @@ impl LoxFunction {
     }
 }
 
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for LoxFunction {}
+
+impl Hash for LoxFunction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
 impl Debug for LoxFunction {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "<fn {}>", self.name)
@@ -1,17 +1,27 @@
+use ast_printer::AstPrinter;
+use constant_folder::ConstantFolder;
 use interpreter::Interpreter;
 use parser::Parser;
 use error::Error;
 use resolver::Resolver;
 use scanner::Scanner;
+pub use scanner::DEFAULT_MAX_TOKEN_LENGTH;
 use std::cell::RefCell;
+use std::io::Write;
 use std::rc::Rc;
+use crate::limits::DEFAULT_MAX_NESTING_DEPTH;
+use crate::run_error::RunError;
 
+mod ast_printer;
 mod callable;
 mod clock;
+mod constant_folder;
+mod dictionary;
 mod environment;
 mod error;
 mod expression;
 mod interpreter;
+mod list_methods;
 mod lox_function;
 mod native_function;
 mod object;
@@ -23,23 +33,70 @@ mod token;
 mod token_type;
 mod lox_class;
 
-pub fn run_interpreter(script: String) {
+#[derive(Clone, Copy)]
+pub struct Options {
+    pub warnings: bool,
+    pub max_token_length: usize,
+    pub max_nesting_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            warnings: true,
+            max_token_length: DEFAULT_MAX_TOKEN_LENGTH,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+}
+
+pub fn run_interpreter(script: String, options: Options) -> Result<(), RunError> {
     let mut scanner = Scanner::new(script.as_str());
+    scanner.set_max_token_length(options.max_token_length);
     let tokens = scanner.scan_tokens();
 
-    let mut parser = Parser::new(&tokens);
+    let mut parser = Parser::with_max_nesting_depth(&tokens, options.max_nesting_depth);
     let statements = parser.parse();
 
+    if scanner.had_error() || parser.had_error() {
+        return Err(RunError::Compile);
+    }
     if statements.is_empty() {
-        return;
+        return Ok(());
     }
+    let statements = ConstantFolder.fold_statements(&statements);
 
     let interpreter = Rc::new(RefCell::new(Interpreter::new()));
     let mut resolver = Resolver::new(interpreter.clone());
-    match resolver.resolve_statements(&statements) {
-        Err(error) => eprintln!("{}", error.description()),
-        _ => {
-            interpreter.as_ref().borrow_mut().interpret(&statements);
-        }
+    resolver.set_warnings_enabled(options.warnings);
+    let result = resolver.resolve_statements(&statements);
+    for warning in resolver.warnings() {
+        eprintln!("{}", warning);
+    }
+    if let Err(error) = result {
+        eprintln!("{}", error.description());
+        return Err(RunError::Compile);
+    }
+    let result = interpreter.as_ref().borrow_mut().interpret(&statements);
+    result.map_err(|error| {
+        eprintln!("{}", error.description());
+        RunError::Runtime
+    })
+}
+
+pub fn dump_tokens(script: &str, output: &mut dyn Write) {
+    let tokens = Scanner::new(script).scan_tokens();
+    for token in tokens {
+        let lexeme: String = token.lexeme.iter().collect();
+        writeln!(output, "{:?} {:?} {}", token.token_type, lexeme, token.line).expect("Failed to write to output");
+    }
+}
+
+pub fn dump_ast(script: &str, max_nesting_depth: usize, output: &mut dyn Write) {
+    let tokens = Scanner::new(script).scan_tokens();
+    let statements = Parser::with_max_nesting_depth(&tokens, max_nesting_depth).parse();
+    let mut printer = AstPrinter;
+    for statement in &statements {
+        writeln!(output, "{}", printer.print_statement(statement)).expect("Failed to write to output");
     }
 }
@@ -1,15 +1,30 @@
+use super::error::InterpreterError;
+use super::interpreter::Interpreter;
 use super::object::Object;
 use std::fmt::{Debug, Formatter};
 
+/// Natives get the interpreter so they can call the Lox callables they are passed.
+pub type NativeFn = fn(&mut Interpreter, &[Object]) -> Result<Object, InterpreterError>;
+
 #[derive(Clone)]
 pub struct NativeFunction {
     pub arity: usize,
-    pub on_call: Box<fn(&[Object]) -> Object>,
+    pub on_call: Box<NativeFn>,
+    /// Object the native is a method of, passed ahead of the call arguments.
+    pub receiver: Option<Box<Object>>,
 }
 
 impl NativeFunction {
-    pub fn call(&self, arguments: &[Object]) -> Object {
-        (self.on_call)(arguments)
+    pub fn call(&self, interpreter: &mut Interpreter, arguments: &[Object]) -> Result<Object, InterpreterError> {
+        match &self.receiver {
+            Some(receiver) => {
+                let arguments = [receiver.as_ref().clone()].into_iter()
+                    .chain(arguments.iter().cloned())
+                    .collect::<Vec<Object>>();
+                (self.on_call)(interpreter, &arguments)
+            }
+            None => (self.on_call)(interpreter, arguments),
+        }
     }
 }
 
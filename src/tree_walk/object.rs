@@ -1,9 +1,13 @@
 use super::callable::Callable;
+use super::dictionary::{DictionaryEntries, DisplayEntries};
 use super::lox_class::Instance;
+use crate::number::format_number;
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::fmt::{self, Debug, Formatter};
 
+pub type ListElements = Rc<RefCell<Vec<Object>>>;
+
 #[derive(Debug, Clone)]
 pub enum Object {
     Nil,
@@ -12,6 +16,8 @@ pub enum Object {
     Number(f64),
     Callable(Callable),
     Instance(Rc<RefCell<Instance>>),
+    List(Rc<RefCell<Vec<Object>>>),
+    Dictionary(DictionaryEntries),
     NotInitialized,
 }
 
@@ -21,10 +27,21 @@ impl fmt::Display for Object {
             Object::Nil => write!(f, "nil"),
             Object::Boolean(value) => write!(f, "{}", value),
             Object::String(value) => write!(f, "{}", value),
-            Object::Number(value) => write!(f, "{}", value),
+            Object::Number(value) => write!(f, "{}", format_number(*value)),
             Object::Callable(callable) => callable.fmt(f),
             Object::NotInitialized => write!(f, "variable was not initialized"),
             Object::Instance(lox_class) => lox_class.as_ref().borrow().fmt(f),
+            Object::List(elements) => {
+                write!(f, "[")?;
+                for (index, element) in elements.as_ref().borrow().iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            Object::Dictionary(entries) => write!(f, "{}", DisplayEntries(&entries.as_ref().borrow())),
         }
     }
 }
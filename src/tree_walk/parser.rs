@@ -11,25 +11,51 @@ use std::iter::Peekable;
 use std::rc::Rc;
 use std::slice::Iter;
 
+const LAMBDA_NAME: &str = "lambda";
+
 pub struct Parser<'a> {
     tokens_iter: Peekable<Iter<'a, Token>>,
     current: Option<&'a Token>,
+    nesting_depth: usize,
+    max_nesting_depth: usize,
+    had_error: bool,
+    next_expression_id: usize,
 }
 
 type ParseStmtResult = Result<Statement, ParseError>;
 type ParseExprResult = Result<Expression, ParseError>;
+/// Instance methods followed by static methods of a class body.
+type ClassMethods = (Vec<Rc<LoxFunction>>, Vec<Rc<LoxFunction>>);
 
 impl<'a> Parser<'a> {
+    #[cfg(test)]
     pub fn new(tokens: &'a [Token]) -> Self {
+        Parser::with_max_nesting_depth(tokens, crate::limits::DEFAULT_MAX_NESTING_DEPTH)
+    }
+
+    pub fn with_max_nesting_depth(tokens: &'a [Token], max_nesting_depth: usize) -> Self {
         Self {
             tokens_iter: tokens.iter().peekable(),
             current: None,
+            nesting_depth: 0,
+            max_nesting_depth,
+            had_error: false,
+            next_expression_id: 0,
         }
     }
 
+    pub fn had_error(&self) -> bool {
+        self.had_error
+    }
+
+    fn make_expression_id(&mut self) -> usize {
+        let id = self.next_expression_id;
+        self.next_expression_id += 1;
+        id
+    }
+
     pub fn parse(&mut self) -> Vec<Statement> {
         let mut statements: Vec<Statement> = Vec::new();
-        let mut had_error = false;
         loop {
             match self.declaration() {
                 Ok(expression) => {
@@ -38,12 +64,12 @@ impl<'a> Parser<'a> {
                 Err(error) if error.token.token_type == TokenType::Eof => break,
                 Err(error) => {
                     self.synchronize();
-                    had_error = true;
+                    self.had_error = true;
                     eprintln!("{}", error.description());
                 }
             }
         }
-        if had_error {
+        if self.had_error {
             Vec::new()
         } else {
             statements
@@ -51,12 +77,14 @@ impl<'a> Parser<'a> {
     }
 
     fn declaration(&mut self) -> ParseStmtResult {
+        let is_lambda_next = self.is_lambda_next();
+        let is_class_expression_next = self.is_class_expression_next();
         match self.tokens_iter.peek().unwrap().token_type {
-            TokenType::Keyword(KeywordTokenType::Class) => {
+            TokenType::Keyword(KeywordTokenType::Class) if !is_class_expression_next => {
                 self.advance();
                 self.class_statement()
             }
-            TokenType::Keyword(KeywordTokenType::Fun) => {
+            TokenType::Keyword(KeywordTokenType::Fun) if !is_lambda_next => {
                 self.advance();
                 self.function_statement()
             }
@@ -90,6 +118,16 @@ impl<'a> Parser<'a> {
                 self.advance();
                 self.while_statement()
             }
+            TokenType::Keyword(KeywordTokenType::Break) => {
+                self.advance();
+                let line = self.current.unwrap().line;
+                self.loop_control_statement(Statement::Break(line), "Expect ';' after 'break'.")
+            }
+            TokenType::Keyword(KeywordTokenType::Continue) => {
+                self.advance();
+                let line = self.current.unwrap().line;
+                self.loop_control_statement(Statement::Continue(line), "Expect ';' after 'continue'.")
+            }
             TokenType::OpenDelimiter(Delimiter::Brace) => {
                 self.advance();
                 self.block().map(Statement::Block)
@@ -120,13 +158,43 @@ impl<'a> Parser<'a> {
 
     fn class_statement(&mut self) -> ParseStmtResult {
         let name = self.consume_identifier(|| "Expect class name.")?;
+        let line = self.current.unwrap().line;
+        let superclass = self.class_superclass()?;
+        let (methods, static_methods) = self.class_methods()?;
+        Ok(Statement::Class { name, line, methods, static_methods, superclass })
+    }
+
+    /// `class` directly followed by `{` or `<` starts an anonymous class
+    /// expression rather than a declaration.
+    fn is_class_expression_next(&self) -> bool {
+        let mut tokens = self.tokens_iter.clone();
+        tokens.next();
+        matches!(
+            tokens.next().map(|token| &token.token_type),
+            Some(TokenType::OpenDelimiter(Delimiter::Brace))
+                | Some(TokenType::ExpressionOperator(ExpressionOperatorTokenType::Less))
+        )
+    }
+
+    fn class_expression(&mut self) -> ParseExprResult {
+        let line = self.current.unwrap().line;
+        let superclass = self.class_superclass()?;
+        let (methods, static_methods) = self.class_methods()?;
+        Ok(Expression::ClassExpr { line, methods, static_methods, superclass })
+    }
+
+    fn class_superclass(&mut self) -> Result<Option<VariableExpression>, ParseError> {
         let superclass = self
             .parse_superclass()?
             .map(|name| VariableExpression {
                 name,
-                token: self.current.unwrap().clone()
+                token: self.current.unwrap().clone(),
+                id: self.make_expression_id(),
             });
+        Ok(superclass)
+    }
 
+    fn class_methods(&mut self) -> Result<ClassMethods, ParseError> {
         if !self.next_matches_one(TokenType::OpenDelimiter(Delimiter::Brace)) {
             return Err(self.make_error("Expect '{' before class methods"));
         }
@@ -136,19 +204,51 @@ impl<'a> Parser<'a> {
         loop {
             if self.next_matches_one(TokenType::CloseDelimiter(Delimiter::Brace)) {
                 self.advance();
-                return Ok(Statement::Class { name, methods, static_methods, superclass });
+                return Ok((methods, static_methods));
             }
             if self.next_matches_one(TokenType::Keyword(KeywordTokenType::Class)) {
                 self.advance();
-                let method = self.parse_function()?;
+                let method = self.parse_method(&static_methods, false)?;
                 static_methods.push(method);
             } else {
-                let method = self.parse_function()?;
+                let method = self.parse_method(&methods, true)?;
                 methods.push(method);
             }
         }
     }
 
+    fn parse_method(&mut self, methods: &[Rc<LoxFunction>], allow_getter: bool) -> Result<Rc<LoxFunction>, ParseError> {
+        let method = if allow_getter && self.is_getter_declaration() {
+            self.parse_getter()?
+        } else {
+            self.parse_function()?
+        };
+        if methods.iter().any(|defined| defined.name == method.name) {
+            return Err(self.make_error(format!("Duplicate method '{}' in class.", method.name)));
+        }
+        Ok(method)
+    }
+
+    fn is_getter_declaration(&self) -> bool {
+        let mut tokens = self.tokens_iter.clone();
+        let is_identifier = matches!(
+            tokens.next().map(|token| &token.token_type),
+            Some(TokenType::Literal(LiteralTokenType::Identifier(_)))
+        );
+        is_identifier && matches!(
+            tokens.next().map(|token| &token.token_type),
+            Some(TokenType::OpenDelimiter(Delimiter::Brace))
+        )
+    }
+
+    fn parse_getter(&mut self) -> Result<Rc<LoxFunction>, ParseError> {
+        let name = self.consume_identifier(|| "Expect getter name.")?;
+        let line = self.current.unwrap().line;
+        self.advance();
+        let body = self.block()?;
+        Ok(Rc::new(LoxFunction::new_getter(name, line, body)))
+    }
+
     fn parse_superclass(&mut self) -> Result<Option<String>, ParseError> {
         let less_token_type = TokenType::ExpressionOperator(ExpressionOperatorTokenType::Less);
         if !self.next_matches_one(less_token_type) {
@@ -168,19 +268,44 @@ impl<'a> Parser<'a> {
 
     fn parse_function(&mut self) -> Result<Rc<LoxFunction>, ParseError> {
         let name = self.consume_identifier(|| "Expect function name.")?;
+        let line = self.current.unwrap().line;
         self.advance();
         let parameters = self.parse_function_parameters()?;
         self.advance_when_match(
             TokenType::OpenDelimiter(Delimiter::Brace),
             |parser| {
                 let body = parser.block()?;
-                let lox_func = LoxFunction::new(name, parameters, body);
+                let lox_func = LoxFunction::new(name, line, parameters, body);
                 Ok(Rc::new(lox_func))
             },
             |parser| Err(parser.make_error("Expect '{' before function body.")),
         )
     }
 
+    /// `fun` directly followed by `(` starts an anonymous function expression
+    /// rather than a declaration.
+    fn is_lambda_next(&self) -> bool {
+        let mut tokens = self.tokens_iter.clone();
+        tokens.next();
+        matches!(tokens.next().map(|token| &token.token_type), Some(TokenType::OpenDelimiter(Delimiter::Paren)))
+    }
+
+    fn lambda(&mut self) -> ParseExprResult {
+        let line = self.current.unwrap().line;
+        let parameters = self.advance_when_match(
+            TokenType::OpenDelimiter(Delimiter::Paren),
+            Parser::parse_function_parameters,
+            |parser| Err(parser.make_error("Expect '(' after 'fun'.")),
+        )?;
+        let body = self.advance_when_match(
+            TokenType::OpenDelimiter(Delimiter::Brace),
+            Parser::block,
+            |parser| Err(parser.make_error("Expect '{' before function body.")),
+        )?;
+        let lox_func = LoxFunction::new(LAMBDA_NAME.to_string(), line, parameters, body);
+        Ok(Expression::Function(Rc::new(lox_func)))
+    }
+
     fn parse_function_parameters(&mut self) -> Result<Vec<String>, ParseError> {
         let mut parameters = Vec::new();
         if self.next_matches_one(TokenType::CloseDelimiter(Delimiter::Paren)) {
@@ -189,6 +314,9 @@ impl<'a> Parser<'a> {
         }
         loop {
             let parameter = self.consume_identifier(|| "Expect parameter name.")?;
+            if parameters.contains(&parameter) {
+                return Err(self.make_error(format!("Duplicate parameter name '{}'.", parameter)));
+            }
             parameters.push(parameter);
             match &self.tokens_iter.peek().unwrap().token_type {
                 TokenType::SingleChar(SingleCharTokenType::Comma) => {
@@ -206,14 +334,15 @@ impl<'a> Parser<'a> {
     fn variable_statement(&mut self) -> ParseStmtResult {
         self.parse_variable_name()
             .map(|name| {
-                self.make_variable_stmt(name.to_string())
+                let line = self.current.unwrap().line;
+                self.make_variable_stmt(name.to_string(), line)
                     .and_then(|stmt| self.check_semicolon_after_stmt(stmt))
             })
             .unwrap_or_else(|| {
                 let token = self.tokens_iter.peek().unwrap();
                 Err(ParseError {
                     token: (*token).clone(),
-                    message: "Expect variable name.",
+                    message: "Expect variable name.".to_string(),
                 })
             })
     }
@@ -228,7 +357,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn make_variable_stmt(&mut self, name: String) -> ParseStmtResult {
+    fn make_variable_stmt(&mut self, name: String, line: u32) -> ParseStmtResult {
         let equal_type = TokenType::ExpressionOperator(ExpressionOperatorTokenType::Equal);
         if self.next_matches_one(equal_type) {
             self.advance();
@@ -236,9 +365,10 @@ impl<'a> Parser<'a> {
             Ok(Statement::Variable {
                 name,
                 value: Some(right),
+                line,
             })
         } else {
-            Ok(Statement::Variable { name, value: None })
+            Ok(Statement::Variable { name, value: None, line })
         }
     }
 
@@ -258,14 +388,11 @@ impl<'a> Parser<'a> {
                 "Expect ')' after for clauses."
             })?;
         let body = self.statement()?;
-        let loop_body: Statement = match increment {
-            Some(expr) => Statement::Block(vec![body, Statement::Expression(expr)]),
-            None => Statement::Block(vec![body]),
-        };
         let loop_condition = condition.unwrap_or(Expression::Literal(LiteralExpression::True));
         let while_loop = Statement::While {
             condition: loop_condition,
-            body: Box::new(loop_body),
+            body: Box::new(body),
+            increment,
         };
         match initializer {
             Some(stmt) => Ok(Statement::Block(vec![stmt, while_loop])),
@@ -310,26 +437,40 @@ impl<'a> Parser<'a> {
     }
 
     fn if_statement(&mut self) -> ParseStmtResult {
-        let condition: Expression = self.advance_when_match(
-            TokenType::OpenDelimiter(Delimiter::Paren),
-            Parser::expression,
-            |parser| Err(parser.make_error("Expect '(' after 'if'.")),
-        )?;
-        let then_branch: Statement = self.advance_when_match(
-            TokenType::CloseDelimiter(Delimiter::Paren),
-            Parser::statement,
-            |parser| Err(parser.make_error("Expect ')' after if condition.")),
-        )?;
-        let else_branch: Option<Statement> = self.advance_when_match(
-            TokenType::Keyword(KeywordTokenType::Else),
-            |parser| parser.statement().map(Some),
-            |_| Ok(None),
-        )?;
-        Ok(Statement::If {
-            condition,
-            then_branch: Box::new(then_branch),
-            else_branch: else_branch.map(Box::new),
-        })
+        let mut branches = Vec::new();
+        let else_branch = loop {
+            let condition: Expression = self.advance_when_match(
+                TokenType::OpenDelimiter(Delimiter::Paren),
+                Parser::expression,
+                |parser| Err(parser.make_error("Expect '(' after 'if'.")),
+            )?;
+            let then_branch: Statement = self.advance_when_match(
+                TokenType::CloseDelimiter(Delimiter::Paren),
+                Parser::statement,
+                |parser| Err(parser.make_error("Expect ')' after if condition.")),
+            )?;
+            branches.push((condition, then_branch));
+            if !self.next_matches_one(TokenType::Keyword(KeywordTokenType::Else)) {
+                break None;
+            }
+            self.advance();
+            if self.next_matches_one(TokenType::Keyword(KeywordTokenType::If)) {
+                self.advance();
+            } else {
+                break Some(self.statement()?);
+            }
+        };
+        let statement = branches
+            .into_iter()
+            .rev()
+            .fold(else_branch, |else_branch, (condition, then_branch)| {
+                Some(Statement::If {
+                    condition,
+                    then_branch: Box::new(then_branch),
+                    else_branch: else_branch.map(Box::new),
+                })
+            });
+        Ok(statement.expect("if statement has at least one branch"))
     }
 
     fn print_statement(&mut self) -> ParseStmtResult {
@@ -367,9 +508,18 @@ impl<'a> Parser<'a> {
         Ok(Statement::While {
             condition,
             body: Box::new(body),
+            increment: None,
         })
     }
 
+    fn loop_control_statement(&mut self, statement: Statement, message: &'static str) -> ParseStmtResult {
+        self.advance_when_match(
+            TokenType::SingleChar(SingleCharTokenType::Semicolon),
+            |_| Ok(statement),
+            |parser| Err(parser.make_error(message)),
+        )
+    }
+
     fn expression_statement(&mut self) -> ParseStmtResult {
         self.expression()
             .map(Statement::Expression)
@@ -377,7 +527,20 @@ impl<'a> Parser<'a> {
     }
 
     fn expression(&mut self) -> ParseExprResult {
-        self.assignment()
+        self.nested(Parser::assignment)
+    }
+
+    fn nested<F>(&mut self, parse: F) -> ParseExprResult
+    where
+        F: FnOnce(&mut Self) -> ParseExprResult,
+    {
+        if self.nesting_depth >= self.max_nesting_depth {
+            return Err(self.make_error("Expression nesting too deep."));
+        }
+        self.nesting_depth += 1;
+        let result = parse(self);
+        self.nesting_depth -= 1;
+        result
     }
 
     fn assignment(&mut self) -> ParseExprResult {
@@ -385,14 +548,17 @@ impl<'a> Parser<'a> {
         let equal_token_type = TokenType::ExpressionOperator(ExpressionOperatorTokenType::Equal);
         if self.next_matches_one(equal_token_type) {
             self.advance();
-            let right = self.assignment()?;
+            let right = self.nested(Parser::assignment)?;
             match left {
                 Expression::Variable(expr) => {
-                    Ok(Expression::Assignment(expr.token, Box::new(right)))
+                    Ok(Expression::Assignment { token: expr.token, value: Box::new(right), id: expr.id })
                 },
                 Expression::Get { name, expression } => {
                     Ok(Expression::Set { name, object: expression, value: Box::new(right) })
                 },
+                Expression::Index { object, index, close_bracket } => {
+                    Ok(Expression::SetIndex { object, index, value: Box::new(right), close_bracket })
+                },
                 _ => Err(self.make_error("Invalid assignment target."))
             }
         } else {
@@ -440,6 +606,7 @@ impl<'a> Parser<'a> {
         let token_types = vec![
             TokenType::SingleChar(SingleCharTokenType::Slash),
             TokenType::SingleChar(SingleCharTokenType::Star),
+            TokenType::SingleChar(SingleCharTokenType::Percent),
         ];
         self.find_binary_expression(Parser::unary, Expression::Binary, &token_types)
     }
@@ -450,9 +617,13 @@ impl<'a> Parser<'a> {
             TokenType::ExpressionOperator(ExpressionOperatorTokenType::Not),
         ];
         if self.next_matches_any(&token_types) {
-            let operator = self.current.unwrap();
-            self.advance();
-            let right_expression = self.unary()?;
+            let operator = self.advance().unwrap();
+            // A grouping operand already counts its own nesting level.
+            let right_expression = if self.next_matches_one(TokenType::OpenDelimiter(Delimiter::Paren)) {
+                self.unary()?
+            } else {
+                self.nested(Parser::unary)?
+            };
             Ok(Expression::Unary(
                 operator.clone(),
                 Box::new(right_expression),
@@ -476,6 +647,19 @@ impl<'a> Parser<'a> {
                     let name = self.consume_identifier(|| "Expect property name after '.'.")?;
                     expression = Expression::Get { name, expression: Box::new(expression) };
                 }
+                TokenType::OpenDelimiter(Delimiter::Bracket) => {
+                    self.advance();
+                    let index = self.expression()?;
+                    expression = self.advance_when_match(
+                        TokenType::CloseDelimiter(Delimiter::Bracket),
+                        |parser| Ok(Expression::Index {
+                            object: Box::new(expression),
+                            index: Box::new(index),
+                            close_bracket: parser.current.unwrap().clone(),
+                        }),
+                        |parser| Err(parser.make_error("Expect ']' after index.")),
+                    )?;
+                }
                 _ => {
                     return Ok(expression);
                 }
@@ -520,27 +704,55 @@ impl<'a> Parser<'a> {
         match &next_token.token_type {
             TokenType::Literal(literal) => {
                 self.advance();
-                Ok(literal.to_expression(next_token))
+                Ok(literal.to_expression(next_token, self.make_expression_id()))
             }
             TokenType::Keyword(KeywordTokenType::Super) => {
                 self.advance();
                 self.find_super()
             }
+            TokenType::Keyword(KeywordTokenType::Fun) => {
+                self.advance();
+                self.lambda()
+            }
+            TokenType::Keyword(KeywordTokenType::Class) => {
+                self.advance();
+                self.class_expression()
+            }
             TokenType::Keyword(keyword) => {
                 self.advance();
-                Ok(keyword.to_expression(next_token).expect("Expect expression"))
+                Ok(keyword.to_expression(next_token, self.make_expression_id()).expect("Expect expression"))
             }
             TokenType::OpenDelimiter(delimiter) if *delimiter == Delimiter::Paren => {
                 self.advance();
                 self.find_group()
             }
+            TokenType::OpenDelimiter(Delimiter::Bracket) => {
+                self.advance();
+                self.list()
+            }
             _ => Err(ParseError {
                 token: next_token.clone(),
-                message: "Expected expression",
+                message: "Expected expression".to_string(),
             }),
         }
     }
 
+    fn list(&mut self) -> ParseExprResult {
+        let mut elements = Vec::new();
+        if !self.next_matches_one(TokenType::CloseDelimiter(Delimiter::Bracket)) {
+            elements.push(self.expression()?);
+            while self.next_matches_one(TokenType::SingleChar(SingleCharTokenType::Comma)) {
+                self.advance();
+                elements.push(self.expression()?);
+            }
+        }
+        self.advance_when_match(
+            TokenType::CloseDelimiter(Delimiter::Bracket),
+            |_| Ok(Expression::List(elements)),
+            |parser| Err(parser.make_error("Expect ']' after list elements.")),
+        )
+    }
+
     fn find_group(&mut self) -> ParseExprResult {
         let expression = self.expression()?;
         let close_paren_token = TokenType::CloseDelimiter(Delimiter::Paren);
@@ -551,7 +763,7 @@ impl<'a> Parser<'a> {
             }
             _ => Err(ParseError {
                 token: (*self.tokens_iter.peek().unwrap()).clone(),
-                message: "Expect ')' after expression.",
+                message: "Expect ')' after expression.".to_string(),
             }),
         }
     }
@@ -563,7 +775,8 @@ impl<'a> Parser<'a> {
                 parser.consume_identifier(|| "Expect superclass method name.")
                     .map(|method| Expression::Super {
                         keyword_token: parser.current.unwrap().clone(),
-                        method
+                        method,
+                        id: parser.make_expression_id(),
                     })
             },
             |parser| Err(parser.make_error("Expect '.' after 'super'."))
@@ -585,7 +798,7 @@ impl<'a> Parser<'a> {
             self.advance();
             let operator = self.current.unwrap();
 
-            let right_expression = expr_lookup(self)?;
+            let right_expression = self.nested(&expr_lookup)?;
             expression = expr_factory(
                 Box::new(expression),
                 operator.clone(),
@@ -642,10 +855,10 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn make_error(&self, message: &'static str) -> ParseError {
+    fn make_error(&self, message: impl Into<String>) -> ParseError {
         ParseError {
             token: self.current.unwrap().clone(),
-            message,
+            message: message.into(),
         }
     }
 
@@ -659,19 +872,16 @@ impl<'a> Parser<'a> {
             let token = self.tokens_iter.peek().unwrap();
             match &token.token_type {
                 TokenType::Eof => return,
-                TokenType::Keyword(keyword) => {
-                    match keyword {
-                        KeywordTokenType::Class
-                        | KeywordTokenType::For
-                        | KeywordTokenType::Fun
-                        | KeywordTokenType::Var
-                        | KeywordTokenType::If
-                        | KeywordTokenType::While
-                        | KeywordTokenType::Print
-                        | KeywordTokenType::Return => return,
-                        _ => {}
-                    }
-                }
+                TokenType::Keyword(
+                    KeywordTokenType::Class
+                    | KeywordTokenType::For
+                    | KeywordTokenType::Fun
+                    | KeywordTokenType::Var
+                    | KeywordTokenType::If
+                    | KeywordTokenType::While
+                    | KeywordTokenType::Print
+                    | KeywordTokenType::Return
+                ) => return,
                 _ => {}
             }
             previous = self.current;
@@ -690,12 +900,13 @@ impl<'a> Parser<'a> {
 }
 
 impl LiteralTokenType {
-    fn to_expression(&self, token: &Token) -> Expression {
+    fn to_expression(&self, token: &Token, id: usize) -> Expression {
         match self {
             LiteralTokenType::Identifier(name) => Expression::Variable(
                 VariableExpression {
                     name: name.to_string(),
                     token: token.clone(),
+                    id,
                 }
             ),
             LiteralTokenType::Number(number) => {
@@ -709,9 +920,9 @@ impl LiteralTokenType {
 }
 
 impl KeywordTokenType {
-    fn to_expression(&self, token: &Token) -> Option<Expression> {
+    fn to_expression(&self, token: &Token, id: usize) -> Option<Expression> {
         match self {
-            KeywordTokenType::This => Some(Expression::This(token.clone())),
+            KeywordTokenType::This => Some(Expression::This { token: token.clone(), id }),
             KeywordTokenType::False => Some(Expression::Literal(LiteralExpression::False)),
             KeywordTokenType::True => Some(Expression::Literal(LiteralExpression::True)),
             KeywordTokenType::Nil => Some(Expression::Literal(LiteralExpression::Nil)),
@@ -722,15 +933,82 @@ impl KeywordTokenType {
 
 struct ParseError {
     token: Token,
-    message: &'static str,
+    message: String,
 }
 
 impl Error for ParseError {
     fn message(&self) -> &str {
-        self.message
+        &self.message
     }
 
     fn line(&self) -> usize {
         self.token.line as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::scanner::Scanner;
+
+    /// Low enough for the nesting tests to fit in a test thread's stack.
+    const MAX_NESTING_DEPTH: usize = 32;
+
+    fn parse(source: &str) -> Vec<Statement> {
+        let tokens = Scanner::new(source).scan_tokens();
+        Parser::with_max_nesting_depth(&tokens, MAX_NESTING_DEPTH).parse()
+    }
+
+    #[test]
+    fn duplicate_method_name() {
+        assert!(parse("class A { foo() {} foo() {} }").is_empty());
+        assert_eq!(parse("class A { foo() {} bar() {} }").len(), 1);
+    }
+
+    #[test]
+    fn trailing_dot_number() {
+        assert!(parse("print 5.;").is_empty());
+        assert_eq!(parse("print 5.foo;").len(), 1);
+    }
+
+    #[test]
+    fn duplicate_parameter_name() {
+        assert!(parse("fun f(a, a) {}").is_empty());
+        assert_eq!(parse("fun f(a, b) {}").len(), 1);
+    }
+
+    #[test]
+    fn deeply_nested_expression_is_an_error() {
+        let depth = 10_000;
+        assert!(parse(&format!("print {}1{};", "(".repeat(depth), ")".repeat(depth))).is_empty());
+        assert!(parse(&format!("print {}1;", "-".repeat(depth))).is_empty());
+        assert!(parse(&format!("a{};", " = a".repeat(depth))).is_empty());
+    }
+
+    #[test]
+    fn nesting_counts_function_bodies() {
+        let lambdas = |depth: usize| format!("print {}1{};", "fun () { return ".repeat(depth), "; }".repeat(depth));
+        let classes = |depth: usize| format!("print {}1{};", "class { m() { return ".repeat(depth), "; } }".repeat(depth));
+        assert_eq!(parse(&lambdas(MAX_NESTING_DEPTH / 2)).len(), 1);
+        assert_eq!(parse(&classes(MAX_NESTING_DEPTH / 2)).len(), 1);
+        assert!(parse(&lambdas(3000)).is_empty());
+        assert!(parse(&classes(3000)).is_empty());
+    }
+
+    #[test]
+    fn nesting_within_limit() {
+        let depth = MAX_NESTING_DEPTH - 1;
+        assert_eq!(parse(&format!("print {}1{};", "(".repeat(depth), ")".repeat(depth))).len(), 1);
+        assert_eq!(parse(&format!("print {}1;", "-".repeat(depth))).len(), 1);
+        assert_eq!(parse(&format!("print {}1{};", "-(".repeat(depth), ")".repeat(depth))).len(), 1);
+        assert_eq!(parse(&format!("print {}1{};", "1 + (".repeat(depth / 2), ")".repeat(depth / 2))).len(), 1);
+        assert!(parse(&format!("print {}1{};", "-(".repeat(depth + 2), ")".repeat(depth + 2))).is_empty());
+        let tokens = Scanner::new("print ((1));").scan_tokens();
+        assert!(Parser::with_max_nesting_depth(&tokens, 2).parse().is_empty());
+    }
+
+    #[test]
+    fn static_method_may_share_instance_method_name() {
+        assert_eq!(parse("class A { foo() {} class foo() {} }").len(), 1);
+    }
+}
@@ -7,13 +7,44 @@ use super::token::Token;
 use super::lox_class::{CONSTRUCTOR_KEYWORD, SUPER_KEYWORD, THIS_KEYWORD};
 use std::cell::RefCell;
 use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::mem;
 use std::rc::Rc;
 
 pub struct Resolver {
     interpreter: Rc<RefCell<Interpreter>>,
-    scopes: VecDeque<HashMap<String, VariableState>>,
+    scopes: VecDeque<HashMap<String, Variable>>,
     current_function_type: FunctionType,
     current_class_type: ClassType,
+    loop_depth: usize,
+    warnings_enabled: bool,
+    warnings: Vec<Warning>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub name: String,
+    pub line: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarningKind {
+    UnusedLocal,
+    ShadowedParameter,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            WarningKind::UnusedLocal => {
+                write!(f, "[line {}] Local variable '{}' is not used.", self.line, self.name)
+            }
+            WarningKind::ShadowedParameter => {
+                write!(f, "[line {}] Local variable '{}' shadows a parameter.", self.line, self.name)
+            }
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -23,6 +54,13 @@ enum VariableState {
     Read
 }
 
+#[derive(Copy, Clone)]
+struct Variable {
+    state: VariableState,
+    line: u32,
+    is_parameter: bool,
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 enum FunctionType {
     None,
@@ -51,8 +89,8 @@ impl statement::Visitor<ResolveResult> for Resolver {
         Ok(())
     }
 
-    fn visit_variable_stmt(&mut self, name: &str, value: &Option<Expression>) -> ResolveResult {
-        self.declare(name)?;
+    fn visit_variable_stmt(&mut self, name: &str, value: &Option<Expression>, line: u32) -> ResolveResult {
+        self.declare(name, line)?;
         if let Some(expression) = value {
             self.resolve_expression(expression)?;
         }
@@ -62,6 +100,7 @@ impl statement::Visitor<ResolveResult> for Resolver {
 
     fn visit_block(&mut self, statements: &[Statement]) -> ResolveResult {
         self.begin_scope();
+        self.declare_functions(statements)?;
         self.resolve_statements(statements)?;
         self.end_scope();
         Ok(())
@@ -81,17 +120,35 @@ impl statement::Visitor<ResolveResult> for Resolver {
         Ok(())
     }
 
-    fn visit_while(&mut self, condition: &Expression, body: &Statement) -> ResolveResult {
+    fn visit_while(&mut self, condition: &Expression, body: &Statement, increment: &Option<Expression>) -> ResolveResult {
         self.resolve_expression(condition)?;
-        self.resolve_statement(body)?;
+        let enclosing_loop_depth = self.loop_depth;
+        self.loop_depth += 1;
+        let result = self.resolve_statement(body);
+        self.loop_depth = enclosing_loop_depth;
+        result?;
+        if let Some(increment) = increment {
+            self.resolve_expression(increment)?;
+        }
         Ok(())
     }
 
+    fn visit_break(&mut self, line: u32) -> ResolveResult {
+        self.check_inside_loop(line, "Can't use 'break' outside of a loop.")
+    }
+
+    fn visit_continue(&mut self, line: u32) -> ResolveResult {
+        self.check_inside_loop(line, "Can't use 'continue' outside of a loop.")
+    }
+
     fn visit_function(&mut self, func: Rc<LoxFunction>) -> ResolveResult {
         let func = func.as_ref();
-        self.declare(&func.name)?;
-        self.define(&func.name);
-        self.resolve_function(&func.parameters, &func.body, FunctionType::Function)?;
+        let is_declared = self.scopes.front().is_some_and(|scope| scope.contains_key(&func.name));
+        if !is_declared {
+            self.declare(&func.name, func.line)?;
+            self.define(&func.name);
+        }
+        self.resolve_function(func, FunctionType::Function)?;
         Ok(())
     }
 
@@ -101,7 +158,7 @@ impl statement::Visitor<ResolveResult> for Resolver {
                 Err(InterpreterError::new(0, "Can't return from top-level code.".to_string()))
             },
             FunctionType::Initializer if &Expression::Literal(LiteralExpression::Nil) != expression => {
-                return Err(InterpreterError::new(0, "Can't return a value from an initializer.".to_string()));
+                Err(InterpreterError::new(0, "Can't return a value from an initializer.".to_string()))
             },
             _ => {
                 self.resolve_expression(expression)?;
@@ -113,54 +170,23 @@ impl statement::Visitor<ResolveResult> for Resolver {
     fn visit_class(
         &mut self,
         name: &str,
+        line: u32,
         methods: &[Rc<LoxFunction>],
         static_methods: &[Rc<LoxFunction>],
         superclass: &Option<VariableExpression>
     ) -> ResolveResult {
-        self.declare(name)?;
+        self.declare(name, line)?;
         self.define(name);
 
-        let current_class_type = self.current_class_type;
-        self.current_class_type = superclass
-            .as_ref()
-            .map(|_| ClassType::Subclass)
-            .unwrap_or(ClassType::Class);
-
         if let Some(expression) = superclass {
-            if &expression.name == name {
+            if expression.name == name {
                 return Err(InterpreterError::new_from_static_str(
                     &expression.token,
                     "A class can't inherit from itself"
                 ));
             }
-            self.visit_variable(&expression.name, &expression.token)?;
-            self.begin_scope();
-            self.declare(SUPER_KEYWORD)?;
-            self.define(SUPER_KEYWORD)
-        }
-
-        self.begin_scope();
-        self.declare(THIS_KEYWORD)?;
-        self.define(THIS_KEYWORD);
-        for method in methods {
-            let fn_type = if method.as_ref().name == CONSTRUCTOR_KEYWORD {
-                FunctionType::Initializer
-            } else {
-                FunctionType::Method
-            };
-            self.resolve_function(&method.parameters, &method.body, fn_type)?;
         }
-        for method in static_methods {
-            self.resolve_function(&method.parameters, &method.body, FunctionType::Method)?;
-        }
-        self.end_scope();
-
-        if let Some(_) = superclass {
-            self.end_scope();
-        }
-
-        self.current_class_type = current_class_type;
-        Ok(())
+        self.resolve_class(line, methods, static_methods, superclass)
     }
 }
 
@@ -190,12 +216,12 @@ impl expression::Visitor<ResolveResult> for Resolver {
         Ok(())
     }
 
-    fn visit_variable(&mut self, literal: &str, token: &Token) -> ResolveResult {
+    fn visit_variable(&mut self, literal: &str, token: &Token, id: usize) -> ResolveResult {
         let current_val = self
             .scopes
             .front()
             .and_then(|v| v.get(literal))
-            .copied();
+            .map(|variable| variable.state);
 
         match current_val {
             Some(VariableState::Declared) => {
@@ -205,16 +231,16 @@ impl expression::Visitor<ResolveResult> for Resolver {
                 ))
             },
             _ => {
-                self.resolve_local(literal, token.id, true);
+                self.resolve_local(literal, id, true);
                 Ok(())
             }
         }
     }
 
-    fn visit_assignment(&mut self, token: &Token, right: &Expression) -> ResolveResult {
+    fn visit_assignment(&mut self, token: &Token, right: &Expression, id: usize) -> ResolveResult {
         self.resolve_expression(right)?;
         let variable_name: String = token.lexeme.iter().collect();
-        self.resolve_local(&variable_name, token.id, false);
+        self.resolve_local(&variable_name, id, false);
         Ok(())
     }
 
@@ -253,17 +279,17 @@ impl expression::Visitor<ResolveResult> for Resolver {
         Ok(())
     }
 
-    fn visit_this(&mut self, token: &Token) -> ResolveResult {
+    fn visit_this(&mut self, _token: &Token, id: usize) -> ResolveResult {
         if let ClassType::None = self.current_class_type {
             // TODO: we need to find a way how to pass a real line number here
-            return Err(InterpreterError::new(0, "Can't use 'this' outside of a class.".to_string()))
+            Err(InterpreterError::new(0, "Can't use 'this' outside of a class.".to_string()))
         } else {
-            self.resolve_local(THIS_KEYWORD, token.id, false);
+            self.resolve_local(THIS_KEYWORD, id, false);
             Ok(())
         }
     }
 
-    fn visit_super(&mut self, keyword_token: &Token, _method: &str) -> ResolveResult {
+    fn visit_super(&mut self, keyword_token: &Token, _method: &str, id: usize) -> ResolveResult {
         match self.current_class_type {
             ClassType::None => {
                 Err(InterpreterError::new_from_static_str(keyword_token, "Can't use 'super' outside of a class."))
@@ -275,11 +301,51 @@ impl expression::Visitor<ResolveResult> for Resolver {
                 ))
             }
             ClassType::Subclass => {
-                self.resolve_local(SUPER_KEYWORD, keyword_token.id, false);
+                self.resolve_local(SUPER_KEYWORD, id, false);
                 Ok(())
             }
         }
     }
+
+    fn visit_lambda(&mut self, func: &Rc<LoxFunction>) -> ResolveResult {
+        self.resolve_function(func, FunctionType::Function)
+    }
+
+    fn visit_list(&mut self, elements: &[Expression]) -> ResolveResult {
+        for element in elements {
+            self.resolve_expression(element)?;
+        }
+        Ok(())
+    }
+
+    fn visit_index(&mut self, object: &Expression, index: &Expression, _close_bracket: &Token) -> ResolveResult {
+        self.resolve_expression(object)?;
+        self.resolve_expression(index)?;
+        Ok(())
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Expression,
+        index: &Expression,
+        value: &Expression,
+        _close_bracket: &Token,
+    ) -> ResolveResult {
+        self.resolve_expression(object)?;
+        self.resolve_expression(index)?;
+        self.resolve_expression(value)?;
+        Ok(())
+    }
+
+    fn visit_class_expr(
+        &mut self,
+        line: u32,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: &Option<VariableExpression>,
+    ) -> ResolveResult {
+        self.resolve_class(line, methods, static_methods, superclass)
+    }
 }
 
 impl Resolver {
@@ -289,9 +355,20 @@ impl Resolver {
             scopes: VecDeque::new(),
             current_function_type: FunctionType::None,
             current_class_type: ClassType::None,
+            loop_depth: 0,
+            warnings_enabled: true,
+            warnings: Vec::new(),
         }
     }
 
+    pub fn set_warnings_enabled(&mut self, enabled: bool) {
+        self.warnings_enabled = enabled;
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
     pub fn resolve_statements(&mut self, statements: &[Statement]) -> ResolveResult {
         for statement in statements {
             self.resolve_statement(statement)?;
@@ -305,15 +382,31 @@ impl Resolver {
 
     fn end_scope(&mut self) {
         if let Some(scope) = self.scopes.pop_front() {
-            scope
-                .iter()
-                .filter(|(key, state)| {
-                    key != &THIS_KEYWORD && key != &SUPER_KEYWORD && state != &&VariableState::Read
-                })
-                .for_each(|(key, _)| {
-                    eprintln!("Local variable {} is not used.", key);
+            if !self.warnings_enabled {
+                return;
+            }
+            let mut warnings: Vec<Warning> = scope
+                .into_iter()
+                .filter(|(key, variable)| {
+                    key != THIS_KEYWORD && key != SUPER_KEYWORD && variable.state != VariableState::Read
                 })
+                .map(|(name, variable)| Warning { kind: WarningKind::UnusedLocal, name, line: variable.line })
+                .collect();
+            warnings.sort_by(|left, right| (left.line, &left.name).cmp(&(right.line, &right.name)));
+            self.warnings.extend(warnings);
+        }
+    }
+
+    /// Declares every function of a block before any statement is resolved,
+    /// so local functions can refer to ones declared later in the same block.
+    fn declare_functions(&mut self, statements: &[Statement]) -> ResolveResult {
+        for statement in statements {
+            if let Statement::Function(func) = statement {
+                self.declare(&func.name, func.line)?;
+                self.define(&func.name);
+            }
         }
+        Ok(())
     }
 
     fn resolve_statement(&mut self, statement: &Statement) -> ResolveResult {
@@ -324,7 +417,14 @@ impl Resolver {
         expression.accept(self)
     }
 
-    fn declare(&mut self, name: &str) -> ResolveResult {
+    fn declare(&mut self, name: &str, line: u32) -> ResolveResult {
+        self.declare_variable(name, line, false)
+    }
+
+    fn declare_variable(&mut self, name: &str, line: u32, is_parameter: bool) -> ResolveResult {
+        if !is_parameter && self.shadows_parameter(name) && self.warnings_enabled {
+            self.warnings.push(Warning { kind: WarningKind::ShadowedParameter, name: name.to_string(), line });
+        }
         match self.scopes.front_mut() {
             Some(inner_scope) if inner_scope.contains_key(name) => {
                 let message = "Already a variable with this name in this scope.".to_string();
@@ -332,21 +432,33 @@ impl Resolver {
                 Err(InterpreterError::new(0, message))
             }
             Some(inner_scope) => {
-                inner_scope.insert(name.to_string(), VariableState::Declared);
+                let variable = Variable { state: VariableState::Declared, line, is_parameter };
+                inner_scope.insert(name.to_string(), variable);
                 Ok(())
             }
             None => Ok(()),
         }
     }
 
+    /// Whether the closest enclosing declaration of `name` is a parameter.
+    /// A parameter and a local of the function body share the same scope,
+    /// so only locals of nested blocks can shadow one.
+    fn shadows_parameter(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .skip(1)
+            .find_map(|scope| scope.get(name))
+            .is_some_and(|variable| variable.is_parameter)
+    }
+
     fn define(&mut self, name: &str) {
         let option_ref = self.scopes.front_mut().and_then(|v| v.get_mut(name));
-        if let Some(state) = option_ref {
-            *state = VariableState::Defined;
+        if let Some(variable) = option_ref {
+            variable.state = VariableState::Defined;
         }
     }
 
-    fn resolve_local(&mut self, name: &str, token_id: usize, is_read: bool)  {
+    fn resolve_local(&mut self, name: &str, expression_id: usize, is_read: bool)  {
         let scope_len = self.scopes.len();
         for index in (0..scope_len).rev() {
             let scope = &mut self.scopes[index];
@@ -356,27 +468,197 @@ impl Resolver {
             self.interpreter
                 .as_ref()
                 .borrow_mut()
-                .resolve(token_id, index);
+                .resolve(expression_id, index);
 
             if is_read {
-                scope.insert(name.to_string(), VariableState::Read);
+                if let Some(variable) = scope.get_mut(name) {
+                    variable.state = VariableState::Read;
+                }
             }
         }
     }
 
-    fn resolve_function(&mut self, params: &[String], body: &[Statement], fn_type: FunctionType) -> ResolveResult {
+    fn check_inside_loop(&self, line: u32, message: &'static str) -> ResolveResult {
+        if self.loop_depth == 0 {
+            Err(InterpreterError::new(line as usize, message.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn resolve_function(&mut self, func: &LoxFunction, fn_type: FunctionType) -> ResolveResult {
         let enclosing_function = self.current_function_type;
         self.current_function_type = fn_type;
+        let enclosing_loop_depth = mem::replace(&mut self.loop_depth, 0);
 
         self.begin_scope();
-        for parameter in params {
-            self.declare(parameter)?;
+        for parameter in &func.parameters {
+            self.declare_variable(parameter, func.line, true)?;
             self.define(parameter);
         }
-        self.resolve_statements(body)?;
+        self.declare_functions(&func.body)?;
+        self.resolve_statements(&func.body)?;
         self.end_scope();
         self.current_function_type = enclosing_function;
+        self.loop_depth = enclosing_loop_depth;
+
+        Ok(())
+    }
+
+    fn resolve_class(
+        &mut self,
+        line: u32,
+        methods: &[Rc<LoxFunction>],
+        static_methods: &[Rc<LoxFunction>],
+        superclass: &Option<VariableExpression>
+    ) -> ResolveResult {
+        let current_class_type = self.current_class_type;
+        self.current_class_type = superclass
+            .as_ref()
+            .map(|_| ClassType::Subclass)
+            .unwrap_or(ClassType::Class);
+
+        if let Some(expression) = superclass {
+            self.visit_variable(&expression.name, &expression.token, expression.id)?;
+            self.begin_scope();
+            self.declare(SUPER_KEYWORD, line)?;
+            self.define(SUPER_KEYWORD)
+        }
+
+        self.begin_scope();
+        self.declare(THIS_KEYWORD, line)?;
+        self.define(THIS_KEYWORD);
+        for method in methods {
+            let fn_type = if method.as_ref().name == CONSTRUCTOR_KEYWORD {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.resolve_function(method, fn_type)?;
+        }
+        for method in static_methods {
+            self.resolve_function(method, FunctionType::Method)?;
+        }
+        self.end_scope();
+
+        if superclass.is_some() {
+            self.end_scope();
+        }
 
+        self.current_class_type = current_class_type;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::error::Error;
+    use super::super::parser::Parser;
+    use super::super::scanner::Scanner;
+
+    fn warnings(source: &str, enabled: bool) -> Vec<Warning> {
+        let tokens = Scanner::new(source).scan_tokens();
+        let statements = Parser::new(&tokens).parse();
+        let interpreter = Rc::new(RefCell::new(Interpreter::new()));
+        let mut resolver = Resolver::new(interpreter);
+        resolver.set_warnings_enabled(enabled);
+        assert!(resolver.resolve_statements(&statements).is_ok());
+        resolver.warnings().to_vec()
+    }
+
+    fn first_error(source: &str) -> Option<(usize, String)> {
+        let tokens = Scanner::new(source).scan_tokens();
+        let statements = Parser::new(&tokens).parse();
+        let interpreter = Rc::new(RefCell::new(Interpreter::new()));
+        let mut resolver = Resolver::new(interpreter);
+        resolver.resolve_statements(&statements)
+            .err()
+            .map(|error| (error.line(), error.message().to_string()))
+    }
+
+    fn unused(name: &str, line: u32) -> Warning {
+        Warning { kind: WarningKind::UnusedLocal, name: name.to_string(), line }
+    }
+
+    #[test]
+    fn read_local_in_its_own_initializer() {
+        let message = "Can't read local variable in its own initializer.".to_string();
+        assert_eq!(first_error("{\n  var a = a;\n}"), Some((2, message.clone())));
+        assert_eq!(first_error("fun f() {\n  var a = a;\n}"), Some((2, message.clone())));
+        assert_eq!(first_error("{\n  var a = 1;\n  {\n    var a = a;\n  }\n}"), Some((4, message)));
+        assert_eq!(first_error("{ var a = 1; { var b = a; print b; } }"), None);
+        assert_eq!(first_error("var a = a;"), None);
+    }
+
+    #[test]
+    fn unused_local_warnings() {
+        let source = "{ var a = 1; var b = 2; print b; }";
+        assert_eq!(warnings(source, true), vec![unused("a", 1)]);
+        assert!(warnings(source, false).is_empty());
+        assert!(warnings("fun f(a) { return a; } class A { m() { return this; } }", true).is_empty());
+    }
+
+    #[test]
+    fn warning_message_includes_declaration_line() {
+        let source = "fun f(unused) {\n  var a = 1;\n\n  class Local {}\n  return a;\n}";
+        let messages: Vec<String> = warnings(source, true).iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 1] Local variable 'unused' is not used.",
+                "[line 4] Local variable 'Local' is not used.",
+            ]
+        );
+    }
+
+    #[test]
+    fn warnings_name_unused_locals_with_lines() {
+        let source = "{\n  var used = 1;\n  var first;\n  var second = used;\n}";
+        assert_eq!(
+            warnings(source, true),
+            vec![
+                unused("first", 3),
+                unused("second", 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_functions_are_declared_before_the_block_is_resolved() {
+        let source = "{ fun isEven(n) { if (n == 0) return true; return isOdd(n - 1); } \
+            fun isOdd(n) { if (n == 0) return false; return isEven(n - 1); } print isEven(4); }";
+        assert_eq!(first_error(source), None);
+        assert!(warnings(source, true).is_empty());
+        let message = "Already a variable with this name in this scope.".to_string();
+        assert_eq!(first_error("{ fun f() {} fun f() {} }"), Some((0, message.clone())));
+        assert_eq!(first_error("fun g() { var f = 1; fun f() {} }"), Some((0, message)));
+    }
+
+    #[test]
+    fn shadowed_parameter_warnings() {
+        let source = "fun f(a, b) {\n  {\n    var a = b;\n    print a;\n  }\n  while (b) {\n    fun b() {}\n    b();\n  }\n}";
+        let messages: Vec<String> = warnings(source, true).iter().map(|warning| warning.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 3] Local variable 'a' shadows a parameter.",
+                "[line 7] Local variable 'b' shadows a parameter.",
+            ]
+        );
+        assert!(warnings(source, false).is_empty());
+
+        let source = "fun f(a) {\n  {\n    var c = a;\n    print c;\n  }\n  fun g(a) { return a; }\n  return g;\n}\n{ var a = 1; { var a = 2; print a; } print a; }";
+        assert!(warnings(source, true).is_empty());
+    }
+
+    #[test]
+    fn loop_control_outside_of_loop() {
+        let message = |keyword| format!("Can't use '{}' outside of a loop.", keyword);
+        assert_eq!(first_error("break;"), Some((1, message("break"))));
+        assert_eq!(first_error("{\n  continue;\n}"), Some((2, message("continue"))));
+        assert_eq!(first_error("while (true) {\n  fun f() { break; }\n}"), Some((2, message("break"))));
+        assert_eq!(first_error("while (true) { if (true) break; else continue; }"), None);
+        assert_eq!(first_error("for (;;) { { continue; } }"), None);
+    }
+}
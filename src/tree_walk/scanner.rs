@@ -3,13 +3,18 @@ use super::token_type::{
     Delimiter, ExpressionOperatorTokenType, KeywordTokenType, LiteralTokenType,
     SingleCharTokenType, TokenType,
 };
+use crate::number::{parse_number, radix_of_prefix};
 use peekmore::{PeekMore, PeekMoreIterator};
 use std::collections::HashMap;
 use std::str::Chars;
 
+/// Longest identifier or string literal, in characters, the scanner accepts.
+pub const DEFAULT_MAX_TOKEN_LENGTH: usize = 1 << 16;
+
 pub struct Scanner<'a> {
     source_iter: PeekMoreIterator<Chars<'a>>,
-    current_id: usize,
+    had_error: bool,
+    max_token_length: usize,
 }
 
 struct MatchedExpression {
@@ -18,12 +23,11 @@ struct MatchedExpression {
 }
 
 impl MatchedExpression {
-    fn make_token(self, line: u32, id: usize) -> Token {
+    fn make_token(self, line: u32) -> Token {
         Token {
             token_type: TokenType::ExpressionOperator(self.token_type),
             lexeme: self.lexeme,
             line,
-            id,
         }
     }
 }
@@ -40,10 +44,21 @@ impl<'a> Scanner<'a> {
     pub fn new(source: &'a str) -> Scanner<'a> {
         Scanner {
             source_iter: source.chars().peekmore(),
-            current_id: 0,
+            had_error: false,
+            max_token_length: DEFAULT_MAX_TOKEN_LENGTH,
         }
     }
 
+    /// Identifiers and string literals longer than `max_token_length`
+    /// characters are reported as errors instead of being buffered.
+    pub fn set_max_token_length(&mut self, max_token_length: usize) {
+        self.max_token_length = max_token_length;
+    }
+
+    pub fn had_error(&self) -> bool {
+        self.had_error
+    }
+
     pub fn scan_tokens(&mut self) -> Vec<Token> {
         let mut tokens = vec![];
         let mut line = 1u32;
@@ -62,6 +77,7 @@ impl<'a> Scanner<'a> {
                     tokens.push(token);
                 }
                 CharacterScanResult::Err(message) => {
+                    self.had_error = true;
                     println!("[Line {}] Error: {}", line, message);
                 }
                 CharacterScanResult::Skipped => {}
@@ -71,7 +87,6 @@ impl<'a> Scanner<'a> {
             token_type: TokenType::Eof,
             lexeme: Vec::new(),
             line,
-            id: self.make_token_id(),
         });
         tokens
     }
@@ -83,9 +98,8 @@ impl<'a> Scanner<'a> {
         keywords: &HashMap<String, KeywordTokenType>,
     ) -> CharacterScanResult {
         let make_result = |token| CharacterScanResult::Token(token);
-        let id = self.make_token_id();
         let make_token =
-            |token_type| make_result(Token::new_single_char(token_type, character, line, id));
+            |token_type| make_result(Token::new_single_char(token_type, character, line));
 
         match character {
             '(' => make_token(TokenType::OpenDelimiter(Delimiter::Paren)),
@@ -95,11 +109,21 @@ impl<'a> Scanner<'a> {
             '[' => make_token(TokenType::OpenDelimiter(Delimiter::Bracket)),
             ']' => make_token(TokenType::CloseDelimiter(Delimiter::Bracket)),
             ',' => make_token(TokenType::SingleChar(SingleCharTokenType::Comma)),
+            '.' if self.source_iter.peek().is_some_and(char::is_ascii_digit) => {
+                match self.scan_number(character) {
+                    Ok((number, lexeme)) => {
+                        let token_type = TokenType::Literal(LiteralTokenType::Number(number));
+                        make_result(Token::new(token_type, lexeme, line))
+                    }
+                    Err(message) => CharacterScanResult::Err(message.to_string()),
+                }
+            }
             '.' => make_token(TokenType::SingleChar(SingleCharTokenType::Dot)),
             '-' => make_token(TokenType::SingleChar(SingleCharTokenType::Minus)),
             '+' => make_token(TokenType::SingleChar(SingleCharTokenType::Plus)),
             ';' => make_token(TokenType::SingleChar(SingleCharTokenType::Semicolon)),
             '*' => make_token(TokenType::SingleChar(SingleCharTokenType::Star)),
+            '%' => make_token(TokenType::SingleChar(SingleCharTokenType::Percent)),
             '!' => make_result(
                 self.matches_expression(
                     '!',
@@ -107,7 +131,7 @@ impl<'a> Scanner<'a> {
                     ExpressionOperatorTokenType::NotEqual,
                     ExpressionOperatorTokenType::Not,
                 )
-                .make_token(line, id),
+                .make_token(line),
             ),
             '=' => make_result(
                 self.matches_expression(
@@ -116,7 +140,7 @@ impl<'a> Scanner<'a> {
                     ExpressionOperatorTokenType::EqualEqual,
                     ExpressionOperatorTokenType::Equal,
                 )
-                .make_token(line, id),
+                .make_token(line),
             ),
             '>' => make_result(
                 self.matches_expression(
@@ -125,7 +149,7 @@ impl<'a> Scanner<'a> {
                     ExpressionOperatorTokenType::GreaterEqual,
                     ExpressionOperatorTokenType::Greater,
                 )
-                .make_token(line, id),
+                .make_token(line),
             ),
             '<' => make_result(
                 self.matches_expression(
@@ -134,18 +158,18 @@ impl<'a> Scanner<'a> {
                     ExpressionOperatorTokenType::LessEqual,
                     ExpressionOperatorTokenType::Less,
                 )
-                .make_token(line, id),
+                .make_token(line),
             ),
             '/' => {
                 if let Some(token_type) = self.scan_slash() {
                     make_token(token_type)
                 } else {
-                    CharacterScanResult::NewLine
+                    CharacterScanResult::Skipped
                 }
             }
             ' ' | '\r' | '\t' => CharacterScanResult::Skipped,
-            '"' => self.scan_string_literal().map_or(
-                CharacterScanResult::Err("Unterminated string".to_string()),
+            '"' => self.scan_string_literal().map_or_else(
+                |message| CharacterScanResult::Err(message.to_string()),
                 |(literal, line_count)| {
                     let lexeme = literal.chars().clone().collect();
                     let token_type = TokenType::Literal(LiteralTokenType::String(literal));
@@ -153,33 +177,27 @@ impl<'a> Scanner<'a> {
                         token_type,
                         lexeme,
                         line,
-                        id,
                     };
                     CharacterScanResult::StringLiteral(token, line_count)
                 },
             ),
             '\n' => CharacterScanResult::NewLine,
-            character if character.is_digit(10) => {
-                let (number, lexeme) = self.scan_number(character);
-                let token_type = TokenType::Literal(LiteralTokenType::Number(number));
-                let token = Token::new(token_type, lexeme, line, id);
-                make_result(token)
-            }
-            character if character.is_alphanumeric() => {
-                let (token_type, lexeme) = self.scan_identifier(character, keywords);
-                let token = Token::new(token_type, lexeme, line, id);
-                make_result(token)
-            }
+            character if character.is_ascii_digit() => match self.scan_number(character) {
+                Ok((number, lexeme)) => {
+                    let token_type = TokenType::Literal(LiteralTokenType::Number(number));
+                    let token = Token::new(token_type, lexeme, line);
+                    make_result(token)
+                }
+                Err(message) => CharacterScanResult::Err(message.to_string()),
+            },
+            character if character.is_alphanumeric() => match self.scan_identifier(character, keywords) {
+                Ok((token_type, lexeme)) => make_result(Token::new(token_type, lexeme, line)),
+                Err(message) => CharacterScanResult::Err(message.to_string()),
+            },
             _ => CharacterScanResult::Err(format!("Unknown symbol {}", character)),
         }
     }
 
-    fn make_token_id(&mut self) -> usize {
-        let id = self.current_id;
-        self.current_id += 1;
-        id
-    }
-
     fn matches_expression(
         &mut self,
         first_char: char,
@@ -208,43 +226,61 @@ impl<'a> Scanner<'a> {
     }
 
     fn scan_slash(&mut self) -> Option<TokenType> {
-        let mut is_advanced = false;
+        if self.source_iter.peek() != Some(&'/') {
+            return Some(TokenType::SingleChar(SingleCharTokenType::Slash));
+        }
         while let Some(next) = self.source_iter.peek() {
-            match next {
-                '/' => is_advanced = true,
-                '\n' => return None,
-                _ => {
-                    if !is_advanced {
-                        return Some(TokenType::SingleChar(SingleCharTokenType::Slash));
-                    }
-                }
+            if *next == '\n' {
+                break;
             }
             self.source_iter.next();
         }
         None
     }
 
-    fn scan_string_literal(&mut self) -> Option<(String, u32)> {
+    /// Keeps consuming a literal past the length limit, so scanning resumes
+    /// after its closing quote, but stops buffering its characters.
+    fn scan_string_literal(&mut self) -> Result<(String, u32), &'static str> {
         let mut result = String::new();
         let mut new_line_count = 0u32;
-        for next in &mut self.source_iter {
+        for (length, next) in (&mut self.source_iter).enumerate() {
             match next {
+                '"' if length > self.max_token_length => return Err("String literal is too long."),
+                '"' => return Ok((result, new_line_count)),
                 '\n' => new_line_count += 1,
-                '"' => return Some((result, new_line_count)),
+                _ if length >= self.max_token_length => {}
                 _ => result.push(next),
             }
         }
-        None
+        Err("Unterminated string")
     }
 
-    fn scan_number(&mut self, first_char: char) -> (f64, String) {
+    /// A `.` only belongs to the number when a digit follows it, so `5.` scans
+    /// as the number `5` and a `Dot` token, which the parser then rejects as
+    /// a property access without a name.
+    fn scan_number(&mut self, first_char: char) -> Result<(f64, String), &'static str> {
+        if first_char == '.' {
+            let mut result = self.scan_digits();
+            result.insert(0, first_char);
+            return Scanner::chars_to_number(&result);
+        }
+        if first_char == '0' {
+            if let Some(&prefix) = self.source_iter.peek() {
+                if let Some(radix) = radix_of_prefix(prefix) {
+                    self.source_iter.next();
+                    let mut result = vec![first_char, prefix];
+                    result.extend(self.scan_digits_of_radix(radix));
+                    return Scanner::chars_to_number(&result);
+                }
+            }
+        }
         let mut result = self.scan_digits();
         result.insert(0, first_char);
 
         if let Some(&'.') = self.source_iter.peek() {
             let _ = self.source_iter.advance_cursor();
             if let Some(character) = self.source_iter.peek() {
-                if character.is_digit(10) {
+                if character.is_ascii_digit() {
                     self.source_iter.reset_cursor();
                     self.source_iter.next();
                     let digits = self.scan_digits();
@@ -259,9 +295,13 @@ impl<'a> Scanner<'a> {
     }
 
     fn scan_digits(&mut self) -> Vec<char> {
+        self.scan_digits_of_radix(10)
+    }
+
+    fn scan_digits_of_radix(&mut self, radix: u32) -> Vec<char> {
         let mut result = Vec::new();
         while let Some(next) = self.source_iter.peek() {
-            if next.is_digit(10) {
+            if next.is_digit(radix) || *next == '_' {
                 result.push(*next);
                 self.source_iter.next();
             } else {
@@ -271,35 +311,171 @@ impl<'a> Scanner<'a> {
         result
     }
 
-    fn chars_to_number(chars: &[char]) -> (f64, String) {
+    fn chars_to_number(chars: &[char]) -> Result<(f64, String), &'static str> {
         let string: String = chars.iter().collect();
-        (string.parse().unwrap(), string)
+        parse_number(&string).map(|number| (number, string))
     }
 
     fn scan_identifier(
         &mut self,
         first_char: char,
         keywords: &HashMap<String, KeywordTokenType>,
-    ) -> (TokenType, String) {
+    ) -> Result<(TokenType, String), &'static str> {
         let mut keyword: Vec<char> = vec![first_char];
+        let mut length = 1usize;
 
         while let Some(next) = self.source_iter.peek() {
             if next.is_alphanumeric() {
-                keyword.push(*next);
+                if length < self.max_token_length {
+                    keyword.push(*next);
+                }
+                length += 1;
                 self.source_iter.next();
             } else {
                 break;
             }
         }
+        if length > self.max_token_length {
+            return Err("Identifier is too long.");
+        }
         let string: String = keyword.iter().collect();
         if let Some(keyword) = keywords.get(&string) {
-            (TokenType::Keyword(keyword.clone()), string)
+            Ok((TokenType::Keyword(keyword.clone()), string))
         } else {
             let lexeme = string.clone();
-            (
+            Ok((
                 TokenType::Literal(LiteralTokenType::Identifier(string)),
                 lexeme,
-            )
+            ))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_types(source: &str) -> Vec<TokenType> {
+        Scanner::new(source)
+            .scan_tokens()
+            .into_iter()
+            .map(|token| token.token_type)
+            .collect()
+    }
+
+    #[test]
+    fn number_literal() {
+        assert_eq!(token_types("12.5"), vec![
+            TokenType::Literal(LiteralTokenType::Number(12.5)),
+            TokenType::Eof,
+        ]);
+    }
+
+    fn identifier(name: &str) -> TokenType {
+        TokenType::Literal(LiteralTokenType::Identifier(name.to_string()))
+    }
+
+    #[test]
+    fn slash() {
+        let slash = TokenType::SingleChar(SingleCharTokenType::Slash);
+        assert_eq!(token_types("a / b"), vec![identifier("a"), slash.clone(), identifier("b"), TokenType::Eof]);
+        assert_eq!(token_types("a/b"), vec![identifier("a"), slash.clone(), identifier("b"), TokenType::Eof]);
+        assert_eq!(token_types("a /\nb"), vec![identifier("a"), slash.clone(), identifier("b"), TokenType::Eof]);
+        assert_eq!(token_types("a /"), vec![identifier("a"), slash, TokenType::Eof]);
+    }
+
+    #[test]
+    fn comment() {
+        assert_eq!(token_types("a // comment / b"), vec![identifier("a"), TokenType::Eof]);
+        let tokens = Scanner::new("a // comment\nb").scan_tokens();
+        let lines: Vec<(TokenType, u32)> = tokens.into_iter().map(|token| (token.token_type, token.line)).collect();
+        assert_eq!(lines, vec![(identifier("a"), 1), (identifier("b"), 2), (TokenType::Eof, 2)]);
+    }
+
+    fn scan_error(source: &str) -> Option<String> {
+        scan_error_with_limit(source, DEFAULT_MAX_TOKEN_LENGTH)
+    }
+
+    fn scan_error_with_limit(source: &str, max_token_length: usize) -> Option<String> {
+        let mut scanner = Scanner::new(source);
+        scanner.set_max_token_length(max_token_length);
+        let character = scanner.source_iter.next()?;
+        match scanner.scan_token(character, 1, &KeywordTokenType::make_keywords()) {
+            CharacterScanResult::Err(message) => Some(message),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn number_literal_with_separators() {
+        assert_eq!(token_types("1_000"), vec![
+            TokenType::Literal(LiteralTokenType::Number(1000.0)),
+            TokenType::Eof,
+        ]);
+        assert_eq!(token_types("1_0.2_5"), vec![
+            TokenType::Literal(LiteralTokenType::Number(10.25)),
+            TokenType::Eof,
+        ]);
+        let error = Some("Digit separator '_' must be placed between digits.".to_string());
+        assert_eq!(scan_error("1__0"), error);
+        assert_eq!(scan_error("1_"), error);
+        assert_eq!(scan_error("_1"), Some("Unknown symbol _".to_string()));
+    }
+
+    #[test]
+    fn leading_dot_number_literal() {
+        assert_eq!(token_types(".5 1.5"), vec![
+            TokenType::Literal(LiteralTokenType::Number(0.5)),
+            TokenType::Literal(LiteralTokenType::Number(1.5)),
+            TokenType::Eof,
+        ]);
+        assert_eq!(token_types("a.b"), vec![
+            identifier("a"),
+            TokenType::SingleChar(SingleCharTokenType::Dot),
+            identifier("b"),
+            TokenType::Eof,
+        ]);
+    }
+
+    #[test]
+    fn trailing_dot_is_not_part_of_number() {
+        let five = TokenType::Literal(LiteralTokenType::Number(5.0));
+        let dot = TokenType::SingleChar(SingleCharTokenType::Dot);
+        assert_eq!(token_types("5."), vec![five.clone(), dot.clone(), TokenType::Eof]);
+        assert_eq!(token_types("5.foo"), vec![five, dot, identifier("foo"), TokenType::Eof]);
+    }
+
+    #[test]
+    fn radix_number_literals() {
+        assert_eq!(token_types("0xff 0b1010"), vec![
+            TokenType::Literal(LiteralTokenType::Number(255.0)),
+            TokenType::Literal(LiteralTokenType::Number(10.0)),
+            TokenType::Eof,
+        ]);
+        assert_eq!(scan_error("0x"), Some("Expected digits after number prefix.".to_string()));
+        assert_eq!(scan_error("0x;"), Some("Expected digits after number prefix.".to_string()));
+    }
+
+    #[test]
+    fn out_of_range_number_literal_is_an_error() {
+        let source = format!("1{}", "0".repeat(400));
+        assert_eq!(token_types(&source), vec![TokenType::Eof]);
+    }
+
+    #[test]
+    fn token_length_limit() {
+        assert_eq!(scan_error_with_limit("abcd", 4), None);
+        assert_eq!(scan_error_with_limit("abcde", 4), Some("Identifier is too long.".to_string()));
+        assert_eq!(scan_error_with_limit("\"abcd\"", 4), None);
+        assert_eq!(scan_error_with_limit("\"abcde\"", 4), Some("String literal is too long.".to_string()));
+        assert_eq!(scan_error_with_limit("\"abcde", 4), Some("Unterminated string".to_string()));
+
+        let mut scanner = Scanner::new("\"abcde\" abcd");
+        scanner.set_max_token_length(4);
+        let tokens: Vec<TokenType> = scanner.scan_tokens().into_iter().map(|token| token.token_type).collect();
+        assert!(scanner.had_error());
+        assert_eq!(tokens, vec![identifier("abcd"), TokenType::Eof]);
+        let long_identifier = "a".repeat(DEFAULT_MAX_TOKEN_LENGTH);
+        assert_eq!(token_types(&long_identifier), vec![identifier(&long_identifier), TokenType::Eof]);
+    }
+}
@@ -2,13 +2,14 @@ use super::expression::{Expression, VariableExpression};
 use super::lox_function::LoxFunction;
 use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
     Expression(Expression),
     Print(Expression),
     Variable {
         name: String,
         value: Option<Expression>,
+        line: u32,
     },
     Block(Vec<Statement>),
     If {
@@ -19,11 +20,15 @@ pub enum Statement {
     While {
         condition: Expression,
         body: Box<Statement>,
+        increment: Option<Expression>,
     },
+    Break(u32),
+    Continue(u32),
     Function(Rc<LoxFunction>),
     Return(Expression),
     Class {
         name: String,
+        line: u32,
         methods: Vec<Rc<LoxFunction>>,
         static_methods: Vec<Rc<LoxFunction>>,
         superclass: Option<VariableExpression>
@@ -33,7 +38,7 @@ pub enum Statement {
 pub trait Visitor<T> {
     fn visit_print(&mut self, expression: &Expression) -> T;
     fn visit_expression(&mut self, expression: &Expression) -> T;
-    fn visit_variable_stmt(&mut self, name: &str, value: &Option<Expression>) -> T;
+    fn visit_variable_stmt(&mut self, name: &str, value: &Option<Expression>, line: u32) -> T;
     fn visit_block(&mut self, statements: &[Statement]) -> T;
     fn visit_if(
         &mut self,
@@ -41,12 +46,15 @@ pub trait Visitor<T> {
         then_branch: &Statement,
         else_branch: &Option<Box<Statement>>,
     ) -> T;
-    fn visit_while(&mut self, condition: &Expression, body: &Statement) -> T;
+    fn visit_while(&mut self, condition: &Expression, body: &Statement, increment: &Option<Expression>) -> T;
+    fn visit_break(&mut self, line: u32) -> T;
+    fn visit_continue(&mut self, line: u32) -> T;
     fn visit_function(&mut self, func: Rc<LoxFunction>) -> T;
     fn visit_return(&mut self, expression: &Expression) -> T;
     fn visit_class(
         &mut self,
         name: &str,
+        line: u32,
         methods: &[Rc<LoxFunction>],
         static_methods: &[Rc<LoxFunction>],
         superclass: &Option<VariableExpression>
@@ -58,18 +66,20 @@ impl Statement {
         match self {
             Statement::Expression(expr) => visitor.visit_expression(expr),
             Statement::Print(expr) => visitor.visit_print(expr),
-            Statement::Variable { name, value } => visitor.visit_variable_stmt(name, value),
+            Statement::Variable { name, value, line } => visitor.visit_variable_stmt(name, value, *line),
             Statement::Block(statements) => visitor.visit_block(statements),
             Statement::If {
                 condition,
                 then_branch,
                 else_branch,
             } => visitor.visit_if(condition, then_branch, else_branch),
-            Statement::While { condition, body } => visitor.visit_while(condition, body),
+            Statement::While { condition, body, increment } => visitor.visit_while(condition, body, increment),
+            Statement::Break(line) => visitor.visit_break(*line),
+            Statement::Continue(line) => visitor.visit_continue(*line),
             Statement::Function(func) => visitor.visit_function(func.clone()),
             Statement::Return(expr) => visitor.visit_return(expr),
-            Statement::Class { name, methods, static_methods, superclass } => {
-                visitor.visit_class(name, methods, static_methods, superclass)
+            Statement::Class { name, line, methods, static_methods, superclass } => {
+                visitor.visit_class(name, *line, methods, static_methods, superclass)
             },
         }
     }
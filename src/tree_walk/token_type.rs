@@ -27,6 +27,7 @@ pub enum SingleCharTokenType {
     Semicolon,
     Slash,
     Star,
+    Percent,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -51,7 +52,9 @@ pub enum LiteralTokenType {
 #[derive(Debug, Clone, PartialEq)]
 pub enum KeywordTokenType {
     And,
+    Break,
     Class,
+    Continue,
     Else,
     False,
     Fun,
@@ -72,7 +75,9 @@ impl KeywordTokenType {
     pub fn make_keywords() -> HashMap<String, KeywordTokenType> {
         vec![
             ("and", KeywordTokenType::And),
+            ("break", KeywordTokenType::Break),
             ("class", KeywordTokenType::Class),
+            ("continue", KeywordTokenType::Continue),
             ("else", KeywordTokenType::Else),
             ("false", KeywordTokenType::False),
             ("for", KeywordTokenType::For),
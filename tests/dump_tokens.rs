@@ -0,0 +1,35 @@
+use rlox::{bytecode, tree_walk};
+
+fn token_types(dump: Vec<u8>) -> Vec<String> {
+    String::from_utf8(dump)
+        .unwrap()
+        .lines()
+        .map(|line| line.split(' ').next().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn bytecode_token_dump() {
+    let mut output = Vec::new();
+    assert!(bytecode::dump_tokens("var x = 1;", &mut output).is_ok());
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Var \"var\" 1\nIdentifier \"x\" 1\nEqual \"=\" 1\nNumber \"1\" 1\nSemicolon \";\" 1\nEof \"\" 1\n"
+    );
+}
+
+#[test]
+fn bytecode_token_dump_reports_scan_errors() {
+    let mut output = Vec::new();
+    assert!(bytecode::dump_tokens("var x = \"1;", &mut output).is_err());
+}
+
+#[test]
+fn tree_walk_token_dump() {
+    let mut output = Vec::new();
+    tree_walk::dump_tokens("var x = 1;", &mut output);
+    assert_eq!(
+        token_types(output),
+        vec!["Keyword(Var)", "Literal(Identifier(\"x\"))", "ExpressionOperator(Equal)", "Literal(Number(1.0))", "SingleChar(Semicolon)", "Eof"]
+    );
+}
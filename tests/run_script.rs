@@ -0,0 +1,260 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs};
+
+static NEXT_SCRIPT_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn run_with_output(arguments: &[&str], source: &str) -> Output {
+    let path = env::temp_dir().join(format!(
+        "rlox_run_script_{}_{}.lox",
+        std::process::id(),
+        NEXT_SCRIPT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .args(arguments)
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+fn run(arguments: &[&str], source: &str) -> String {
+    String::from_utf8(run_with_output(arguments, source).stdout).unwrap()
+}
+
+/// Arguments selecting each backend, for tests that both must pass alike.
+const BACKENDS: [&[&str]; 2] = [&[], &["--tree-walk"]];
+
+fn assert_backends_print(source: &str, expected: &str) {
+    for arguments in BACKENDS {
+        assert_eq!(run(arguments, source), expected, "{:?}", arguments);
+    }
+}
+
+/// Message of the runtime error a script fails with, without the location
+/// each backend reports it at.
+fn runtime_error(arguments: &[&str], source: &str) -> String {
+    let output = run_with_output(arguments, source);
+    assert_eq!(output.status.code(), Some(70), "{:?}", arguments);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let line = stderr.lines().next().unwrap_or_default();
+    line.split_once("] Error: ").map_or(line, |(_, message)| message).to_string()
+}
+
+#[test]
+fn prints_only_program_output() {
+    assert_eq!(run(&[], "print 1;"), "1\n");
+}
+
+#[test]
+fn trace_prints_compiler_output() {
+    let output = run(&["--trace"], "print 1;");
+    assert!(output.contains("add constant"), "{}", output);
+    assert!(output.ends_with("1\n"), "{}", output);
+}
+
+#[test]
+fn tree_walk_unused_local_warnings() {
+    let source = "{ var unused = 1; print 2; }";
+    let output = run_with_output(&["--tree-walk"], source);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "[line 1] Local variable 'unused' is not used.\n");
+
+    let output = run_with_output(&["--tree-walk", "--no-warnings"], source);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn radix_number_literals() {
+    let source = "print 0xff; print 0b1010;";
+    assert_eq!(run(&[], source), "255\n10\n");
+    assert_eq!(run(&["--tree-walk"], source), "255\n10\n");
+}
+
+#[test]
+fn leading_dot_number_literal() {
+    let source = "print .5 == 0.5; print .5 + 1;";
+    assert_eq!(run(&[], source), "true\n1.5\n");
+    assert_eq!(run(&["--tree-walk"], source), "true\n1.5\n");
+}
+
+#[test]
+fn prompt_keeps_globals_between_lines() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"var x = 1;\nprint x;\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "> > 1\n> ");
+}
+
+#[test]
+fn exit_codes() {
+    for arguments in [&[][..], &["--tree-walk"][..]] {
+        let exit_code = |source| run_with_output(arguments, source).status.code();
+        assert_eq!(exit_code("print 1;"), Some(0), "{:?}", arguments);
+        assert_eq!(exit_code("print 1 +;"), Some(65), "{:?}", arguments);
+        assert_eq!(exit_code("print \"unterminated;"), Some(65), "{:?}", arguments);
+        assert_eq!(exit_code("print undefined;"), Some(70), "{:?}", arguments);
+    }
+}
+
+#[test]
+fn runtime_error_line() {
+    let output = run_with_output(&[], "var a = 1;\nvar b = \"b\";\nprint a / b;");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "Operands must be numbers.\n[line 3] in script\n");
+
+    let output = run_with_output(&[], "fun f(x) {\n  return x / nil;\n}\nf(1);");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "Operands must be numbers.\n[line 2] in f()\n[line 4] in script\n");
+}
+
+#[test]
+fn backends_agree_on_nesting_limit() {
+    let depth = rlox::limits::DEFAULT_MAX_NESTING_DEPTH;
+    let nested = |depth: usize| format!("print {}1{};", "-(".repeat(depth), ")".repeat(depth));
+    let classes = |depth: usize| format!("print {}1{};", "class { m() { return ".repeat(depth), "; } }".repeat(depth));
+    for arguments in [&[][..], &["--tree-walk"][..]] {
+        assert_eq!(run(arguments, &nested(depth - 1)), "-1\n", "{:?}", arguments);
+        assert_eq!(run_with_output(arguments, &nested(depth + 1)).status.code(), Some(65), "{:?}", arguments);
+        assert_eq!(run(arguments, &classes(depth - 1)), "anonymous\n", "{:?}", arguments);
+    }
+}
+
+#[test]
+fn max_nesting_depth_option() {
+    let nested = |depth: usize| format!("print {}1{};", "-(".repeat(depth), ")".repeat(depth));
+    for backend in [&[][..], &["--tree-walk"][..]] {
+        let arguments = [backend, &["--max-nesting-depth=8"]].concat();
+        assert_eq!(run(&arguments, &nested(7)), "-1\n", "{:?}", arguments);
+        assert_eq!(run_with_output(&arguments, &nested(9)).status.code(), Some(65), "{:?}", arguments);
+        let arguments = [backend, &["--max-nesting-depth=2000"]].concat();
+        assert_eq!(run(&arguments, &nested(1999)), "-1\n", "{:?}", arguments);
+    }
+    assert_eq!(run_with_output(&["--max-nesting-depth=deep"], "print 1;").status.code(), Some(64));
+}
+
+#[test]
+fn nested_function_bodies_hit_nesting_limit() {
+    let classes = format!("print {}1{};", "class { m() { return ".repeat(300), "; } }".repeat(300));
+    for arguments in [&[][..], &["--tree-walk"][..]] {
+        assert_eq!(run_with_output(arguments, &classes).status.code(), Some(65), "{:?}", arguments);
+    }
+    let lambdas = format!("print {}1{};", "fun () { return ".repeat(300), "; }".repeat(300));
+    assert_eq!(run_with_output(&["--tree-walk"], &lambdas).status.code(), Some(65));
+}
+
+#[test]
+fn tree_walk_flags_require_a_script() {
+    for flag in ["--tree-walk", "--no-warnings", "--max-token-length=8"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_rlox"))
+            .arg(flag)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(64), "{}", flag);
+    }
+}
+
+#[test]
+fn tree_walk_max_token_length() {
+    let source = "var abcdef = 1; print abcdef;";
+    assert_eq!(run(&["--tree-walk"], source), "1\n");
+    let output = run_with_output(&["--tree-walk", "--max-token-length=5"], source);
+    assert_eq!(output.status.code(), Some(65));
+    assert!(String::from_utf8(output.stdout).unwrap().contains("Identifier is too long."));
+}
+
+fn else_if_chain(branches: usize, selected: usize) -> String {
+    let mut source = format!("var x = {}; if (x == 0) print 0;", selected);
+    for branch in 1..branches {
+        source.push_str(&format!(" else if (x == {}) print {};", branch, branch));
+    }
+    source.push_str(" else print \"none\";");
+    source
+}
+
+#[test]
+fn long_else_if_chain() {
+    assert_backends_print(&else_if_chain(200, 0), "0\n");
+    assert_backends_print(&else_if_chain(200, 137), "137\n");
+    assert_backends_print(&else_if_chain(200, 199), "199\n");
+    assert_backends_print(&else_if_chain(200, 500), "none\n");
+}
+
+#[test]
+fn print_without_quotes() {
+    assert_backends_print("print \"a\";", "a\n");
+    assert_backends_print("print \"a\" + \"b\";", "ab\n");
+    assert_backends_print("print 1; print true; print nil;", "1\ntrue\nnil\n");
+}
+
+#[test]
+fn class_construction_arity() {
+    let init = "class P { init(a, b) { this.a = a; this.b = b; } }";
+    let method = "class Q { m(a) { return a; } } var m = Q().m;";
+    let errors = [
+        ("class A {} A(1, 2);".to_string(), "Expected 0 arguments but got 2."),
+        (format!("{} P(1);", init), "Expected 2 arguments but got 1."),
+        (format!("{} P(1, 2, 3);", init), "Expected 2 arguments but got 3."),
+        (format!("{} m();", method), "Expected 1 arguments but got 0."),
+    ];
+    for arguments in BACKENDS {
+        for (source, message) in &errors {
+            assert_eq!(runtime_error(arguments, source), *message, "{:?} {}", arguments, source);
+        }
+    }
+    let source = format!("{} print P(1, 2).a; {} m(1); class A {{}} A(); print 1;", init, method);
+    assert_backends_print(&source, "1\n1\n");
+}
+
+#[test]
+fn mutually_recursive_local_functions() {
+    let source = "fun parity(n) { \
+        fun isEven(n) { if (n == 0) return true; return isOdd(n - 1); } \
+        fun isOdd(n) { if (n == 0) return false; return isEven(n - 1); } \
+        return isEven(n); } \
+        { var before = 1; fun ping(n) { if (n > 0) pong(n - 1); else print \"done\"; } \
+        var between = 2; fun pong(n) { ping(n); } ping(3); print before + between; } \
+        print parity(4); print parity(7);";
+    assert_backends_print(source, "done\n3\ntrue\nfalse\n");
+}
+
+/// The tree-walk backend reports division by zero, so NaN is made as inf - inf.
+const MAKE_NAN: &str = "var inf = 1; for (var i = 0; i < 400; i = i + 1) inf = inf * 10; var nan = inf - inf;";
+
+#[test]
+fn nan_is_not_equal_or_ordered() {
+    let source = format!("{} print nan == nan; print nan != nan; print nan < 1; print nan >= 1; \
+        print nan <= nan; print inf == inf;", MAKE_NAN);
+    assert_backends_print(&source, "false\ntrue\nfalse\nfalse\nfalse\ntrue\n");
+}
+
+#[test]
+fn dictionaries() {
+    let source = "var d = dict(); d[1] = \"one\"; d[\"a\"] = 2; d[-0] = \"zero\"; d[nil] = d[1]; \
+        print d; print d[0]; print d[\"b\"]; print d == d; print dict() == dict();";
+    assert_backends_print(source, "{0: zero, 1: one, a: 2, nil: one}\nzero\nnil\ntrue\nfalse\n");
+    for arguments in BACKENDS {
+        let error = runtime_error(arguments, "dict()[[]] = 1;");
+        assert_eq!(error, "Dictionary keys must be numbers, strings, booleans or nil.", "{:?}", arguments);
+    }
+}
+
+#[test]
+fn dictionary_keys_reject_nan() {
+    for arguments in BACKENDS {
+        let set = format!("{} var d = dict(); d[nan] = 1;", MAKE_NAN);
+        assert_eq!(runtime_error(arguments, &set), "Dictionary key can't be NaN.", "{:?}", arguments);
+        let get = format!("{} print dict()[nan];", MAKE_NAN);
+        assert_eq!(runtime_error(arguments, &get), "Dictionary key can't be NaN.", "{:?}", arguments);
+    }
+    assert_eq!(runtime_error(&[], "dict()[0 / 0] = 1;"), "Dictionary key can't be NaN.");
+}